@@ -0,0 +1,87 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountDeserialize;
+use solana_client::rpc_client::RpcClient;
+
+use crate::error::Result;
+use solearn_solana::state_inf::ASSIGNMENTS_PER_PAGE;
+
+use crate::{
+    pda, Assignment, AssignmentList, AssignmentPage, ChunkedData, Delegation, Inference, MinerInfo,
+    MinerStats, SlashRecord, SolLearnInfo, Tasks, VotingInfo,
+};
+
+// checks the discriminator before decoding, like Account<T> does on chain
+pub fn deserialize<T: AccountDeserialize>(data: &[u8]) -> Result<T> {
+    let mut data = data;
+    Ok(T::try_deserialize(&mut data)?)
+}
+
+pub fn fetch<T: AccountDeserialize>(rpc: &RpcClient, address: &Pubkey) -> Result<T> {
+    let data = rpc.get_account_data(address)?;
+    deserialize(&data)
+}
+
+pub fn fetch_sol_learn_info(rpc: &RpcClient, sol_learn: &Pubkey) -> Result<SolLearnInfo> {
+    fetch(rpc, sol_learn)
+}
+
+pub fn fetch_miner_info(rpc: &RpcClient, sol_learn: &Pubkey, miner: &Pubkey) -> Result<MinerInfo> {
+    fetch(rpc, &pda::miner(miner, sol_learn))
+}
+
+pub fn fetch_miner_stats(rpc: &RpcClient, sol_learn: &Pubkey, miner: &Pubkey) -> Result<MinerStats> {
+    fetch(rpc, &pda::miner_stats(miner, sol_learn))
+}
+
+pub fn fetch_delegation(
+    rpc: &RpcClient,
+    sol_learn: &Pubkey,
+    delegator: &Pubkey,
+    miner: &Pubkey,
+) -> Result<Delegation> {
+    fetch(rpc, &pda::delegation(delegator, &pda::miner(miner, sol_learn)))
+}
+
+// `index` counts from zero up to MinerInfo::slash_count
+pub fn fetch_slash_record(
+    rpc: &RpcClient,
+    sol_learn: &Pubkey,
+    miner: &Pubkey,
+    index: u64,
+) -> Result<SlashRecord> {
+    fetch(rpc, &pda::slash_record(&pda::miner(miner, sol_learn), index))
+}
+
+pub fn fetch_inference(rpc: &RpcClient, inference_id: u64) -> Result<Inference> {
+    fetch(rpc, &pda::inference(inference_id))
+}
+
+pub fn fetch_assignment(rpc: &RpcClient, assignment_id: u64) -> Result<Assignment> {
+    fetch(rpc, &pda::assignment(assignment_id))
+}
+
+// assignments of the current round of the inference, read from its pages
+pub fn fetch_assignments(rpc: &RpcClient, inference: &Inference) -> Result<AssignmentList> {
+    let mut list = AssignmentList::default();
+    let pages = (inference.assignment_count as usize).div_ceil(ASSIGNMENTS_PER_PAGE);
+    for page in 0..pages {
+        let stored: AssignmentPage = fetch(rpc, &pda::assignment_page(inference.id, page as u8))?;
+        list.ids.extend(stored.assignments);
+        list.digests.extend(stored.digests);
+    }
+    Ok(list)
+}
+
+// content of an inference input or an assignment output
+pub fn fetch_data(rpc: &RpcClient, address: &Pubkey) -> Result<Vec<u8>> {
+    let data: ChunkedData = fetch(rpc, address)?;
+    Ok(data.data)
+}
+
+pub fn fetch_voting_info(rpc: &RpcClient, inference_id: u64) -> Result<VotingInfo> {
+    fetch(rpc, &pda::voting_info(inference_id))
+}
+
+pub fn fetch_tasks(rpc: &RpcClient, sol_learn: &Pubkey, inference_id: u64) -> Result<Tasks> {
+    fetch(rpc, &pda::tasks(sol_learn, inference_id))
+}
@@ -0,0 +1,34 @@
+use std::fmt;
+
+use solana_client::client_error::ClientError as RpcError;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Rpc(Box<RpcError>),
+    Deserialize(Box<anchor_lang::error::Error>),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Rpc(err) => write!(f, "rpc error: {}", err),
+            ClientError::Deserialize(err) => write!(f, "cannot deserialize account: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<RpcError> for ClientError {
+    fn from(err: RpcError) -> Self {
+        ClientError::Rpc(Box::new(err))
+    }
+}
+
+impl From<anchor_lang::error::Error> for ClientError {
+    fn from(err: anchor_lang::error::Error) -> Self {
+        ClientError::Deserialize(Box::new(err))
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
@@ -0,0 +1,1476 @@
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::keccak::hash;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use anchor_spl::{associated_token, token};
+use solearn_solana::state::ModelParams;
+use solearn_solana::state_inf::{DataRef, DataScheme, MAX_ASSIGNMENT_PAGES};
+use solearn_solana::{accounts, instruction};
+
+use crate::{pda, PROGRAM_ID};
+
+// bytes written per write_data, small enough for one transaction
+pub const DATA_CHUNK_LEN: usize = 800;
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+// appends every assignment page an inference can have, writable
+fn with_assignment_pages(mut ix: Instruction, inference_id: u64) -> Instruction {
+    ix.accounts.extend(
+        (0..MAX_ASSIGNMENT_PAGES)
+            .map(|page| AccountMeta::new(pda::assignment_page(inference_id, page as u8), false)),
+    );
+    ix
+}
+
+// `sol_learn` is a fresh keypair and must sign next to the admin
+pub fn initialize(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    staking_token: &Pubkey,
+    params: instruction::Initialize,
+) -> Instruction {
+    build(
+        accounts::Initialize {
+            admin: *admin,
+            staking_token: *staking_token,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            models: pda::models(sol_learn),
+            sol_learn_account: *sol_learn,
+            system_program: system_program::ID,
+            sysvar_clock: sysvar::clock::ID,
+        },
+        params,
+    )
+}
+
+pub fn initialize2(admin: &Pubkey, sol_learn: &Pubkey) -> Instruction {
+    build(
+        accounts::InitializeExtra {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            system_program: system_program::ID,
+        },
+        instruction::Initialize2 {},
+    )
+}
+
+pub fn init_reward_pool(admin: &Pubkey, sol_learn: &Pubkey) -> Instruction {
+    build(
+        accounts::InitRewardPool {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            reward_pool_pda: pda::reward_pool(sol_learn),
+            system_program: system_program::ID,
+        },
+        instruction::InitRewardPool {},
+    )
+}
+
+pub fn fund_reward_pool(
+    funder: &Pubkey,
+    sol_learn: &Pubkey,
+    funder_wallet: &Pubkey,
+    reward_pool_wallet: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        accounts::FundRewardPool {
+            funder: *funder,
+            sol_learn_account: *sol_learn,
+            funder_wallet: *funder_wallet,
+            reward_pool_pda: pda::reward_pool(sol_learn),
+            reward_pool_wallet: *reward_pool_wallet,
+            token_program: token::ID,
+        },
+        instruction::FundRewardPool { amount },
+    )
+}
+
+// closes `epoch_id` and up to `catch_up` more elapsed epochs after it
+pub fn update_epoch(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    reward_pool_wallet: &Pubkey,
+    epoch_id: u64,
+    catch_up: u64,
+) -> Instruction {
+    let mut ix = build(
+        accounts::NewEpochVld {
+            sol_learn_account: *sol_learn,
+            miner_reward: pda::reward_in_epoch(epoch_id),
+            reward_pool_pda: pda::reward_pool(sol_learn),
+            reward_pool_wallet: *reward_pool_wallet,
+            signer: *signer,
+            system_program: system_program::ID,
+            sysvar_clock: sysvar::clock::ID,
+        },
+        instruction::UpdateEpoch { epoch_id },
+    );
+    ix.accounts.extend(
+        (1..=catch_up)
+            .map(|offset| AccountMeta::new(pda::reward_in_epoch(epoch_id + offset), false)),
+    );
+    ix
+}
+
+pub fn create_model(admin: &Pubkey, sol_learn: &Pubkey, model: &Pubkey, params: ModelParams) -> Instruction {
+    build(
+        accounts::CreateModel {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            models: pda::models(sol_learn),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            model_info: pda::model_info(sol_learn, model),
+            system_program: system_program::ID,
+        },
+        instruction::CreateModel {
+            model: *model,
+            params,
+        },
+    )
+}
+
+// settings of a model added before ModelInfo existed
+pub fn init_model_info(admin: &Pubkey, sol_learn: &Pubkey, model: &Pubkey, params: ModelParams) -> Instruction {
+    build(
+        accounts::InitModelInfoVld {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            models: pda::models(sol_learn),
+            model_info: pda::model_info(sol_learn, model),
+            system_program: system_program::ID,
+        },
+        instruction::InitModelInfo {
+            model: *model,
+            params,
+        },
+    )
+}
+
+pub fn update_model(admin: &Pubkey, sol_learn: &Pubkey, model: &Pubkey, params: ModelParams) -> Instruction {
+    build(
+        accounts::UpdateModel {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            model_info: pda::model_info(sol_learn, model),
+        },
+        instruction::UpdateModel {
+            model: *model,
+            params,
+        },
+    )
+}
+
+pub fn set_model_enabled(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    enabled: bool,
+) -> Instruction {
+    build(
+        accounts::UpdateModel {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            model_info: pda::model_info(sol_learn, model),
+        },
+        instruction::SetModelEnabled {
+            model: *model,
+            enabled,
+        },
+    )
+}
+
+pub fn set_model_comparator(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    comparator: u8,
+    tolerance: u64,
+) -> Instruction {
+    build(
+        accounts::UpdateModel {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            model_info: pda::model_info(sol_learn, model),
+        },
+        instruction::SetModelComparator {
+            model: *model,
+            comparator,
+            tolerance,
+        },
+    )
+}
+
+pub fn retire_model(admin: &Pubkey, sol_learn: &Pubkey, model: &Pubkey) -> Instruction {
+    build(
+        accounts::RetireModel {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            models: pda::models(sol_learn),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            model_info: pda::model_info(sol_learn, model),
+            system_program: system_program::ID,
+        },
+        instruction::RetireModel { model: *model },
+    )
+}
+
+// anyone can move a miner off a retired model
+pub fn reassign_model(signer: &Pubkey, sol_learn: &Pubkey, miner: &Pubkey) -> Instruction {
+    build(
+        accounts::ReassignModel {
+            signer: *signer,
+            sol_learn_account: *sol_learn,
+            miner_account: pda::miner(miner, sol_learn),
+            models: pda::models(sol_learn),
+            recent_slothashes: sysvar::slot_hashes::ID,
+        },
+        instruction::ReassignModel {},
+    )
+}
+
+fn update_params(admin: &Pubkey, sol_learn: &Pubkey, data: impl InstructionData) -> Instruction {
+    build(
+        accounts::UpdateParamsVld {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            system_program: system_program::ID,
+        },
+        data,
+    )
+}
+
+pub fn set_selection_mode(admin: &Pubkey, sol_learn: &Pubkey, selection_mode: u8) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetSelectionMode { selection_mode },
+    )
+}
+
+pub fn set_perf_reward_percentage(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    perf_reward_percentage: u16,
+) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetPerfRewardPercentage {
+            perf_reward_percentage,
+        },
+    )
+}
+
+pub fn set_crank_bounty(admin: &Pubkey, sol_learn: &Pubkey, crank_bounty: u64) -> Instruction {
+    update_params(admin, sol_learn, instruction::SetCrankBounty { crank_bounty })
+}
+
+pub fn set_miner_min_stake(admin: &Pubkey, sol_learn: &Pubkey, min_stake: u64) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetMinerMinStake { data: min_stake },
+    )
+}
+
+pub fn set_fine_percentage(admin: &Pubkey, sol_learn: &Pubkey, fine_percentage: u16) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetFinePercentage { fine_percentage },
+    )
+}
+
+pub fn set_fine_model(admin: &Pubkey, sol_learn: &Pubkey, fine_model: u8) -> Instruction {
+    update_params(admin, sol_learn, instruction::SetFineModel { fine_model })
+}
+
+pub fn set_fine_creator_percentage(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    fine_creator_percentage: u16,
+) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetFineCreatorPercentage {
+            fine_creator_percentage,
+        },
+    )
+}
+
+pub fn set_escalation(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    escalation_rounds: u8,
+    escalation_validators: u8,
+) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetEscalation {
+            escalation_rounds,
+            escalation_validators,
+        },
+    )
+}
+
+pub fn set_dispute_params(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    dispute_window: u64,
+    dispute_bond: u64,
+) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetDisputeParams {
+            dispute_window,
+            dispute_bond,
+        },
+    )
+}
+
+pub fn set_arbiter(admin: &Pubkey, sol_learn: &Pubkey, arbiter: Pubkey) -> Instruction {
+    update_params(admin, sol_learn, instruction::SetArbiter { arbiter })
+}
+
+pub fn set_penalty_duration(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    penalty_duration: u64,
+) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetPenaltyDuration { penalty_duration },
+    )
+}
+
+pub fn set_min_fee_to_use(admin: &Pubkey, sol_learn: &Pubkey, min_fee_to_use: u64) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetMinFeeToUse { min_fee_to_use },
+    )
+}
+
+pub fn set_l2_owner(admin: &Pubkey, sol_learn: &Pubkey, l2_owner: &Pubkey) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetL2Owner {
+            l2_owner_address: *l2_owner,
+        },
+    )
+}
+
+// the mint authority of `dao_token` must already be the vault PDA
+pub fn set_dao_token(admin: &Pubkey, sol_learn: &Pubkey, dao_token: &Pubkey) -> Instruction {
+    build(
+        accounts::SetDaoTokenVld {
+            admin: *admin,
+            sol_learn_account: *sol_learn,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            dao_token: *dao_token,
+        },
+        instruction::SetDaoToken {},
+    )
+}
+
+pub fn set_treasury_address(admin: &Pubkey, sol_learn: &Pubkey, treasury: &Pubkey) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetTreasuryAddress {
+            treasury_address: *treasury,
+        },
+    )
+}
+
+pub fn set_fee_ratio_miner_validator(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    new_ratio: u16,
+) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetFeeRatioMinerValidator { new_ratio },
+    )
+}
+
+pub fn set_dao_token_reward(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    new_dao_token_reward: u64,
+) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetDaoTokenReward {
+            new_dao_token_reward,
+        },
+    )
+}
+
+pub fn set_dao_token_max_emission(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    new_dao_token_max_emission: u64,
+) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetDaoTokenMaxEmission {
+            new_dao_token_max_emission,
+        },
+    )
+}
+
+pub fn set_quality_weight(admin: &Pubkey, sol_learn: &Pubkey, new_quality_weight: u16) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetQualityWeight { new_quality_weight },
+    )
+}
+
+pub fn set_dao_token_epoch_budget(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    new_dao_token_epoch_budget: u64,
+) -> Instruction {
+    update_params(
+        admin,
+        sol_learn,
+        instruction::SetDaoTokenEpochBudget {
+            new_dao_token_epoch_budget,
+        },
+    )
+}
+
+pub fn miner_register(
+    miner: &Pubkey,
+    sol_learn: &Pubkey,
+    miner_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    staking_token: &Pubkey,
+    stake_amount: u64,
+) -> Instruction {
+    build(
+        accounts::MinerRegister {
+            miner: *miner,
+            sol_learn_account: *sol_learn,
+            models: pda::models(sol_learn),
+            miner_account: pda::miner(miner, sol_learn),
+            miner_stats: pda::miner_stats(miner, sol_learn),
+            miner_staking_wallet: *miner_wallet,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            staking_token: *staking_token,
+            recent_slothashes: sysvar::slot_hashes::ID,
+            token_program: token::ID,
+            system_program: system_program::ID,
+            sysvar_clock: sysvar::clock::ID,
+        },
+        instruction::MinerRegister { stake_amount },
+    )
+}
+
+// `signer` pays for the stats account of a miner registered before it existed
+pub fn init_miner_stats(signer: &Pubkey, sol_learn: &Pubkey, miner: &Pubkey) -> Instruction {
+    build(
+        accounts::InitMinerStatsVld {
+            signer: *signer,
+            sol_learn_account: *sol_learn,
+            miner_account: pda::miner(miner, sol_learn),
+            miner_stats: pda::miner_stats(miner, sol_learn),
+            system_program: system_program::ID,
+        },
+        instruction::InitMinerStats {},
+    )
+}
+
+// `miners` lists the miners of the old list in its order, their MinerInfo
+// are passed as remaining accounts
+pub fn migrate_miners_of_model(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    miners: &[Pubkey],
+) -> Instruction {
+    let mut ix = build(
+        accounts::MigrateMinersOfModelVld {
+            signer: *signer,
+            sol_learn_account: *sol_learn,
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            system_program: system_program::ID,
+        },
+        instruction::MigrateMinersOfModel { model: *model },
+    );
+    ix.accounts.extend(
+        miners
+            .iter()
+            .map(|miner| AccountMeta::new_readonly(pda::miner(miner, sol_learn), false)),
+    );
+    ix
+}
+
+// `model` is the model the miner was assigned at registration
+pub fn join_for_minting(miner: &Pubkey, sol_learn: &Pubkey, model: &Pubkey) -> Instruction {
+    build(
+        accounts::JoinForMinting {
+            miner: *miner,
+            sol_learn_account: *sol_learn,
+            miner_account: pda::miner(miner, sol_learn),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            models: pda::models(sol_learn),
+            system_program: system_program::ID,
+            sysvar_clock: sysvar::clock::ID,
+        },
+        instruction::JoinForMinting {},
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn topup(
+    miner: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    miner_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    staking_token: &Pubkey,
+    topup_amount: u64,
+) -> Instruction {
+    build(
+        accounts::Topup {
+            miner: *miner,
+            sol_learn_account: *sol_learn,
+            miner_account: pda::miner(miner, sol_learn),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            miner_staking_wallet: *miner_wallet,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            staking_token: *staking_token,
+            token_program: token::ID,
+        },
+        instruction::Topup { topup_amount },
+    )
+}
+
+pub fn miner_unstake(
+    miner: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    model_index: u64,
+) -> Instruction {
+    build(
+        accounts::MinerUnStaking {
+            miner: *miner,
+            sol_learn_account: *sol_learn,
+            miner_account: pda::miner(miner, sol_learn),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            system_program: system_program::ID,
+            sysvar_clock: sysvar::clock::ID,
+        },
+        instruction::MinerUnstake { model_index },
+    )
+}
+
+pub fn unstake(miner: &Pubkey, sol_learn: &Pubkey, model: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::MinerUnStaking {
+            miner: *miner,
+            sol_learn_account: *sol_learn,
+            miner_account: pda::miner(miner, sol_learn),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            system_program: system_program::ID,
+            sysvar_clock: sysvar::clock::ID,
+        },
+        instruction::Unstake { amount },
+    )
+}
+
+pub fn miner_claim_unstaked(
+    miner: &Pubkey,
+    sol_learn: &Pubkey,
+    miner_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    staking_token: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::MinerClaim {
+            miner: *miner,
+            sol_learn_account: *sol_learn,
+            miner_account: pda::miner(miner, sol_learn),
+            miner_staking_wallet: *miner_wallet,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            staking_token: *staking_token,
+            token_program: token::ID,
+            sysvar_clock: sysvar::clock::ID,
+            system_program: system_program::ID,
+        },
+        instruction::MinerClaimUnstaked {},
+    )
+}
+
+pub fn set_commission(miner: &Pubkey, sol_learn: &Pubkey, commission: u16) -> Instruction {
+    build(
+        accounts::SetCommission {
+            miner: *miner,
+            sol_learn_account: *sol_learn,
+            miner_account: pda::miner(miner, sol_learn),
+        },
+        instruction::SetCommission { commission },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn delegate(
+    delegator: &Pubkey,
+    sol_learn: &Pubkey,
+    miner: &Pubkey,
+    model: &Pubkey,
+    delegator_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    staking_token: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let miner_account = pda::miner(miner, sol_learn);
+    build(
+        accounts::Delegate {
+            delegator: *delegator,
+            sol_learn_account: *sol_learn,
+            miner_account,
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            delegation: pda::delegation(delegator, &miner_account),
+            delegator_wallet: *delegator_wallet,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            staking_token: *staking_token,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::Delegate { amount },
+    )
+}
+
+pub fn undelegate(delegator: &Pubkey, sol_learn: &Pubkey, miner: &Pubkey, shares: u64) -> Instruction {
+    let miner_account = pda::miner(miner, sol_learn);
+    build(
+        accounts::Undelegate {
+            delegator: *delegator,
+            sol_learn_account: *sol_learn,
+            miner_account,
+            delegation: pda::delegation(delegator, &miner_account),
+            sysvar_clock: sysvar::clock::ID,
+        },
+        instruction::Undelegate { shares },
+    )
+}
+
+pub fn claim_undelegated(
+    delegator: &Pubkey,
+    sol_learn: &Pubkey,
+    miner: &Pubkey,
+    model: &Pubkey,
+    delegator_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    staking_token: &Pubkey,
+) -> Instruction {
+    let miner_account = pda::miner(miner, sol_learn);
+    build(
+        accounts::ClaimUndelegated {
+            delegator: *delegator,
+            sol_learn_account: *sol_learn,
+            miner_account,
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            delegation: pda::delegation(delegator, &miner_account),
+            delegator_wallet: *delegator_wallet,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            staking_token: *staking_token,
+            token_program: token::ID,
+            sysvar_clock: sysvar::clock::ID,
+        },
+        instruction::ClaimUndelegated {},
+    )
+}
+
+// unclaimed epoch snapshots are passed as remaining accounts
+pub fn miner_claim_reward(
+    miner: &Pubkey,
+    sol_learn: &Pubkey,
+    miner_wallet: &Pubkey,
+    reward_pool_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    staking_token: &Pubkey,
+    epoch_ids: &[u64],
+) -> Instruction {
+    let mut ix = build(
+        accounts::MinerClaimReward {
+            miner: *miner,
+            sol_learn_account: *sol_learn,
+            miner_account: pda::miner(miner, sol_learn),
+            miner_staking_wallet: *miner_wallet,
+            reward_pool_pda: pda::reward_pool(sol_learn),
+            reward_pool_wallet: *reward_pool_wallet,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            staking_token: *staking_token,
+            system_program: system_program::ID,
+            sysvar_clock: sysvar::clock::ID,
+            token_program: token::ID,
+        },
+        instruction::MinerClaimReward {},
+    );
+    ix.accounts.extend(
+        epoch_ids
+            .iter()
+            .map(|&epoch_id| AccountMeta::new_readonly(pda::reward_in_epoch(epoch_id), false)),
+    );
+    ix
+}
+
+pub fn next_inference_id(sol_learn: &Pubkey) -> Instruction {
+    build(
+        accounts::ReadStateVld {
+            sol_learn_account: *sol_learn,
+        },
+        instruction::NextInferenceId {},
+    )
+}
+
+pub fn next_assignment_id(sol_learn: &Pubkey) -> Instruction {
+    build(
+        accounts::ReadStateVld {
+            sol_learn_account: *sol_learn,
+        },
+        instruction::NextAssignmentId {},
+    )
+}
+
+pub fn next_epoch_id(sol_learn: &Pubkey) -> Instruction {
+    build(
+        accounts::ReadStateVld {
+            sol_learn_account: *sol_learn,
+        },
+        instruction::NextEpochId {},
+    )
+}
+
+pub fn get_task_count(sol_learn: &Pubkey, inference_id: u64) -> Instruction {
+    build(
+        accounts::ReadTasksVld {
+            tasks: pda::tasks(sol_learn, inference_id),
+        },
+        instruction::GetTaskCount {},
+    )
+}
+
+pub fn get_miner_stats(sol_learn: &Pubkey, miner: &Pubkey) -> Instruction {
+    build(
+        accounts::ReadMinerStatsVld {
+            sol_learn_account: *sol_learn,
+            miner_stats: pda::miner_stats(miner, sol_learn),
+        },
+        instruction::GetMinerStats { _miner: *miner },
+    )
+}
+
+pub fn get_assignment(assignment_id: u64, field_name: String) -> Instruction {
+    build(
+        accounts::ReadAssignmentVld {
+            assignment: pda::assignment(assignment_id),
+        },
+        instruction::GetAssignment {
+            assignment_id,
+            field_name,
+        },
+    )
+}
+
+pub fn create_data(owner: &Pubkey, data_id: u64, len: u32) -> Instruction {
+    build(
+        accounts::CreateDataVld {
+            data: pda::data(owner, data_id),
+            signer: *owner,
+            system_program: system_program::ID,
+        },
+        instruction::CreateData { data_id, len },
+    )
+}
+
+pub fn write_data(owner: &Pubkey, data_id: u64, offset: u32, chunk: Vec<u8>) -> Instruction {
+    build(
+        accounts::WriteDataVld {
+            data: pda::data(owner, data_id),
+            signer: *owner,
+        },
+        instruction::WriteData {
+            _data_id: data_id,
+            offset,
+            chunk,
+        },
+    )
+}
+
+pub fn finalize_data(owner: &Pubkey, data_id: u64, hash: [u8; 32]) -> Instruction {
+    build(
+        accounts::WriteDataVld {
+            data: pda::data(owner, data_id),
+            signer: *owner,
+        },
+        instruction::FinalizeData { data_id, hash },
+    )
+}
+
+// create_data, one write_data per DATA_CHUNK_LEN bytes and finalize_data,
+// to be sent in order; returns them with the hash infer / submit_solution /
+// reveal expect
+pub fn upload_data(owner: &Pubkey, data_id: u64, content: &[u8]) -> (Vec<Instruction>, [u8; 32]) {
+    let content_hash = hash(content).to_bytes();
+    let mut ixs = vec![create_data(owner, data_id, content.len() as u32)];
+    for (i, chunk) in content.chunks(DATA_CHUNK_LEN).enumerate() {
+        ixs.push(write_data(owner, data_id, (i * DATA_CHUNK_LEN) as u32, chunk.to_vec()));
+    }
+    ixs.push(finalize_data(owner, data_id, content_hash));
+    (ixs, content_hash)
+}
+
+// the data account of an Inline output, see upload_data
+fn output_data(worker: &Pubkey, assignment_id: u64, output: &DataRef) -> Option<Pubkey> {
+    (output.scheme == DataScheme::Inline).then(|| pda::data(worker, assignment_id))
+}
+
+pub fn register_referrer(signer: &Pubkey, code: &str) -> Instruction {
+    build(
+        accounts::RegisterReferrerVld {
+            signer: *signer,
+            referrer: pda::referrer(signer),
+            referral_code: pda::referral_code(code),
+            system_program: system_program::ID,
+        },
+        instruction::RegisterReferrer {
+            code: code.to_string(),
+        },
+    )
+}
+
+// `referrer` is the user who registered `code`
+pub fn set_referrer(signer: &Pubkey, code: &str, referrer: &Pubkey) -> Instruction {
+    build(
+        accounts::SetReferrerVld {
+            signer: *signer,
+            referee: pda::referrer(signer),
+            referral_code: pda::referral_code(code),
+            referrer: pda::referrer(referrer),
+            system_program: system_program::ID,
+        },
+        instruction::SetReferrer {
+            code: code.to_string(),
+        },
+    )
+}
+
+// `input_data` is the finalized ChunkedData of an Inline input, see
+// upload_data. `referrer` is the signer's referrer, required once it has one.
+#[allow(clippy::too_many_arguments)]
+pub fn infer(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    signer_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    inference_id: u64,
+    creator: &Pubkey,
+    input: DataRef,
+    input_data: Option<Pubkey>,
+    referrer: Option<Pubkey>,
+    value: u64,
+) -> Instruction {
+    let ix = build(
+        accounts::InferVld {
+            infs: pda::inference(inference_id),
+            sol_learn_account: *sol_learn,
+            voting_info: pda::voting_info(inference_id),
+            tasks: pda::tasks(sol_learn, inference_id),
+            dao_receiver_infos: pda::dao_receiver_infos(sol_learn, inference_id),
+            model_info: pda::model_info(sol_learn, model),
+            referee: pda::referrer(signer),
+            referrer: referrer.map(|referrer| pda::referrer(&referrer)),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            signer: *signer,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            miner_staking_wallet: *signer_wallet,
+            recent_slothashes: sysvar::slot_hashes::ID,
+            token_program: token::ID,
+            system_program: system_program::ID,
+            input_data,
+        },
+        instruction::Infer {
+            inference_id,
+            creator: *creator,
+            input,
+            _value: value,
+            model: *model,
+        },
+    );
+    with_assignment_pages(ix, inference_id)
+}
+
+pub fn create_assignment(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+) -> Instruction {
+    build(
+        accounts::CreateAssignmentVld {
+            assignment: pda::assignment(assignment_id),
+            tasks: pda::tasks(sol_learn, inference_id),
+            signer: *signer,
+            system_program: system_program::ID,
+        },
+        instruction::CreateAssignment { assignment_id },
+    )
+}
+
+pub fn topup_infer(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    signer_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    inference_id: u64,
+    value: u64,
+) -> Instruction {
+    build(
+        accounts::UpdateInferVld {
+            sol_learn_account: *sol_learn,
+            infs: pda::inference(inference_id),
+            signer: *signer,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            miner_staking_wallet: *signer_wallet,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::TopupInfer {
+            inference_id,
+            value,
+        },
+    )
+}
+
+fn seize_accounts(
+    worker: &Pubkey,
+    sol_learn: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+) -> accounts::SeizeMinerRoleVld {
+    accounts::SeizeMinerRoleVld {
+        sol_learn_account: *sol_learn,
+        infs: pda::inference(inference_id),
+        assignment: pda::assignment(assignment_id),
+        voting_info: pda::voting_info(inference_id),
+        miner_account: pda::miner(worker, sol_learn),
+        miner_stats: pda::miner_stats(worker, sol_learn),
+        signer: *worker,
+        system_program: system_program::ID,
+        output_data: None,
+    }
+}
+
+pub fn seize_miner_role(
+    worker: &Pubkey,
+    sol_learn: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+) -> Instruction {
+    build(
+        seize_accounts(worker, sol_learn, inference_id, assignment_id),
+        instruction::SeizeMinerRole {
+            assignment_id,
+            inference_id,
+        },
+    )
+}
+
+pub fn submit_solution(
+    worker: &Pubkey,
+    sol_learn: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+    output: DataRef,
+) -> Instruction {
+    let ix = build(
+        accounts::SeizeMinerRoleVld {
+            output_data: output_data(worker, assignment_id, &output),
+            ..seize_accounts(worker, sol_learn, inference_id, assignment_id)
+        },
+        instruction::SubmitSolution {
+            assignment_id,
+            inference_id,
+            output,
+        },
+    );
+    with_assignment_pages(ix, inference_id)
+}
+
+pub fn commit(
+    worker: &Pubkey,
+    sol_learn: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+    commitment: [u8; 32],
+) -> Instruction {
+    build(
+        accounts::CommitVld {
+            sol_learn_account: *sol_learn,
+            infs: pda::inference(inference_id),
+            assignment: pda::assignment(assignment_id),
+            miner_account: pda::miner(worker, sol_learn),
+            voting_info: pda::voting_info(inference_id),
+            tasks: pda::tasks(sol_learn, inference_id),
+            signer: *worker,
+            system_program: system_program::ID,
+        },
+        instruction::Commit {
+            assignment_id,
+            inference_id,
+            commitment,
+        },
+    )
+}
+
+// accounts shared by reveal and resolve_inference; `token_recipient` receives
+// the refund when the inference is resolved without an answer
+#[allow(clippy::too_many_arguments)]
+fn update_assignment_accounts(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    worker: &Pubkey,
+    vault_wallet: &Pubkey,
+    token_recipient: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+) -> accounts::UpdateAssignmentVld {
+    accounts::UpdateAssignmentVld {
+        sol_learn_account: *sol_learn,
+        infs: pda::inference(inference_id),
+        assignment: pda::assignment(assignment_id),
+        miner_account: pda::miner(worker, sol_learn),
+        miner_stats: pda::miner_stats(worker, sol_learn),
+        voting_info: pda::voting_info(inference_id),
+        tasks: pda::tasks(sol_learn, inference_id),
+        dao_receiver_infos: pda::dao_receiver_infos(sol_learn, inference_id),
+        signer: *signer,
+        vault_wallet_owner_pda: pda::vault(sol_learn),
+        vault_staking_wallet: *vault_wallet,
+        token_recipient: *token_recipient,
+        token_program: token::ID,
+        system_program: system_program::ID,
+        miners_of_model: pda::miners_of_model(sol_learn, model),
+        recent_slothashes: sysvar::slot_hashes::ID,
+        output_data: None,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn reveal(
+    worker: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    vault_wallet: &Pubkey,
+    creator_wallet: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+    nonce: u64,
+    output: DataRef,
+) -> Instruction {
+    let ix = build(
+        accounts::UpdateAssignmentVld {
+            output_data: output_data(worker, assignment_id, &output),
+            ..update_assignment_accounts(
+                worker,
+                sol_learn,
+                model,
+                worker,
+                vault_wallet,
+                creator_wallet,
+                inference_id,
+                assignment_id,
+            )
+        },
+        instruction::Reveal {
+            assignment_id,
+            inference_id,
+            nonce,
+            output,
+        },
+    );
+    with_assignment_pages(ix, inference_id)
+}
+
+// `worker` owns the assignment passed in, usually the miner of the inference
+#[allow(clippy::too_many_arguments)]
+pub fn resolve_inference(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    worker: &Pubkey,
+    vault_wallet: &Pubkey,
+    creator_wallet: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+) -> Instruction {
+    let ix = build(
+        update_assignment_accounts(
+            signer,
+            sol_learn,
+            model,
+            worker,
+            vault_wallet,
+            creator_wallet,
+            inference_id,
+            assignment_id,
+        ),
+        instruction::ResolveInference {
+            assignment_id,
+            inference_id,
+        },
+    );
+    with_assignment_pages(ix, inference_id)
+}
+
+pub fn pay_miner(
+    sol_learn: &Pubkey,
+    worker: &Pubkey,
+    vault_wallet: &Pubkey,
+    token_recipient: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+) -> Instruction {
+    build(
+        accounts::PayMinerVld {
+            tasks: pda::tasks(sol_learn, inference_id),
+            sol_learn_account: *sol_learn,
+            assignment: pda::assignment(assignment_id),
+            miner_account: pda::miner(worker, sol_learn),
+            miner_stats: pda::miner_stats(worker, sol_learn),
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            token_recipient: *token_recipient,
+            token_program: token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::PayMiner { assignment_id },
+    )
+}
+
+// `slash_count` is the worker's MinerInfo::slash_count, `creator_wallet` the
+// inference creator
+#[allow(clippy::too_many_arguments)]
+pub fn slash_miner(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    worker: &Pubkey,
+    model: &Pubkey,
+    vault_wallet: &Pubkey,
+    staking_token: &Pubkey,
+    treasury_wallet: &Pubkey,
+    creator_wallet: &Pubkey,
+    inference_id: u64,
+    assignment_id: u64,
+    slash_count: u64,
+) -> Instruction {
+    let miner_account = pda::miner(worker, sol_learn);
+    build(
+        accounts::SlashMinerVld {
+            sol_learn_account: *sol_learn,
+            miner_account,
+            miner_stats: pda::miner_stats(worker, sol_learn),
+            tasks: pda::tasks(sol_learn, inference_id),
+            assignment: pda::assignment(assignment_id),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            staking_token: *staking_token,
+            token_program: token::ID,
+            system_program: system_program::ID,
+            token_recipient: *treasury_wallet,
+            infs: pda::inference(inference_id),
+            creator_wallet: *creator_wallet,
+            signer: *signer,
+            slash_record: pda::slash_record(&miner_account, slash_count),
+        },
+        instruction::SlashMiner { assignment_id },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn slash_miner_by_admin(
+    admin: &Pubkey,
+    sol_learn: &Pubkey,
+    miner: &Pubkey,
+    model: &Pubkey,
+    epoch_id: u64,
+    slash_count: u64,
+    is_fined: bool,
+) -> Instruction {
+    let miner_account = pda::miner(miner, sol_learn);
+    build(
+        accounts::SlashMinerByAdminVld {
+            system_program: system_program::ID,
+            miner_reward: pda::reward_in_epoch(epoch_id),
+            miner_account,
+            miner_stats: pda::miner_stats(miner, sol_learn),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            sol_learn_account: *sol_learn,
+            signer: *admin,
+            slash_record: pda::slash_record(&miner_account, slash_count),
+        },
+        instruction::SlashMinerByAdmin {
+            _miner: *miner,
+            is_fined,
+        },
+    )
+}
+
+// `index` is the slash record's position among the miner's slashes
+pub fn open_dispute(
+    miner: &Pubkey,
+    sol_learn: &Pubkey,
+    miner_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    index: u64,
+) -> Instruction {
+    let miner_account = pda::miner(miner, sol_learn);
+    build(
+        accounts::OpenDisputeVld {
+            signer: *miner,
+            sol_learn_account: *sol_learn,
+            miner_account,
+            slash_record: pda::slash_record(&miner_account, index),
+            miner_wallet: *miner_wallet,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            token_program: token::ID,
+        },
+        instruction::OpenDispute { index },
+    )
+}
+
+// `signer` is the arbiter or the admin
+#[allow(clippy::too_many_arguments)]
+pub fn resolve_dispute(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    miner: &Pubkey,
+    miner_wallet: &Pubkey,
+    treasury_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    index: u64,
+    overturned: bool,
+) -> Instruction {
+    let miner_account = pda::miner(miner, sol_learn);
+    build(
+        accounts::ResolveDisputeVld {
+            signer: *signer,
+            sol_learn_account: *sol_learn,
+            miner_account,
+            slash_record: pda::slash_record(&miner_account, index),
+            miner_wallet: *miner_wallet,
+            treasury_wallet: *treasury_wallet,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            token_program: token::ID,
+        },
+        instruction::ResolveDispute { index, overturned },
+    )
+}
+
+// `creator_wallet` is the record's creator wallet, or any wallet when it has
+// none
+pub fn execute_slash(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    miner: &Pubkey,
+    treasury_wallet: &Pubkey,
+    creator_wallet: &Pubkey,
+    vault_wallet: &Pubkey,
+    index: u64,
+) -> Instruction {
+    let miner_account = pda::miner(miner, sol_learn);
+    build(
+        accounts::ExecuteSlashVld {
+            signer: *signer,
+            sol_learn_account: *sol_learn,
+            miner_account,
+            slash_record: pda::slash_record(&miner_account, index),
+            treasury_wallet: *treasury_wallet,
+            creator_wallet: *creator_wallet,
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            token_program: token::ID,
+        },
+        instruction::ExecuteSlash { index },
+    )
+}
+
+// `task_accounts` follow the queue order documented on CrankTasksVld;
+// `dao_token` is needed once a MintDaoToken task is reached, missing DAO token
+// wallets are passed as the receivers' associated token accounts
+#[allow(clippy::too_many_arguments)]
+pub fn crank_tasks(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    vault_wallet: &Pubkey,
+    treasury_wallet: &Pubkey,
+    creator_wallet: &Pubkey,
+    cranker_wallet: &Pubkey,
+    dao_token: Option<Pubkey>,
+    inference_id: u64,
+    task_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let mut ix = build(
+        accounts::CrankTasksVld {
+            signer: *signer,
+            sol_learn_account: *sol_learn,
+            infs: pda::inference(inference_id),
+            tasks: pda::tasks(sol_learn, inference_id),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            vault_staking_wallet: *vault_wallet,
+            treasury_wallet: *treasury_wallet,
+            creator_wallet: *creator_wallet,
+            cranker_wallet: *cranker_wallet,
+            dao_token,
+            token_program: token::ID,
+            system_program: system_program::ID,
+            associated_token_program: dao_token.map(|_| associated_token::ID),
+        },
+        instruction::CrankTasks { inference_id },
+    );
+    ix.accounts.extend(task_accounts);
+    ix
+}
+
+// `miner` is the processed miner of the inference; `dao_token` and the
+// signer's `user_dao_wallet` are needed while the DAO token is set
+#[allow(clippy::too_many_arguments)]
+pub fn rate_inference(
+    signer: &Pubkey,
+    sol_learn: &Pubkey,
+    model: &Pubkey,
+    miner: &Pubkey,
+    creator_wallet: &Pubkey,
+    inference_id: u64,
+    score: u8,
+    dao_token: Option<Pubkey>,
+    user_dao_wallet: Option<Pubkey>,
+) -> Instruction {
+    build(
+        accounts::RateInferenceVld {
+            signer: *signer,
+            sol_learn_account: *sol_learn,
+            infs: pda::inference(inference_id),
+            creator_wallet: *creator_wallet,
+            dao_receiver_infos: pda::dao_receiver_infos(sol_learn, inference_id),
+            model_info: pda::model_info(sol_learn, model),
+            miners_of_model: pda::miners_of_model(sol_learn, model),
+            miner_account: pda::miner(miner, sol_learn),
+            vault_wallet_owner_pda: pda::vault(sol_learn),
+            dao_token,
+            user_dao_wallet,
+            token_program: token::ID,
+        },
+        instruction::RateInference {
+            inference_id,
+            score,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Discriminator;
+
+    #[test]
+    fn infer_derives_the_inference_accounts() {
+        let sol_learn = Pubkey::new_unique();
+        let model = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+        let ix = infer(
+            &signer,
+            &sol_learn,
+            &model,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            7,
+            &signer,
+            DataRef::inline(hash(b"prompt").to_bytes()),
+            Some(pda::data(&signer, 7)),
+            None,
+            1_000,
+        );
+
+        assert_eq!(ix.program_id, PROGRAM_ID);
+        let keys: Vec<Pubkey> = ix.accounts.iter().map(|a| a.pubkey).collect();
+        assert_eq!(keys[0], pda::inference(7));
+        assert_eq!(keys[2], pda::voting_info(7));
+        assert_eq!(keys[3], pda::dao_receiver_infos(&sol_learn, 7));
+        assert_eq!(keys[4], pda::tasks(&sol_learn, 7));
+        assert_eq!(keys[5], pda::model_info(&sol_learn, &model));
+        assert!(ix.accounts.iter().any(|a| a.pubkey == signer && a.is_signer));
+        assert_eq!(ix.data[..8], instruction::Infer::DISCRIMINATOR[..]);
+    }
+
+    #[test]
+    fn upload_data_writes_every_chunk_in_order() {
+        let owner = Pubkey::new_unique();
+        let content = vec![1u8; 2 * DATA_CHUNK_LEN + 1];
+        let (ixs, content_hash) = upload_data(&owner, 3, &content);
+
+        assert_eq!(content_hash, hash(&content).to_bytes());
+        assert_eq!(ixs.len(), 5);
+        assert_eq!(ixs[0].data[..8], instruction::CreateData::DISCRIMINATOR[..]);
+        assert_eq!(ixs[3].data[..8], instruction::WriteData::DISCRIMINATOR[..]);
+        assert_eq!(ixs[4].data[..8], instruction::FinalizeData::DISCRIMINATOR[..]);
+        assert!(ixs.iter().all(|ix| ix.accounts[0].pubkey == pda::data(&owner, 3)));
+    }
+
+    #[test]
+    fn claim_reward_appends_epoch_snapshots() {
+        let sol_learn = Pubkey::new_unique();
+        let ix = miner_claim_reward(
+            &Pubkey::new_unique(),
+            &sol_learn,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &[3, 4],
+        );
+
+        let n = ix.accounts.len();
+        assert_eq!(ix.accounts[n - 2].pubkey, pda::reward_in_epoch(3));
+        assert_eq!(ix.accounts[n - 1].pubkey, pda::reward_in_epoch(4));
+        assert!(!ix.accounts[n - 1].is_writable);
+    }
+}
@@ -0,0 +1,17 @@
+//! Rust client for the `solearn` program.
+//!
+//! `pda` derives every program address, `instructions` builds one
+//! `Instruction` per program instruction and `accounts` fetches and
+//! deserializes the program accounts.
+
+pub mod accounts;
+pub mod error;
+pub mod instructions;
+pub mod pda;
+
+pub use solearn_solana::ID as PROGRAM_ID;
+pub use solearn_solana::state::{Delegation, MinerInfo, MinerStats, ModelParams, SolLearnInfo};
+pub use solearn_solana::state_inf::{
+    Assignment, AssignmentList, AssignmentPage, ChunkedData, DataRef, DataScheme, Inference,
+    SlashReason, SlashRecord, Task, Tasks, VotingInfo,
+};
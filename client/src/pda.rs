@@ -0,0 +1,98 @@
+use anchor_lang::prelude::Pubkey;
+
+use crate::PROGRAM_ID;
+
+fn find(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &PROGRAM_ID).0
+}
+
+// owner of the staking vault wallet
+pub fn vault(sol_learn: &Pubkey) -> Pubkey {
+    find(&[b"vault", sol_learn.as_ref()])
+}
+
+// owner of the epoch reward wallet
+pub fn reward_pool(sol_learn: &Pubkey) -> Pubkey {
+    find(&[b"reward_pool", sol_learn.as_ref()])
+}
+
+// list of registered models
+pub fn models(sol_learn: &Pubkey) -> Pubkey {
+    find(&[b"models", sol_learn.as_ref()])
+}
+
+pub fn miners_of_model(sol_learn: &Pubkey, model: &Pubkey) -> Pubkey {
+    find(&[b"models", sol_learn.as_ref(), model.as_ref()])
+}
+
+pub fn model_info(sol_learn: &Pubkey, model: &Pubkey) -> Pubkey {
+    find(&[b"model_info", sol_learn.as_ref(), model.as_ref()])
+}
+
+pub fn miner(miner: &Pubkey, sol_learn: &Pubkey) -> Pubkey {
+    find(&[b"miner", miner.as_ref(), sol_learn.as_ref()])
+}
+
+pub fn miner_stats(miner: &Pubkey, sol_learn: &Pubkey) -> Pubkey {
+    find(&[b"miner_stats", miner.as_ref(), sol_learn.as_ref()])
+}
+
+// `miner_account` is the miner's PDA, not its wallet
+pub fn delegation(delegator: &Pubkey, miner_account: &Pubkey) -> Pubkey {
+    find(&[b"delegation", delegator.as_ref(), miner_account.as_ref()])
+}
+
+pub fn inference(inference_id: u64) -> Pubkey {
+    find(&[b"inference", inference_id.to_le_bytes().as_ref()])
+}
+
+pub fn voting_info(inference_id: u64) -> Pubkey {
+    find(&[b"voting_info", inference_id.to_le_bytes().as_ref()])
+}
+
+pub fn tasks(sol_learn: &Pubkey, inference_id: u64) -> Pubkey {
+    find(&[
+        b"tasks",
+        sol_learn.as_ref(),
+        inference_id.to_le_bytes().as_ref(),
+    ])
+}
+
+pub fn dao_receiver_infos(sol_learn: &Pubkey, inference_id: u64) -> Pubkey {
+    find(&[
+        b"dao_receiver_infos",
+        sol_learn.as_ref(),
+        inference_id.to_le_bytes().as_ref(),
+    ])
+}
+
+pub fn referrer(user: &Pubkey) -> Pubkey {
+    find(&[b"referrer", user.as_ref()])
+}
+
+pub fn referral_code(code: &str) -> Pubkey {
+    find(&[b"referral_code", code.as_bytes()])
+}
+
+// chunked input or output of `owner`; miners use the assignment id as
+// `data_id` of their outputs
+pub fn data(owner: &Pubkey, data_id: u64) -> Pubkey {
+    find(&[b"data", owner.as_ref(), data_id.to_le_bytes().as_ref()])
+}
+
+pub fn assignment_page(inference_id: u64, page: u8) -> Pubkey {
+    find(&[b"assignment_page", inference_id.to_le_bytes().as_ref(), &[page]])
+}
+
+pub fn assignment(assignment_id: u64) -> Pubkey {
+    find(&[b"assignment", assignment_id.to_le_bytes().as_ref()])
+}
+
+// `index` is the number of earlier slashes of the miner
+pub fn slash_record(miner_account: &Pubkey, index: u64) -> Pubkey {
+    find(&[b"slash_record", miner_account.as_ref(), index.to_le_bytes().as_ref()])
+}
+
+pub fn reward_in_epoch(epoch_id: u64) -> Pubkey {
+    find(&[b"reward_in_epoch", epoch_id.to_le_bytes().as_ref()])
+}
@@ -0,0 +1,24 @@
+use anchor_lang::prelude::Pubkey;
+
+use crate::Result;
+
+// runs the model of an inference on its input
+pub trait InferenceBackend: Send {
+    fn infer(&mut self, model: &Pubkey, input: &[u8]) -> Result<Vec<u8>>;
+}
+
+// answers every inference with its input, so all workers agree
+pub struct EchoBackend;
+
+impl InferenceBackend for EchoBackend {
+    fn infer(&mut self, _model: &Pubkey, input: &[u8]) -> Result<Vec<u8>> {
+        Ok(input.to_vec())
+    }
+}
+
+pub fn by_name(name: &str) -> Option<Box<dyn InferenceBackend>> {
+    match name {
+        "echo" => Some(Box::new(EchoBackend)),
+        _ => None,
+    }
+}
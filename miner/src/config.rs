@@ -0,0 +1,43 @@
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+
+use crate::Result;
+
+pub struct Config {
+    pub rpc_url: String,
+    pub ws_url: String,
+    pub keypair: PathBuf,
+    pub sol_learn: Pubkey,
+    pub vault_wallet: Pubkey,
+    pub nonces: PathBuf,
+    pub backend: String,
+}
+
+fn var_or(name: &str, default: &str) -> String {
+    env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn pubkey_var(name: &str) -> Result<Pubkey> {
+    let value = env::var(name).map_err(|_| format!("{} is not set", name))?;
+    Ok(Pubkey::from_str(&value).map_err(|err| format!("{}: {}", name, err))?)
+}
+
+impl Config {
+    // SOLEARN_ACCOUNT and SOLEARN_VAULT_WALLET are required, the rest point
+    // at a local test validator by default
+    pub fn from_env() -> Result<Self> {
+        let home = var_or("HOME", ".");
+        Ok(Config {
+            rpc_url: var_or("SOLEARN_RPC_URL", "http://127.0.0.1:8899"),
+            ws_url: var_or("SOLEARN_WS_URL", "ws://127.0.0.1:8900"),
+            keypair: var_or("SOLEARN_KEYPAIR", &format!("{}/.config/solana/id.json", home)).into(),
+            sol_learn: pubkey_var("SOLEARN_ACCOUNT")?,
+            vault_wallet: pubkey_var("SOLEARN_VAULT_WALLET")?,
+            nonces: var_or("SOLEARN_NONCES", "nonces.txt").into(),
+            backend: var_or("SOLEARN_BACKEND", "echo"),
+        })
+    }
+}
@@ -0,0 +1,95 @@
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use solearn_solana::state_inf::{
+    InferenceEscalated, InferenceStatusUpdate, NewAssignment, NewInference,
+};
+
+const PROGRAM_DATA: &str = "Program data: ";
+
+// program events the node reacts to
+pub enum Event {
+    NewInference(NewInference),
+    NewAssignment(NewAssignment),
+    InferenceStatusUpdate(InferenceStatusUpdate),
+    InferenceEscalated(InferenceEscalated),
+}
+
+fn decode<T: AnchorDeserialize + Discriminator>(data: &[u8]) -> Option<T> {
+    if data.len() < 8 || data[..8] != T::DISCRIMINATOR {
+        return None;
+    }
+    T::deserialize(&mut &data[8..]).ok()
+}
+
+fn parse(data: &[u8]) -> Option<Event> {
+    if let Some(event) = decode(data) {
+        return Some(Event::NewInference(event));
+    }
+    if let Some(event) = decode(data) {
+        return Some(Event::NewAssignment(event));
+    }
+    if let Some(event) = decode(data) {
+        return Some(Event::InferenceStatusUpdate(event));
+    }
+    decode(data).map(Event::InferenceEscalated)
+}
+
+// events emitted through `emit!`, in log order
+pub fn parse_logs(logs: &[String]) -> Vec<Event> {
+    logs.iter()
+        .filter_map(|line| line.strip_prefix(PROGRAM_DATA))
+        .filter_map(|data| STANDARD.decode(data).ok())
+        .filter_map(|data| parse(&data))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::prelude::Pubkey;
+
+    fn log(event: impl anchor_lang::Event) -> String {
+        format!("{}{}", PROGRAM_DATA, STANDARD.encode(event.data()))
+    }
+
+    #[test]
+    fn parses_program_data_in_order() {
+        let worker = Pubkey::new_unique();
+        let logs = vec![
+            "Program log: Instruction: CreateAssignment".to_string(),
+            log(NewAssignment {
+                assignment_id: 3,
+                inference_id: 1,
+                worker,
+            }),
+            log(InferenceStatusUpdate {
+                inference_id: 1,
+                status: 2,
+            }),
+        ];
+
+        let events = parse_logs(&logs);
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            Event::NewAssignment(event) => {
+                assert_eq!(event.assignment_id, 3);
+                assert_eq!(event.worker, worker);
+            }
+            _ => panic!("expected NewAssignment"),
+        }
+        assert!(matches!(
+            &events[1],
+            Event::InferenceStatusUpdate(event) if event.status == 2
+        ));
+    }
+
+    #[test]
+    fn skips_unknown_events() {
+        let logs = vec![
+            format!("{}{}", PROGRAM_DATA, STANDARD.encode([0u8; 16])),
+            format!("{}not base64!", PROGRAM_DATA),
+        ];
+        assert!(parse_logs(&logs).is_empty());
+    }
+}
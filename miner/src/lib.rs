@@ -0,0 +1,14 @@
+//! Miner node for the `solearn` program.
+//!
+//! The node follows the program logs, creates queued assignments, races for
+//! the mining role of its own assignments and answers with a local
+//! `InferenceBackend`, either by submitting the solution or by the
+//! commit/reveal flow when another worker mines.
+
+pub mod backend;
+pub mod config;
+pub mod events;
+pub mod node;
+pub mod nonces;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -0,0 +1,34 @@
+use std::process;
+
+use solana_sdk::signature::read_keypair_file;
+use solearn_miner::config::Config;
+use solearn_miner::node::Node;
+use solearn_miner::nonces::NonceStore;
+use solearn_miner::{backend, Result};
+
+fn start() -> Result<()> {
+    let config = Config::from_env()?;
+    let signer = read_keypair_file(&config.keypair)
+        .map_err(|err| format!("{}: {}", config.keypair.display(), err))?;
+    let backend = backend::by_name(&config.backend)
+        .ok_or_else(|| format!("unknown backend {}", config.backend))?;
+    let nonces = NonceStore::open(&config.nonces)?;
+
+    let mut node = Node::new(
+        &config.rpc_url,
+        signer,
+        config.sol_learn,
+        config.vault_wallet,
+        backend,
+        nonces,
+    );
+    println!("mining as {}", node.worker());
+    node.run(&config.ws_url)
+}
+
+fn main() {
+    if let Err(err) = start() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,349 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::keccak::{hash, hashv};
+use solana_client::pubsub_client::PubsubClient;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::transaction::Transaction;
+use solearn_client::accounts::{
+    fetch_assignment, fetch_assignments, fetch_data, fetch_inference, fetch_tasks,
+};
+use solearn_client::{instructions, pda, Assignment, DataRef, DataScheme, Inference, Task, PROGRAM_ID};
+
+use crate::backend::InferenceBackend;
+use crate::events::{parse_logs, Event};
+use crate::nonces::{Commitment, NonceStore};
+use crate::Result;
+
+// inference status values, see InferenceStatus
+const SOLVING: u8 = 1;
+const COMMIT: u8 = 2;
+const REVEAL: u8 = 3;
+
+// assignment role values, see AssignmentRole
+const VALIDATING: u8 = 1;
+
+// what a restarted node still owes for an assignment it holds a nonce for
+#[derive(Debug, PartialEq)]
+enum Resume {
+    Commit,
+    Reveal,
+    // committed, the reveal phase has not started yet
+    Wait,
+    // revealed already or too late to, the nonce is of no use anymore
+    Forget,
+}
+
+fn resume_step(status: u8, assignment: &Assignment) -> Resume {
+    if assignment.digest != [0; 32] {
+        return Resume::Forget;
+    }
+    match status {
+        COMMIT if assignment.commitment == [0; 32] => Resume::Commit,
+        COMMIT => Resume::Wait,
+        REVEAL if assignment.commitment != [0; 32] => Resume::Reveal,
+        _ => Resume::Forget,
+    }
+}
+
+// commitment checked by `reveal`: keccak(nonce || worker || keccak(output))
+pub fn commitment(nonce: u64, worker: &Pubkey, output: &[u8]) -> [u8; 32] {
+    hashv(&[&nonce.to_le_bytes(), worker.as_ref(), &hash(output).to_bytes()]).to_bytes()
+}
+
+pub struct Node {
+    rpc: RpcClient,
+    signer: Keypair,
+    sol_learn: Pubkey,
+    vault_wallet: Pubkey,
+    backend: Box<dyn InferenceBackend>,
+    nonces: NonceStore,
+}
+
+impl Node {
+    pub fn new(
+        rpc_url: &str,
+        signer: Keypair,
+        sol_learn: Pubkey,
+        vault_wallet: Pubkey,
+        backend: Box<dyn InferenceBackend>,
+        nonces: NonceStore,
+    ) -> Self {
+        Node {
+            rpc: RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed()),
+            signer,
+            sol_learn,
+            vault_wallet,
+            backend,
+            nonces,
+        }
+    }
+
+    pub fn worker(&self) -> Pubkey {
+        self.signer.pubkey()
+    }
+
+    // follows the program logs until the subscription drops; a failed event
+    // is logged and skipped so one bad inference does not stop the node
+    pub fn run(&mut self, ws_url: &str) -> Result<()> {
+        let (_subscription, receiver) = PubsubClient::logs_subscribe(
+            ws_url,
+            RpcTransactionLogsFilter::Mentions(vec![PROGRAM_ID.to_string()]),
+            RpcTransactionLogsConfig {
+                commitment: Some(CommitmentConfig::confirmed()),
+            },
+        )?;
+        // subscribed first so nothing is missed between the scan and the logs
+        self.resume();
+
+        for response in receiver {
+            if response.value.err.is_some() {
+                continue;
+            }
+            for event in parse_logs(&response.value.logs) {
+                if let Err(err) = self.handle(event) {
+                    eprintln!("{}: {}", response.value.signature, err);
+                }
+            }
+        }
+        Err("log subscription closed".into())
+    }
+
+    // commits or reveals what the node missed while it was down, for every
+    // assignment with a stored nonce; failures are logged like in `run`
+    pub fn resume(&mut self) {
+        for assignment_id in self.nonces.assignment_ids() {
+            if let Err(err) = self.resume_assignment(assignment_id) {
+                eprintln!("assignment {}: {}", assignment_id, err);
+            }
+        }
+    }
+
+    fn resume_assignment(&mut self, assignment_id: u64) -> Result<()> {
+        let assignment = fetch_assignment(&self.rpc, assignment_id)?;
+        let inference = fetch_inference(&self.rpc, assignment.inference_id)?;
+        match resume_step(inference.status, &assignment) {
+            Resume::Commit => self.commit(inference.id),
+            Resume::Reveal => self.reveal(inference.id),
+            Resume::Wait => Ok(()),
+            Resume::Forget => Ok(self.nonces.remove(assignment_id)?),
+        }
+    }
+
+    pub fn handle(&mut self, event: Event) -> Result<()> {
+        match event {
+            Event::NewInference(event) => self.create_assignments(event.inference_id),
+            // a new validation round queues new assignments
+            Event::InferenceEscalated(event) => self.create_assignments(event.inference_id),
+            Event::NewAssignment(event) if event.worker == self.worker() => {
+                self.seize_or_wait(event.inference_id, event.assignment_id)
+            }
+            Event::NewAssignment(_) => Ok(()),
+            Event::InferenceStatusUpdate(event) => match event.status {
+                COMMIT => self.commit(event.inference_id),
+                REVEAL => self.reveal(event.inference_id),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    fn send(&self, ix: Instruction) -> Result<Signature> {
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.signer.pubkey()),
+            &[&self.signer],
+            blockhash,
+        );
+        Ok(self.rpc.send_and_confirm_transaction(&tx)?)
+    }
+
+    // writes an output to the data account of the assignment
+    fn upload(&self, assignment_id: u64, output: &[u8]) -> Result<DataRef> {
+        let (ixs, output_hash) = instructions::upload_data(&self.worker(), assignment_id, output);
+        for ix in ixs {
+            self.send(ix)?;
+        }
+        Ok(DataRef::inline(output_hash))
+    }
+
+    // only inline inputs are read, off-chain ones need a fetcher for the uri
+    fn input(&self, inference: &Inference) -> Result<Vec<u8>> {
+        match inference.input.scheme {
+            DataScheme::Inline => Ok(fetch_data(&self.rpc, &inference.input_data)?),
+            _ => Err(format!("cannot fetch input {}", inference.input.uri).into()),
+        }
+    }
+
+    // anyone may create the queued assignments, in queue order
+    fn create_assignments(&mut self, inference_id: u64) -> Result<()> {
+        let tasks = fetch_tasks(&self.rpc, &self.sol_learn, inference_id)?;
+        for task in tasks.pending()? {
+            let assignment_id = match task {
+                Task::CreateAssignment { assignment_id, .. } => assignment_id,
+                _ => break,
+            };
+            let ix = instructions::create_assignment(
+                &self.worker(),
+                &self.sol_learn,
+                inference_id,
+                assignment_id,
+            );
+            if let Err(err) = self.send(ix) {
+                // another node created it first
+                if self.rpc.get_account(&pda::assignment(assignment_id)).is_err() {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn seize_or_wait(&mut self, inference_id: u64, assignment_id: u64) -> Result<()> {
+        let inference = fetch_inference(&self.rpc, inference_id)?;
+        if inference.status != SOLVING || inference.processed_miner != Pubkey::default() {
+            return Ok(());
+        }
+        let ix = instructions::seize_miner_role(
+            &self.worker(),
+            &self.sol_learn,
+            inference_id,
+            assignment_id,
+        );
+        if self.send(ix).is_err() {
+            // lost the race, validate once the solution is submitted
+            return Ok(());
+        }
+
+        let input = self.input(&inference)?;
+        let output = self.backend.infer(&inference.model_address, &input)?;
+        let output = self.upload(assignment_id, &output)?;
+        let ix = instructions::submit_solution(
+            &self.worker(),
+            &self.sol_learn,
+            inference_id,
+            assignment_id,
+            output,
+        );
+        self.send(ix)?;
+        Ok(())
+    }
+
+    fn own_assignment(&self, inference: &Inference) -> Result<Option<Assignment>> {
+        for assignment_id in fetch_assignments(&self.rpc, inference)?.ids {
+            let assignment = fetch_assignment(&self.rpc, assignment_id)?;
+            if assignment.worker == self.worker() {
+                return Ok(Some(assignment));
+            }
+        }
+        Ok(None)
+    }
+
+    fn commit(&mut self, inference_id: u64) -> Result<()> {
+        let inference = fetch_inference(&self.rpc, inference_id)?;
+        let assignment = match self.own_assignment(&inference)? {
+            Some(assignment) => assignment,
+            None => return Ok(()),
+        };
+        if assignment.role != VALIDATING || assignment.commitment != [0; 32] {
+            return Ok(());
+        }
+
+        let input = self.input(&inference)?;
+        let output = self.backend.infer(&inference.model_address, &input)?;
+        let nonce = rand::random::<u64>();
+        let hash = commitment(nonce, &self.worker(), &output);
+        // persist first, a commitment without its nonce can never be revealed
+        self.nonces.insert(assignment.id, Commitment { nonce, output })?;
+
+        let ix = instructions::commit(
+            &self.worker(),
+            &self.sol_learn,
+            inference_id,
+            assignment.id,
+            hash,
+        );
+        self.send(ix)?;
+        Ok(())
+    }
+
+    fn reveal(&mut self, inference_id: u64) -> Result<()> {
+        let inference = fetch_inference(&self.rpc, inference_id)?;
+        let assignment = match self.own_assignment(&inference)? {
+            Some(assignment) => assignment,
+            None => return Ok(()),
+        };
+        if assignment.role != VALIDATING || assignment.digest != [0; 32] {
+            return Ok(());
+        }
+        let committed = match self.nonces.get(assignment.id) {
+            Some(committed) => committed.clone(),
+            None => return Err(format!("no nonce stored for assignment {}", assignment.id).into()),
+        };
+        // written only now, before the reveal the output must stay hidden
+        let output = self.upload(assignment.id, &committed.output)?;
+
+        let ix = instructions::reveal(
+            &self.worker(),
+            &self.sol_learn,
+            &inference.model_address,
+            &self.vault_wallet,
+            &inference.creator,
+            inference_id,
+            assignment.id,
+            committed.nonce,
+            output,
+        );
+        self.send(ix)?;
+        self.nonces.remove(assignment.id)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commitment_binds_nonce_worker_and_output() {
+        let worker = Pubkey::new_unique();
+        let hash = commitment(5, &worker, b"answer");
+
+        let mut concatenated = 5u64.to_le_bytes().to_vec();
+        concatenated.extend(worker.to_bytes());
+        concatenated.extend(anchor_lang::solana_program::keccak::hash(b"answer").to_bytes());
+        assert_eq!(hash, anchor_lang::solana_program::keccak::hash(&concatenated).to_bytes());
+        assert_ne!(hash, commitment(6, &worker, b"answer"));
+        assert_ne!(hash, commitment(5, &Pubkey::new_unique(), b"answer"));
+    }
+
+    #[test]
+    fn resume_follows_the_inference_status() {
+        let mut assignment = Assignment {
+            bump: 0,
+            id: 1,
+            inference_id: 1,
+            commitment: [0; 32],
+            digest: [0; 32],
+            reveal_nonce: 0,
+            worker: Pubkey::new_unique(),
+            role: VALIDATING,
+            vote: 0,
+            output: DataRef::default(),
+            output_data: Pubkey::default(),
+        };
+        assert_eq!(resume_step(COMMIT, &assignment), Resume::Commit);
+        // the commit never landed, there is nothing to reveal
+        assert_eq!(resume_step(REVEAL, &assignment), Resume::Forget);
+
+        assignment.commitment = [1; 32];
+        assert_eq!(resume_step(COMMIT, &assignment), Resume::Wait);
+        assert_eq!(resume_step(REVEAL, &assignment), Resume::Reveal);
+        assert_eq!(resume_step(4, &assignment), Resume::Forget);
+
+        assignment.digest = [2; 32];
+        assert_eq!(resume_step(REVEAL, &assignment), Resume::Forget);
+    }
+}
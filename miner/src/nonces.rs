@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+// nonce and output committed for an assignment, kept until the reveal
+#[derive(Clone, Debug, PartialEq)]
+pub struct Commitment {
+    pub nonce: u64,
+    pub output: Vec<u8>,
+}
+
+// one `assignment_id nonce hex_output` line per pending reveal, so a restarted
+// node can still reveal what it committed
+pub struct NonceStore {
+    path: PathBuf,
+    entries: HashMap<u64, Commitment>,
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<(u64, Commitment)> {
+    let mut parts = line.split_whitespace();
+    let assignment_id = parts.next()?.parse().ok()?;
+    let nonce = parts.next()?.parse().ok()?;
+    let output = from_hex(parts.next().unwrap_or(""))?;
+    Some((assignment_id, Commitment { nonce, output }))
+}
+
+impl NonceStore {
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        let entries = content.lines().filter_map(parse_line).collect();
+        Ok(NonceStore { path, entries })
+    }
+
+    pub fn get(&self, assignment_id: u64) -> Option<&Commitment> {
+        self.entries.get(&assignment_id)
+    }
+
+    // assignments with a stored commitment, in ascending order
+    pub fn assignment_ids(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self.entries.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    pub fn insert(&mut self, assignment_id: u64, commitment: Commitment) -> io::Result<()> {
+        self.entries.insert(assignment_id, commitment);
+        self.save()
+    }
+
+    pub fn remove(&mut self, assignment_id: u64) -> io::Result<()> {
+        if self.entries.remove(&assignment_id).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut ids: Vec<&u64> = self.entries.keys().collect();
+        ids.sort();
+        let content: String = ids
+            .into_iter()
+            .map(|id| {
+                let entry = &self.entries[id];
+                format!("{} {} {}\n", id, entry.nonce, to_hex(&entry.output))
+            })
+            .collect();
+        // write then rename so a crash never leaves a truncated file
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commitments_survive_a_reopen() {
+        let path = std::env::temp_dir().join(format!("solearn-nonces-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut store = NonceStore::open(&path).unwrap();
+        let first = Commitment {
+            nonce: 42,
+            output: b"answer".to_vec(),
+        };
+        let empty = Commitment {
+            nonce: 7,
+            output: vec![],
+        };
+        store.insert(1, first.clone()).unwrap();
+        store.insert(2, empty.clone()).unwrap();
+        store.insert(3, first.clone()).unwrap();
+        store.remove(3).unwrap();
+
+        let store = NonceStore::open(&path).unwrap();
+        assert_eq!(store.get(1), Some(&first));
+        assert_eq!(store.get(2), Some(&empty));
+        assert_eq!(store.get(3), None);
+        assert_eq!(store.assignment_ids(), vec![1, 2]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+//! End-to-end run of three miner nodes against a local test validator.
+//!
+//! Inference and assignment addresses are not scoped to a `sol_learn`
+//! account, so start a fresh validator with the program deployed first:
+//!
+//!     anchor build
+//!     solana-test-validator --reset \
+//!         --bpf-program GKcNsg7fGp4U5Sh3EhzU27zf136DqzDgfb2MJKRqo1cs target/deploy/solearn_solana.so
+//!     cargo test -p solearn-miner --test local_validator -- --ignored
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::system_instruction;
+use anchor_spl::token::spl_token;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use solearn_client::accounts::{
+    fetch_assignment, fetch_assignments, fetch_data, fetch_inference, fetch_sol_learn_info,
+};
+use solearn_client::{instructions, pda, DataRef, ModelParams};
+use solearn_miner::backend::EchoBackend;
+use solearn_miner::node::Node;
+use solearn_miner::nonces::NonceStore;
+
+const RPC_URL: &str = "http://127.0.0.1:8899";
+const WS_URL: &str = "ws://127.0.0.1:8900";
+const MIN_STAKE: u64 = 1_000_000;
+const MINIMUM_FEE: u64 = 1_000;
+const INFER_VALUE: u64 = 101_000;
+const DURATION: u64 = 1_000;
+
+struct Chain {
+    rpc: RpcClient,
+    payer: Keypair,
+    mint: Keypair,
+}
+
+impl Chain {
+    fn send(&self, ixs: &[Instruction], signers: &[&Keypair]) {
+        let mut all_signers = vec![&self.payer];
+        all_signers.extend_from_slice(signers);
+        let blockhash = self.rpc.get_latest_blockhash().unwrap();
+        let tx = Transaction::new_signed_with_payer(
+            ixs,
+            Some(&self.payer.pubkey()),
+            &all_signers[..],
+            blockhash,
+        );
+        self.rpc.send_and_confirm_transaction(&tx).unwrap();
+    }
+
+    fn fund(&self, to: &Pubkey) {
+        self.send(
+            &[system_instruction::transfer(&self.payer.pubkey(), to, 1_000_000_000)],
+            &[],
+        );
+    }
+
+    fn create_mint(&self) {
+        let payer = self.payer.pubkey();
+        let space = spl_token::state::Mint::LEN;
+        let rent = self.rpc.get_minimum_balance_for_rent_exemption(space).unwrap();
+        self.send(
+            &[
+                system_instruction::create_account(
+                    &payer,
+                    &self.mint.pubkey(),
+                    rent,
+                    space as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint2(
+                    &spl_token::id(),
+                    &self.mint.pubkey(),
+                    &payer,
+                    None,
+                    6,
+                )
+                .unwrap(),
+            ],
+            &[&self.mint],
+        );
+    }
+
+    fn create_wallet(&self, owner: &Pubkey, amount: u64) -> Pubkey {
+        let payer = self.payer.pubkey();
+        let wallet = Keypair::new();
+        let space = spl_token::state::Account::LEN;
+        let rent = self.rpc.get_minimum_balance_for_rent_exemption(space).unwrap();
+        let mut ixs = vec![
+            system_instruction::create_account(
+                &payer,
+                &wallet.pubkey(),
+                rent,
+                space as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &wallet.pubkey(),
+                &self.mint.pubkey(),
+                owner,
+            )
+            .unwrap(),
+        ];
+        if amount > 0 {
+            ixs.push(
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    &self.mint.pubkey(),
+                    &wallet.pubkey(),
+                    &payer,
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+            );
+        }
+        self.send(&ixs, &[&wallet]);
+        wallet.pubkey()
+    }
+}
+
+#[test]
+#[ignore = "needs a fresh local test validator with the program deployed"]
+fn three_nodes_resolve_an_inference() {
+    let rpc = RpcClient::new_with_commitment(RPC_URL.to_string(), CommitmentConfig::confirmed());
+    let payer = Keypair::new();
+    let airdrop = rpc.request_airdrop(&payer.pubkey(), 100_000_000_000).unwrap();
+    while !rpc.confirm_transaction(&airdrop).unwrap() {
+        thread::sleep(Duration::from_millis(200));
+    }
+    let chain = Chain {
+        rpc,
+        payer,
+        mint: Keypair::new(),
+    };
+    let admin = chain.payer.pubkey();
+    chain.create_mint();
+
+    let sol_learn = Keypair::new();
+    let model = Pubkey::new_unique();
+    let vault_wallet = chain.create_wallet(&pda::vault(&sol_learn.pubkey()), 0);
+    let treasury = Pubkey::new_unique();
+    chain.send(
+        &[instructions::initialize(
+            &admin,
+            &sol_learn.pubkey(),
+            &chain.mint.pubkey(),
+            solearn_solana::instruction::Initialize {
+                reward_per_epoch: 0,
+                epoch_duration: 1_000_000_000,
+                min_stake: MIN_STAKE,
+                treasury_address: treasury,
+                fee_l2_percentage: 10_00,
+                fee_treasury_percentage: 10_00,
+                fee_ratio_miner_validator: 50_00,
+                submit_duration: DURATION,
+                commit_duration: DURATION,
+                reveal_duration: DURATION,
+                penalty_duration: 0,
+                miner_requirement: 3,
+                fine_percentage: 10_00,
+                dao_token_reward: 0,
+                miner_percentage: 0,
+                user_percentage: 0,
+                referrer_percentage: 0,
+                referee_percentage: 0,
+                l2_owner_percentage: 0,
+                unstake_delay_time: 0,
+            },
+        )],
+        &[&sol_learn],
+    );
+    let sol_learn = sol_learn.pubkey();
+    chain.send(
+        &[instructions::create_model(
+            &admin,
+            &sol_learn,
+            &model,
+            ModelParams {
+                minimum_fee: MINIMUM_FEE,
+                tier: 0,
+                miner_requirement: 3,
+                submit_duration: DURATION,
+                commit_duration: DURATION,
+                reveal_duration: DURATION,
+            },
+        )],
+        &[],
+    );
+
+    let miners: Vec<Keypair> = (0..3).map(|_| Keypair::new()).collect();
+    for miner in miners.iter() {
+        chain.fund(&miner.pubkey());
+        let wallet = chain.create_wallet(&miner.pubkey(), MIN_STAKE);
+        chain.send(
+            &[
+                instructions::miner_register(
+                    &miner.pubkey(),
+                    &sol_learn,
+                    &wallet,
+                    &vault_wallet,
+                    &chain.mint.pubkey(),
+                    MIN_STAKE,
+                ),
+                instructions::join_for_minting(&miner.pubkey(), &sol_learn, &model),
+            ],
+            &[miner],
+        );
+    }
+
+    for (i, miner) in miners.iter().enumerate() {
+        let nonces = std::env::temp_dir().join(format!("solearn-e2e-{}-{}.txt", std::process::id(), i));
+        let mut node = Node::new(
+            RPC_URL,
+            miner.insecure_clone(),
+            sol_learn,
+            vault_wallet,
+            Box::new(EchoBackend),
+            NonceStore::open(nonces).unwrap(),
+        );
+        thread::spawn(move || node.run(WS_URL).unwrap());
+    }
+    // let the nodes subscribe before anything is emitted
+    thread::sleep(Duration::from_secs(2));
+
+    let user = Keypair::new();
+    chain.fund(&user.pubkey());
+    let user_wallet = chain.create_wallet(&user.pubkey(), INFER_VALUE);
+    let inference_id = fetch_sol_learn_info(&chain.rpc, &sol_learn)
+        .unwrap()
+        .inference_number
+        + 1;
+    let (upload, input_hash) = instructions::upload_data(&user.pubkey(), inference_id, b"prompt");
+    chain.send(&upload, &[&user]);
+    chain.send(
+        &[instructions::infer(
+            &user.pubkey(),
+            &sol_learn,
+            &model,
+            &user_wallet,
+            &vault_wallet,
+            inference_id,
+            &user_wallet,
+            DataRef::inline(input_hash),
+            Some(pda::data(&user.pubkey(), inference_id)),
+            None,
+            INFER_VALUE,
+        )],
+        &[&user],
+    );
+
+    let deadline = Instant::now() + Duration::from_secs(120);
+    let inference = loop {
+        let inference = fetch_inference(&chain.rpc, inference_id).unwrap();
+        if inference.status >= 4 {
+            break inference;
+        }
+        assert!(Instant::now() < deadline, "stuck in status {}", inference.status);
+        thread::sleep(Duration::from_millis(500));
+    };
+
+    assert_eq!(inference.status, 4);
+    assert!(miners.iter().any(|m| m.pubkey() == inference.processed_miner));
+    for assignment_id in fetch_assignments(&chain.rpc, &inference).unwrap().ids {
+        let assignment = fetch_assignment(&chain.rpc, assignment_id).unwrap();
+        assert_eq!(assignment.output, DataRef::inline(input_hash));
+        assert_eq!(fetch_data(&chain.rpc, &assignment.output_data).unwrap(), b"prompt".to_vec());
+    }
+}
@@ -1,15 +1,15 @@
 pub mod errors;
 pub mod state;
-mod utils;
+pub mod utils;
 
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::keccak::hash;
 use anchor_lang::system_program;
 use anchor_spl::token::{self, transfer_checked, Transfer, TransferChecked};
 use solearn_solana::cpi::accounts::InferVld;
+use solearn_solana::state_inf::DataRef;
 use errors::*;
 use state::*;
-use utils::*;
 
 declare_id!("GJDRVDToZqT6ZQZ74TreUqm4tvR8yYhUcMwYKMHucoen");
 
@@ -93,13 +93,16 @@ pub mod hybrid_model {
         Ok(())
     }
 
-    pub fn infer(ctx: Context<CpiInferVld>,
+    // an inline input is written to a solearn data account beforehand, the
+    // assignment pages of the inference go in the remaining accounts
+    pub fn infer<'info>(ctx: Context<'_, '_, '_, 'info, CpiInferVld<'info>>,
         id_collection: u64,
         id_nft: u64,
-        input: Vec<u8>,
+        input: DataRef,
         creator: Pubkey,
         _value: u64,
         inference_id: u64,
+        model: Pubkey,
     ) -> Result<()> {
         msg!("Instruction: Infer");
         let cpi_program = ctx.accounts.solearn_program.to_account_info();
@@ -110,19 +113,24 @@ pub mod hybrid_model {
             // assignment: ctx.accounts.assignment.to_account_info(), // Add missing field
             // miner_addresses: ctx.accounts.miner_addresses.to_account_info(), // Add missing field
             tasks: ctx.accounts.tasks.to_account_info(), // Add missing field
+            dao_receiver_infos: ctx.accounts.dao_receiver_infos.to_account_info(),
             signer: ctx.accounts.signer.to_account_info(), // Add missing field
             vault_wallet_owner_pda: ctx.accounts.vault_wallet_owner_pda.to_account_info(), // Add missing field
             miner_staking_wallet: ctx.accounts.miner_staking_wallet.to_account_info(),
-            models: ctx.accounts.models.to_account_info(),
-            // referrer: ctx.accounts.referrer.to_account_info(),
+            recent_slothashes: ctx.accounts.recent_slothashes.to_account_info(),
+            model_info: ctx.accounts.model_info.to_account_info(),
+            referee: ctx.accounts.referee.to_account_info(),
+            referrer: ctx.accounts.referrer.as_ref().map(|referrer| referrer.to_account_info()),
             token_program: ctx.accounts.token_program.to_account_info(),
             vault_staking_wallet: ctx.accounts.vault_staking_wallet.to_account_info(),
             miners_of_model: ctx.accounts.miners_of_model.to_account_info(),
             // dao_receiver_infos: ctx.accounts.miners_of_model.to_account_info(),
             voting_info: ctx.accounts.miners_of_model.to_account_info(),
+            input_data: ctx.accounts.input_data.as_ref().map(|data| data.to_account_info()),
         };
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        solearn_solana::cpi::infer(cpi_ctx, inference_id, creator, input, _value, ctx.accounts.models.key())?;
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        solearn_solana::cpi::infer(cpi_ctx, inference_id, creator, input, _value, model)?;
         Ok(())
     }
 }
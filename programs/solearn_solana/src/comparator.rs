@@ -0,0 +1,103 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak::{hash, hashv};
+
+use crate::errors::*;
+
+// How validators' outputs are compared with each other, set per model.
+// COMPARATOR_EXACT: any bytes, equal outputs only.
+// COMPARATOR_NUMERIC: a vector of fixed-point i64 (little endian), equal when
+//   every value is within the tolerance of the group's first output.
+// COMPARATOR_LABEL: a u32 class label (little endian) followed by anything
+//   (scores, logits), equal when the labels are.
+pub const COMPARATOR_EXACT: u8 = 0;
+pub const COMPARATOR_NUMERIC: u8 = 1;
+pub const COMPARATOR_LABEL: u8 = 2;
+
+// max_len of DigestTally::values
+pub const MAX_NUMERIC_VALUES: usize = 8;
+
+pub fn validate_comparator(comparator: u8) -> Result<()> {
+    if comparator > COMPARATOR_LABEL {
+        return Err(SolLearnError::InvalidComparator.into());
+    }
+    Ok(())
+}
+
+// digest of an output under the exact comparator, over the keccak of its
+// content so that inline and off-chain copies of an output agree
+pub fn exact_digest(inference_id: u64, content_hash: &[u8; 32]) -> [u8; 32] {
+    hashv(&[&inference_id.to_le_bytes(), content_hash]).to_bytes()
+}
+
+// Digest an output is grouped by and, for numeric outputs, its values.
+// Label outputs are hashed on the label alone so that any two outputs with
+// the same label share a digest.
+pub fn parse_output(comparator: u8, inference_id: u64, output: &[u8]) -> Result<([u8; 32], Vec<i64>)> {
+    let id = inference_id.to_le_bytes();
+    match comparator {
+        COMPARATOR_EXACT => Ok((exact_digest(inference_id, &hash(output).to_bytes()), vec![])),
+        COMPARATOR_NUMERIC => {
+            if output.is_empty() || !output.len().is_multiple_of(8) || output.len() / 8 > MAX_NUMERIC_VALUES {
+                return Err(SolLearnError::InvalidOutput.into());
+            }
+            let values = output
+                .chunks(8)
+                .map(|chunk| i64::from_le_bytes(chunk.try_into().unwrap()))
+                .collect();
+            Ok((hashv(&[&id, output]).to_bytes(), values))
+        }
+        COMPARATOR_LABEL => {
+            if output.len() < 4 {
+                return Err(SolLearnError::InvalidOutput.into());
+            }
+            Ok((hashv(&[&id, &output[..4]]).to_bytes(), vec![]))
+        }
+        _ => Err(SolLearnError::InvalidComparator.into()),
+    }
+}
+
+pub fn within_tolerance(a: &[i64], b: &[i64], tolerance: u64) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.abs_diff(*y) <= tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numeric(values: &[i64]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn labels_share_a_digest_whatever_follows() {
+        let (a, _) = parse_output(COMPARATOR_LABEL, 1, &[3, 0, 0, 0, 9, 9]).unwrap();
+        let (b, _) = parse_output(COMPARATOR_LABEL, 1, &[3, 0, 0, 0]).unwrap();
+        let (c, _) = parse_output(COMPARATOR_LABEL, 1, &[4, 0, 0, 0, 9, 9]).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(parse_output(COMPARATOR_LABEL, 1, &[3, 0]).is_err());
+    }
+
+    #[test]
+    fn numeric_outputs_are_compared_within_the_tolerance() {
+        let (_, a) = parse_output(COMPARATOR_NUMERIC, 1, &numeric(&[1_000, -2_000])).unwrap();
+        let (_, b) = parse_output(COMPARATOR_NUMERIC, 1, &numeric(&[1_005, -1_995])).unwrap();
+        assert_eq!(a, vec![1_000, -2_000]);
+        assert!(within_tolerance(&a, &b, 5));
+        assert!(!within_tolerance(&a, &b, 4));
+        assert!(!within_tolerance(&a, &b[..1], 5));
+
+        assert!(parse_output(COMPARATOR_NUMERIC, 1, &[0; 7]).is_err());
+        assert!(parse_output(COMPARATOR_NUMERIC, 1, &[]).is_err());
+        assert!(parse_output(COMPARATOR_NUMERIC, 1, &numeric(&[0; MAX_NUMERIC_VALUES + 1])).is_err());
+    }
+
+    #[test]
+    fn exact_digest_binds_the_inference() {
+        let (a, _) = parse_output(COMPARATOR_EXACT, 1, b"answer").unwrap();
+        let (b, _) = parse_output(COMPARATOR_EXACT, 2, b"answer").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a, exact_digest(1, &hash(b"answer").to_bytes()));
+        assert!(parse_output(3, 1, b"answer").is_err());
+    }
+}
@@ -59,4 +59,86 @@ pub enum SolLearnError {
     InvalidModelIndex,
     #[msg("Unknown struct field")]
     UnknownStructField,
+    #[msg("Invalid slot hashes sysvar")]
+    InvalidSlotHashes,
+    #[msg("Invalid selection mode")]
+    InvalidSelectionMode,
+    #[msg("Model is paused")]
+    ModelPaused,
+    #[msg("Model still has active miners")]
+    ModelHasMiners,
+    #[msg("Invalid miner requirement")]
+    InvalidMinerRequirement,
+    #[msg("Must claim pending epoch rewards first")]
+    MustClaimReward,
+    #[msg("Unsupported task version")]
+    UnsupportedTaskVersion,
+    #[msg("Missing accounts for the next task")]
+    MissingTaskAccounts,
+    #[msg("Too many pending unstake requests")]
+    TooManyUnstakeRequests,
+    #[msg("Remaining stake must be zero or at least the minimum stake")]
+    StakeBelowMinimum,
+    #[msg("Invalid commission")]
+    InvalidCommission,
+    #[msg("Delegated stake was fined away, delegators must withdraw first")]
+    DelegationDrained,
+    #[msg("Invalid percentage")]
+    InvalidPercentage,
+    #[msg("Invalid slash record")]
+    InvalidSlashRecord,
+    #[msg("Invalid fine model")]
+    InvalidFineModel,
+    #[msg("Slash can not be disputed")]
+    NotDisputable,
+    #[msg("No open dispute on this slash")]
+    NoOpenDispute,
+    #[msg("Slash is not executable yet")]
+    SlashNotExecutable,
+    #[msg("Missing assignment pages")]
+    MissingAssignmentPages,
+    #[msg("Invalid assignment page")]
+    InvalidAssignmentPage,
+    #[msg("Invalid comparator")]
+    InvalidComparator,
+    #[msg("Output does not match the comparator of the model")]
+    InvalidOutput,
+    #[msg("Invalid data length")]
+    InvalidDataLength,
+    #[msg("Write out of the data bounds")]
+    InvalidDataRange,
+    #[msg("Data is not finalized")]
+    DataNotFinalized,
+    #[msg("Data does not match its hash")]
+    DataHashMismatch,
+    #[msg("Invalid data reference")]
+    InvalidDataRef,
+    #[msg("The comparator of the model needs the output inline")]
+    InlineOutputRequired,
+    #[msg("Invalid referral code")]
+    InvalidReferralCode,
+    #[msg("Referrer is already registered")]
+    ReferrerAlreadyRegistered,
+    #[msg("Referrer is already set")]
+    ReferrerAlreadySet,
+    #[msg("Can not refer yourself")]
+    SelfReferral,
+    #[msg("Users with referees can not be referred")]
+    ReferralCycle,
+    #[msg("Wrong referrer account")]
+    WrongReferrer,
+    #[msg("Invalid score")]
+    InvalidScore,
+    #[msg("Only solved inferences can be rated")]
+    InferenceNotSolved,
+    #[msg("Inference is already rated")]
+    AlreadyRated,
+    #[msg("Missing DAO token accounts")]
+    MissingDaoTokenAccounts,
+    #[msg("Model of the miner is not retired")]
+    ModelNotRetired,
+    #[msg("Account is already migrated")]
+    AlreadyMigrated,
+    #[msg("Account has an outdated layout")]
+    OutdatedLayout,
 }
@@ -1,12 +1,17 @@
+pub mod comparator;
 pub mod errors;
+pub mod selection;
 pub mod state;
 pub mod state_inf;
 mod utils;
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::compute_units::sol_remaining_compute_units;
 use anchor_lang::solana_program::keccak::hash;
 use anchor_spl::token::{self, transfer_checked, Transfer, TransferChecked};
+use comparator::*;
 use errors::*;
+use selection::*;
 use state::*;
 use state_inf::*;
 use utils::*;
@@ -77,6 +82,7 @@ pub mod solearn {
         sol_learn_account.dao_token_percentage.referee_percentage = referee_percentage;
         sol_learn_account.dao_token_percentage.l2_owner_percentage = l2_owner_percentage;
         sol_learn_account.unstake_delay_time = unstake_delay_time;
+        sol_learn_account.version = SOL_LEARN_INFO_VERSION;
 
         // vault account
         ctx.accounts.vault_wallet_owner_pda.bump = ctx.bumps.vault_wallet_owner_pda;
@@ -106,10 +112,13 @@ pub mod solearn {
 
         // set miner info
         let miner_account = &mut ctx.accounts.miner_account;
+        miner_account.miner = ctx.accounts.miner.key();
         miner_account.stake_amount = stake_amount;
         miner_account.bump = ctx.bumps.miner_account;
+        ctx.accounts.miner_stats.miner = ctx.accounts.miner.key();
+        ctx.accounts.miner_stats.bump = ctx.bumps.miner_stats;
 
-        if ctx.accounts.models.data.len() == 0 {
+        if ctx.accounts.models.count() == 0 {
             return Err(SolLearnError::NoModelRegistered.into());
         }
 
@@ -118,17 +127,15 @@ pub mod solearn {
         }
 
         // get random value
-        let model_index = random_number(
-            &&Clock::get()?,
-            0,
-            (ctx.accounts.models.data.len() / 32) as u64,
-        );
-        let model: Pubkey = ctx.accounts.models.data
-            [model_index as usize * 32..(model_index + 1) as usize * 32]
-            .try_into()
-            .expect("Invalid length");
+        let seed = selection_seed(
+            &ctx.accounts.recent_slothashes,
+            ctx.accounts.miner.key().as_ref(),
+        )?;
+        let model_index = random_number(&seed, 0, ctx.accounts.models.count() as u64);
+        let model = ctx.accounts.models.model_at(model_index as usize);
         miner_account.model = model;
         ctx.accounts.sol_learn_account.total_miner += 1;
+        ctx.accounts.sol_learn_account.total_stake += stake_amount;
 
         let cpi_accounts = Transfer {
             from: ctx.accounts.miner_staking_wallet.to_account_info(),
@@ -149,16 +156,64 @@ pub mod solearn {
         Ok(())
     }
 
+    // stats of miners registered before MinerStats existed, anyone can pay
+    // for the account
+    pub fn init_miner_stats(ctx: Context<InitMinerStatsVld>) -> Result<()> {
+        ctx.accounts.miner_stats.miner = ctx.accounts.miner_account.miner;
+        ctx.accounts.miner_stats.bump = ctx.bumps.miner_stats;
+
+        Ok(())
+    }
+
+    // Rewrites a list of miners of the layout before selection weights, each
+    // miner weighted from its MinerInfo as join_for_minting would; anyone can
+    // pay for the extra space.
+    pub fn migrate_miners_of_model<'info>(
+        ctx: Context<'_, '_, '_, 'info, MigrateMinersOfModelVld<'info>>,
+        model: Pubkey,
+    ) -> Result<()> {
+        let info = ctx.accounts.miners_of_model.to_account_info();
+        let (bump, miners) = MinersOfModel::legacy_miners(&info.try_borrow_data()?)?;
+        if ctx.remaining_accounts.len() != miners.len() {
+            return Err(SolLearnError::Unauthorized.into());
+        }
+
+        let sol_learn = ctx.accounts.sol_learn_account.key();
+        let quality_weight = ctx.accounts.sol_learn_account.quality_weight;
+        let mut list = MinersOfModel {
+            bump,
+            version: MINERS_OF_MODEL_VERSION,
+            data: vec![],
+        };
+        for (miner, miner_info) in miners.iter().zip(ctx.remaining_accounts) {
+            let miner_account = load_miner(miner_info, &sol_learn, ctx.program_id)?;
+            if miner_account.miner != *miner || miner_account.model != model {
+                return Err(SolLearnError::Unauthorized.into());
+            }
+            list.push(miner, miner_account.selection_weight(quality_weight));
+        }
+
+        grow_account(
+            info.clone(),
+            8 + MinersOfModel::LEN + list.data.len(),
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+        store_account(&info, &list)?;
+        msg!("migrated {} miners of model {:?}", miners.len(), model);
+
+        Ok(())
+    }
+
     pub fn join_for_minting(ctx: Context<JoinForMinting>) -> Result<()> {
         msg!("Instruction: Join For Minting");
 
-        // update epoch section
-        let n = ((ctx.accounts.sysvar_clock.unix_timestamp as u64)
-            - ctx.accounts.sol_learn_account.last_time)
-            / ctx.accounts.sol_learn_account.epoch_duration;
-        if n > 0 {
-            ctx.accounts.sol_learn_account.last_time = ctx.accounts.sol_learn_account.last_time + n * ctx.accounts.sol_learn_account.epoch_duration;
-            ctx.accounts.sol_learn_account.last_epoch += n;
+        only_updated_epoch(&ctx.accounts.sol_learn_account)?;
+
+        // handle case miner cancle unstaking
+        if ctx.accounts.miner_account.unstaking_time > 0 {
+            ctx.accounts.miner_account.cancel_unstakes();
+            ctx.accounts.miner_account.unstaking_time = 0;
         }
 
         if ctx.accounts.sol_learn_account.miner_minimum_stake
@@ -180,18 +235,23 @@ pub mod solearn {
 
         // insert model address
         let miners_of_model: &mut Account<'_, MinersOfModel> = &mut ctx.accounts.miners_of_model;
-        miners_of_model
-            .data
-            .extend_from_slice(ctx.accounts.miner.key().as_ref());
-
-        // update miner join epoch time
-        ctx.accounts.miner_account.last_epoch = ctx.accounts.sol_learn_account.last_epoch;
-        ctx.accounts.miner_account.is_active = true;
+        miners_of_model.push(
+            &ctx.accounts.miner.key(),
+            ctx.accounts.miner_account.selection_weight(ctx.accounts.sol_learn_account.quality_weight),
+        );
 
-        // handle case miner cancle unstaking
-        if ctx.accounts.miner_account.unstaking_time > 0 {
-            ctx.accounts.miner_account.unstaking_time = 0;
+        // update miner join epoch time, rewards of a previous run must be claimed first
+        skip_idle_epochs(
+            &mut ctx.accounts.miner_account,
+            ctx.accounts.sol_learn_account.last_epoch,
+        );
+        if ctx.accounts.miner_account.last_epoch != ctx.accounts.sol_learn_account.last_epoch {
+            return Err(SolLearnError::MustClaimReward.into());
         }
+        activate_miner(
+            &mut ctx.accounts.sol_learn_account,
+            &mut ctx.accounts.miner_account,
+        );
 
         emit!(MinerJoin {
             miner: *ctx.accounts.miner.key,
@@ -210,6 +270,16 @@ pub mod solearn {
 
         let miner_account = &mut ctx.accounts.miner_account;
         miner_account.stake_amount += topup_amount;
+        ctx.accounts.sol_learn_account.total_stake += topup_amount;
+
+        // keep the stake used for weighted selection in sync
+        let quality_weight = ctx.accounts.sol_learn_account.quality_weight;
+        if miner_account.is_active {
+            let miners_of_model = &mut ctx.accounts.miners_of_model;
+            if let Some(index) = miners_of_model.position(&ctx.accounts.miner.key()) {
+                miners_of_model.set_stake(index, miner_account.selection_weight(quality_weight));
+            }
+        }
 
         let cpi_accounts = Transfer {
             from: ctx.accounts.miner_staking_wallet.to_account_info(),
@@ -233,14 +303,7 @@ pub mod solearn {
     pub fn miner_unstake(ctx: Context<MinerUnStaking>, model_index: u64) -> Result<()> {
         msg!("Instruction: Miner unstake");
 
-        // update epoch section
-        let n = ((ctx.accounts.sysvar_clock.unix_timestamp as u64)
-            - ctx.accounts.sol_learn_account.last_time)
-            / ctx.accounts.sol_learn_account.epoch_duration;
-        if n > 0 {
-            ctx.accounts.sol_learn_account.last_time = ctx.accounts.sol_learn_account.last_time + n * ctx.accounts.sol_learn_account.epoch_duration;
-            ctx.accounts.sol_learn_account.last_epoch += n;
-        }
+        only_updated_epoch(&ctx.accounts.sol_learn_account)?;
 
         if ctx.accounts.miner_account.stake_amount == 0 {
             return Err(SolLearnError::StakingZeroValue.into());
@@ -250,38 +313,88 @@ pub mod solearn {
             return Err(SolLearnError::Unstaking.into());
         }
 
-        // update account unstaking time
-        ctx.accounts.miner_account.unstaking_time = (ctx.accounts.sysvar_clock.unix_timestamp
-            as u64)
+        // queue the whole stake, rejoining before the claim cancels it
+        let unlock_time = (ctx.accounts.sysvar_clock.unix_timestamp as u64)
             + ctx.accounts.sol_learn_account.unstake_delay_time;
+        let miner_account = &mut ctx.accounts.miner_account;
+        let amount = miner_account.stake_amount;
+        miner_account.push_unstake_request(amount, unlock_time)?;
+        miner_account.stake_amount = 0;
+        miner_account.unstaking_time = unlock_time;
 
         if ctx.accounts.miner_account.is_active {
-            ctx.accounts.miner_account.reward += (ctx.accounts.sol_learn_account.last_epoch
-                - ctx.accounts.miner_account.last_epoch)
-                * ctx.accounts.sol_learn_account.reward_per_epoch;
-            ctx.accounts.miner_account.last_epoch = ctx.accounts.sol_learn_account.last_epoch;
-
             // remove from MinersOfModel
             let miner_key = ctx.accounts.miner.key();
-            let mut data = ctx.accounts.miners_of_model.data.clone();
+            let miners_of_model = &mut ctx.accounts.miners_of_model;
 
-            if (data.len() as u64) < 32 * (model_index + 1) {
+            if (miners_of_model.count() as u64) <= model_index {
                 return Err(SolLearnError::InvalidModelIndex.into());
             }
 
-            let miner_key_extracted: Vec<u8> =
-                data[(model_index as usize) * 32..(model_index as usize + 1) * 32].to_vec();
-            if miner_key_extracted == miner_key.try_to_vec()? {
-                data.drain((model_index as usize) * 32..(model_index as usize + 1) * 32);
-
-                // Update the account data
-                ctx.accounts.miners_of_model.data = data;
-                ctx.accounts.miner_account.is_active = false;
+            if miners_of_model.miner_at(model_index as usize) == miner_key {
+                miners_of_model.remove(model_index as usize);
+                deactivate_miner(
+                    &mut ctx.accounts.sol_learn_account,
+                    &mut ctx.accounts.miner_account,
+                );
             } else {
                 return Err(SolLearnError::MinerNotRegistered.into());
             }
         }
 
+        emit!(MinerUnstakeRequested {
+            miner: ctx.accounts.miner.key(),
+            amount,
+            unlock_time,
+        });
+
+        Ok(())
+    }
+
+    // withdraw part of the stake after the unstake delay, the miner stays
+    // active as long as the remaining stake covers the minimum stake
+    pub fn unstake(ctx: Context<MinerUnStaking>, amount: u64) -> Result<()> {
+        msg!("Instruction: Miner partial unstake");
+
+        only_updated_epoch(&ctx.accounts.sol_learn_account)?;
+
+        if amount == 0 {
+            return Err(SolLearnError::StakingZeroValue.into());
+        }
+        let miner_account = &mut ctx.accounts.miner_account;
+        if amount > miner_account.stake_amount {
+            return Err(SolLearnError::InsufficientFunds.into());
+        }
+        let remaining = miner_account.stake_amount - amount;
+        if remaining > 0 && remaining < ctx.accounts.sol_learn_account.miner_minimum_stake {
+            return Err(SolLearnError::StakeBelowMinimum.into());
+        }
+
+        let unlock_time = (ctx.accounts.sysvar_clock.unix_timestamp as u64)
+            + ctx.accounts.sol_learn_account.unstake_delay_time;
+        miner_account.push_unstake_request(amount, unlock_time)?;
+        miner_account.stake_amount = remaining;
+
+        let quality_weight = ctx.accounts.sol_learn_account.quality_weight;
+        if miner_account.is_active {
+            let miner_key = ctx.accounts.miner.key();
+            let miners_of_model = &mut ctx.accounts.miners_of_model;
+            if let Some(index) = miners_of_model.position(&miner_key) {
+                if remaining == 0 {
+                    miners_of_model.remove(index);
+                    deactivate_miner(&mut ctx.accounts.sol_learn_account, miner_account);
+                } else {
+                    miners_of_model.set_stake(index, miner_account.selection_weight(quality_weight));
+                }
+            }
+        }
+
+        emit!(MinerUnstakeRequested {
+            miner: ctx.accounts.miner.key(),
+            amount,
+            unlock_time,
+        });
+
         Ok(())
     }
 
@@ -291,23 +404,28 @@ pub mod solearn {
             return Err(SolLearnError::InvalidToken.into());
         }
 
-        if ctx.accounts.miner_account.is_active {
-            return Err(SolLearnError::Activated.into());
+        // release only the requests whose delay has passed
+        let miner_account = &mut ctx.accounts.miner_account;
+        if miner_account.pending_unstake() == 0 {
+            return Err(SolLearnError::NothingToClaim.into());
         }
-
-        if ctx.accounts.miner_account.unstaking_time == 0
-            || ctx.accounts.miner_account.unstaking_time
-                > (ctx.accounts.sysvar_clock.unix_timestamp as u64)
-        {
+        let unstake_amount =
+            miner_account.take_matured_unstakes(ctx.accounts.sysvar_clock.unix_timestamp as u64);
+        if unstake_amount == 0 {
             return Err(SolLearnError::CanNotClaim.into());
         }
-
-        let unstake_amount = ctx.accounts.miner_account.stake_amount;
-        if unstake_amount == 0 {
-            return Err(SolLearnError::NothingToClaim.into());
+        if miner_account.pending_unstake() == 0 {
+            miner_account.unstaking_time = 0;
         }
-        ctx.accounts.miner_account.stake_amount = 0;
-        ctx.accounts.miner_account.unstaking_time = 0;
+
+        let acc = &mut ctx.accounts.sol_learn_account;
+        only_current_version(acc)?;
+        check_solvency(
+            ctx.accounts.vault_staking_wallet.amount,
+            unstake_amount,
+            acc.total_stake - unstake_amount + acc.total_escrow,
+        )?;
+        acc.total_stake -= unstake_amount;
 
         // this used for unstaking
         let decimals = ctx.accounts.staking_token.decimals;
@@ -336,6 +454,160 @@ pub mod solearn {
 
         transfer_checked(ctx_transfer_token, unstake_amount, decimals)?;
 
+        emit!(MinerUnstakeClaimed {
+            miner: ctx.accounts.miner.key(),
+            amount: unstake_amount,
+        });
+
+        Ok(())
+    }
+
+    // share of the delegators' rewards kept by the miner
+    pub fn set_commission(ctx: Context<SetCommission>, commission: u16) -> Result<()> {
+        if commission as u64 > PERCENTAGE_DENOMINATOR {
+            return Err(SolLearnError::InvalidCommission.into());
+        }
+        ctx.accounts.miner_account.commission = commission;
+
+        emit!(CommissionUpdated {
+            miner: ctx.accounts.miner.key(),
+            commission,
+        });
+
+        Ok(())
+    }
+
+    // back a miner with staking tokens, rewards and fines are shared pro rata
+    pub fn delegate(ctx: Context<Delegate>, amount: u64) -> Result<()> {
+        msg!("Instruction: Delegate");
+
+        if ctx.accounts.sol_learn_account.token != ctx.accounts.staking_token.key() {
+            return Err(SolLearnError::InvalidToken.into());
+        }
+        if amount == 0 {
+            return Err(SolLearnError::StakingZeroValue.into());
+        }
+
+        let miner_account = &mut ctx.accounts.miner_account;
+        let shares = miner_account.add_delegation(amount)?;
+        ctx.accounts.sol_learn_account.total_stake += amount;
+
+        let delegation = &mut ctx.accounts.delegation;
+        if delegation.delegator == Pubkey::default() {
+            delegation.bump = ctx.bumps.delegation;
+            delegation.delegator = ctx.accounts.delegator.key();
+            delegation.miner = miner_account.miner;
+        }
+        delegation.shares += shares;
+
+        let quality_weight = ctx.accounts.sol_learn_account.quality_weight;
+        if miner_account.is_active {
+            let miners_of_model = &mut ctx.accounts.miners_of_model;
+            if let Some(index) = miners_of_model.position(&miner_account.miner) {
+                miners_of_model.set_stake(index, miner_account.selection_weight(quality_weight));
+            }
+        }
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.delegator_wallet.to_account_info(),
+            to: ctx.accounts.vault_staking_wallet.to_account_info(),
+            authority: ctx.accounts.delegator.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, amount)?;
+
+        emit!(Delegated {
+            delegator: ctx.accounts.delegator.key(),
+            miner: ctx.accounts.miner_account.miner,
+            amount,
+            shares,
+        });
+
+        Ok(())
+    }
+
+    // queue shares for withdrawal, they stay exposed to fines until claimed
+    pub fn undelegate(ctx: Context<Undelegate>, shares: u64) -> Result<()> {
+        msg!("Instruction: Undelegate");
+
+        if shares == 0 {
+            return Err(SolLearnError::StakingZeroValue.into());
+        }
+        let delegation = &mut ctx.accounts.delegation;
+        if delegation.pending_shares() + shares > delegation.shares {
+            return Err(SolLearnError::InsufficientFunds.into());
+        }
+
+        let unlock_time = (ctx.accounts.sysvar_clock.unix_timestamp as u64)
+            + ctx.accounts.sol_learn_account.unstake_delay_time;
+        UnstakeRequest::push(&mut delegation.unstake_requests, shares, unlock_time)?;
+
+        emit!(UndelegationRequested {
+            delegator: ctx.accounts.delegator.key(),
+            miner: delegation.miner,
+            shares,
+            unlock_time,
+        });
+
+        Ok(())
+    }
+
+    // withdraw the undelegated shares past their delay at their current value
+    pub fn claim_undelegated(ctx: Context<ClaimUndelegated>) -> Result<()> {
+        if ctx.accounts.sol_learn_account.token != ctx.accounts.staking_token.key() {
+            return Err(SolLearnError::InvalidToken.into());
+        }
+
+        let delegation = &mut ctx.accounts.delegation;
+        if delegation.pending_shares() == 0 {
+            return Err(SolLearnError::NothingToClaim.into());
+        }
+        let now = ctx.accounts.sysvar_clock.unix_timestamp as u64;
+        let shares = UnstakeRequest::take_matured(&mut delegation.unstake_requests, now);
+        if shares == 0 {
+            return Err(SolLearnError::CanNotClaim.into());
+        }
+        delegation.shares -= shares;
+
+        let miner_account = &mut ctx.accounts.miner_account;
+        let amount = miner_account.remove_delegation(shares);
+        let quality_weight = ctx.accounts.sol_learn_account.quality_weight;
+        if miner_account.is_active {
+            let miners_of_model = &mut ctx.accounts.miners_of_model;
+            if let Some(index) = miners_of_model.position(&miner_account.miner) {
+                miners_of_model.set_stake(index, miner_account.selection_weight(quality_weight));
+            }
+        }
+
+        let acc = &mut ctx.accounts.sol_learn_account;
+        only_current_version(acc)?;
+        check_solvency(
+            ctx.accounts.vault_staking_wallet.amount,
+            amount,
+            acc.total_stake - amount + acc.total_escrow,
+        )?;
+        acc.total_stake -= amount;
+
+        if amount > 0 {
+            transfer_from_vault(
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.vault_staking_wallet.to_account_info(),
+                ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                ctx.accounts.delegator_wallet.to_account_info(),
+                &ctx.accounts.sol_learn_account.key(),
+                ctx.accounts.vault_wallet_owner_pda.bump,
+                amount,
+            )?;
+        }
+
+        emit!(UndelegationClaimed {
+            delegator: ctx.accounts.delegator.key(),
+            miner: ctx.accounts.miner_account.miner,
+            shares,
+            amount,
+        });
+
         Ok(())
     }
 
@@ -345,45 +617,64 @@ pub mod solearn {
             return Err(SolLearnError::InvalidToken.into());
         }
 
-        // update epoch section
-        let n = ((ctx.accounts.sysvar_clock.unix_timestamp as u64)
-            - ctx.accounts.sol_learn_account.last_time)
-            / ctx.accounts.sol_learn_account.epoch_duration;
-        if n > 0 {
-            ctx.accounts.sol_learn_account.last_time = ctx.accounts.sol_learn_account.last_time + n * ctx.accounts.sol_learn_account.epoch_duration;
-            ctx.accounts.sol_learn_account.last_epoch += n;
+        // sum the epoch snapshots passed in remaining accounts, oldest first
+        let current_epoch = ctx.accounts.sol_learn_account.last_epoch;
+        let miner_account = &mut ctx.accounts.miner_account;
+        skip_idle_epochs(miner_account, current_epoch);
+        let start_epoch = miner_account.last_epoch;
+
+        let mut reward = miner_account.reward;
+        for info in ctx.remaining_accounts.iter() {
+            if miner_account.last_epoch >= current_epoch {
+                break;
+            }
+
+            let snapshot = load_epoch_snapshot(info, ctx.program_id)?;
+            if snapshot.epoch_id != miner_account.last_epoch {
+                return Err(SolLearnError::InvalidEpochId.into());
+            }
+            reward += epoch_reward_of(miner_account, &snapshot);
+            miner_account.last_epoch += 1;
+            skip_idle_epochs(miner_account, current_epoch);
         }
+        miner_account.reward = 0;
 
-        let mut reward = 0;
-        if ctx.accounts.miner_account.is_active {
-            // udpate latest reward
-            reward = ctx.accounts.miner_account.reward
-                + (ctx.accounts.sol_learn_account.last_epoch
-                    - ctx.accounts.miner_account.last_epoch)
-                    * ctx.accounts.sol_learn_account.reward_per_epoch;
-            if reward == 0 {
+        emit!(MinerRewardClaimed {
+            miner: miner_account.miner,
+            reward,
+            last_epoch: miner_account.last_epoch,
+        });
+
+        if reward == 0 {
+            if miner_account.last_epoch == start_epoch {
                 return Err(SolLearnError::NothingToClaim.into());
             }
+            return Ok(());
         }
-        ctx.accounts.miner_account.reward = 0;
-        ctx.accounts.miner_account.last_epoch = ctx.accounts.sol_learn_account.last_epoch;
 
-        // this used for unstaking
+        // rewards are paid by the reward pool only, never out of stake or escrow
+        let acc = &mut ctx.accounts.sol_learn_account;
+        only_current_version(acc)?;
+        let unclaimed_rewards = acc.unclaimed_rewards.saturating_sub(reward);
+        check_solvency(ctx.accounts.reward_pool_wallet.amount, reward, unclaimed_rewards)?;
+        acc.unclaimed_rewards = unclaimed_rewards;
+        let miner_reward = credit_delegators(acc, &mut ctx.accounts.miner_account, reward);
+
         let decimals = ctx.accounts.staking_token.decimals;
-        let solean_key = ctx.accounts.sol_learn_account.key().clone();
+        let solean_key = ctx.accounts.sol_learn_account.key();
         let seeds = &[
-            "vault".as_bytes(),
+            "reward_pool".as_bytes(),
             solean_key.as_ref(),
-            &[ctx.accounts.vault_wallet_owner_pda.bump],
+            &[ctx.accounts.reward_pool_pda.bump],
         ];
 
         let signer_seeds = &[&seeds[..]];
 
-        // transfer token to contract
+        // transfer reward to miner
         let cpi_accounts = TransferChecked {
-            from: ctx.accounts.miner_staking_wallet.to_account_info(),
-            to: ctx.accounts.vault_staking_wallet.to_account_info(),
-            authority: ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+            from: ctx.accounts.reward_pool_wallet.to_account_info(),
+            to: ctx.accounts.miner_staking_wallet.to_account_info(),
+            authority: ctx.accounts.reward_pool_pda.to_account_info(),
             mint: ctx.accounts.staking_token.clone().to_account_info(),
         };
 
@@ -393,7 +684,118 @@ pub mod solearn {
             signer_seeds,
         );
 
-        transfer_checked(ctx_transfer_token, reward, decimals)?;
+        transfer_checked(ctx_transfer_token, miner_reward, decimals)?;
+
+        // the delegators' cut is staked in the vault
+        let delegators_reward = reward - miner_reward;
+        if delegators_reward > 0 {
+            let cpi_accounts = TransferChecked {
+                from: ctx.accounts.reward_pool_wallet.to_account_info(),
+                to: ctx.accounts.vault_staking_wallet.to_account_info(),
+                authority: ctx.accounts.reward_pool_pda.to_account_info(),
+                mint: ctx.accounts.staking_token.clone().to_account_info(),
+            };
+
+            let ctx_transfer_token = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                cpi_accounts,
+                signer_seeds,
+            );
+
+            transfer_checked(ctx_transfer_token, delegators_reward, decimals)?;
+        }
+
+        Ok(())
+    }
+
+    // close the current epoch: snapshot its miners and tasks so rewards can be
+    // claimed. Further elapsed epochs are closed too, one per snapshot account
+    // of the following epochs passed in remaining accounts.
+    pub fn update_epoch<'info>(
+        ctx: Context<'_, '_, '_, 'info, NewEpochVld<'info>>,
+        epoch_id: u64,
+    ) -> Result<()> {
+        msg!("Instruction: Update epoch");
+
+        let now = ctx.accounts.sysvar_clock.unix_timestamp as u64;
+        let pool_balance = ctx.accounts.reward_pool_wallet.amount;
+        let acc = &mut ctx.accounts.sol_learn_account;
+        only_current_version(acc)?;
+        if epoch_id != acc.last_epoch {
+            return Err(SolLearnError::InvalidEpochId.into());
+        }
+        if now < acc.last_time + acc.epoch_duration {
+            return Err(SolLearnError::EpochRewardUpToDate.into());
+        }
+
+        let snapshot = close_epoch(acc, pool_balance);
+        let miner_reward = &mut ctx.accounts.miner_reward;
+        miner_reward.set_inner(MinerEpochState {
+            bump: ctx.bumps.miner_reward,
+            ..snapshot
+        });
+        emit_epoch_updated(&snapshot);
+
+        for info in ctx.remaining_accounts.iter() {
+            if now < acc.last_time + acc.epoch_duration {
+                break;
+            }
+
+            let id_bytes = acc.last_epoch.to_le_bytes();
+            let (expected, bump) =
+                Pubkey::find_program_address(&[b"reward_in_epoch", id_bytes.as_ref()], ctx.program_id);
+            if expected != info.key() {
+                return Err(SolLearnError::InvalidEpochId.into());
+            }
+            create_pda_account(
+                ctx.accounts.system_program.to_account_info(),
+                ctx.accounts.signer.to_account_info(),
+                info.clone(),
+                &[b"reward_in_epoch", id_bytes.as_ref(), &[bump]],
+                ANCHOR_DISCRIMINATOR + MinerEpochState::INIT_SPACE,
+                ctx.program_id,
+            )?;
+            let snapshot = MinerEpochState {
+                bump,
+                ..close_epoch(acc, pool_balance)
+            };
+            store_account(info, &snapshot)?;
+            emit_epoch_updated(&snapshot);
+        }
+
+        Ok(())
+    }
+
+    pub fn init_reward_pool(ctx: Context<InitRewardPool>) -> Result<()> {
+        msg!("Instruction: Init reward pool");
+
+        ctx.accounts.reward_pool_pda.bump = ctx.bumps.reward_pool_pda;
+        msg!("reward pool PDA bump seed: {}", ctx.bumps.reward_pool_pda);
+
+        Ok(())
+    }
+
+    // anyone can top up the pool miner rewards are paid from
+    pub fn fund_reward_pool(ctx: Context<FundRewardPool>, amount: u64) -> Result<()> {
+        msg!("Instruction: Fund reward pool");
+
+        if amount == 0 {
+            return Err(SolLearnError::ZeroValue.into());
+        }
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.funder_wallet.to_account_info(),
+            to: ctx.accounts.reward_pool_wallet.to_account_info(),
+            authority: ctx.accounts.funder.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        token::transfer(cpi_ctx, amount)?;
+
+        emit!(RewardPoolFunded {
+            funder: ctx.accounts.funder.key(),
+            amount,
+        });
 
         Ok(())
     }
@@ -401,45 +803,206 @@ pub mod solearn {
     // ADMIN section
     // todos:
 
-    // add model
-    pub fn add_model(ctx: Context<AddModel>, model: Pubkey) -> Result<()> {
-        msg!("Instruction: Add model");
+    // create model
+    pub fn create_model(ctx: Context<CreateModel>, model: Pubkey, params: ModelParams) -> Result<()> {
+        msg!("Instruction: Create model");
 
-        let models = &mut ctx.accounts.models;
-        models.data.extend_from_slice(model.as_ref());
+        ctx.accounts.models.push(&model);
         ctx.accounts.miners_of_model.bump = ctx.bumps.miners_of_model;
+        ctx.accounts.miners_of_model.version = MINERS_OF_MODEL_VERSION;
         ctx.accounts.sol_learn_account.total_models += 1;
 
+        let model_info = &mut ctx.accounts.model_info;
+        model_info.set_params(&params)?;
+        model_info.bump = ctx.bumps.model_info;
+        model_info.model = model;
+        model_info.enabled = true;
+
+        emit!(NewModel {
+            model,
+            minimum_fee: params.minimum_fee,
+            tier: params.tier,
+            miner_requirement: params.miner_requirement,
+        });
+
         Ok(())
     }
 
-    // remove model
-    pub fn remove_model(ctx: Context<RemoveModel>, model: Pubkey) -> Result<()> {
-        msg!("Instruction: Add model");
+    // ModelInfo of a model added by the old add_model, which kept only its key
+    // in Models; the miners list of such a model is migrated on its own with
+    // migrate_miners_of_model
+    pub fn init_model_info(ctx: Context<InitModelInfoVld>, model: Pubkey, params: ModelParams) -> Result<()> {
+        msg!("Instruction: Init model info");
 
-        let mut data = ctx.accounts.models.data.clone();
-        // Find the index of the miner's key in the data
-        if let Some(index) = data.chunks(32).position(|chunk| chunk == model.as_ref()) {
-            // Remove the miner's key from the data
-            data.drain(index * 32..(index + 1) * 32);
+        if ctx.accounts.models.position(&model).is_none() {
+            return Err(SolLearnError::NoModelRegistered.into());
+        }
 
-            // Update the models data
-            ctx.accounts.models.data = data;
-        } else {
-            return Err(SolLearnError::ModelNotExist.into());
+        let model_info = &mut ctx.accounts.model_info;
+        model_info.set_params(&params)?;
+        model_info.bump = ctx.bumps.model_info;
+        model_info.model = model;
+        model_info.enabled = true;
+
+        emit!(ModelUpdated {
+            model,
+            minimum_fee: params.minimum_fee,
+            tier: params.tier,
+            miner_requirement: params.miner_requirement,
+            submit_duration: params.submit_duration,
+            commit_duration: params.commit_duration,
+            reveal_duration: params.reveal_duration,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_model(ctx: Context<UpdateModel>, model: Pubkey, params: ModelParams) -> Result<()> {
+        msg!("Instruction: Update model");
+
+        ctx.accounts.model_info.set_params(&params)?;
+
+        emit!(ModelUpdated {
+            model,
+            minimum_fee: params.minimum_fee,
+            tier: params.tier,
+            miner_requirement: params.miner_requirement,
+            submit_duration: params.submit_duration,
+            commit_duration: params.commit_duration,
+            reveal_duration: params.reveal_duration,
+        });
+
+        Ok(())
+    }
+
+    // pause / unpause inference requests for a model
+    pub fn set_model_enabled(ctx: Context<UpdateModel>, model: Pubkey, enabled: bool) -> Result<()> {
+        msg!("Instruction: Set model enabled");
+
+        ctx.accounts.model_info.enabled = enabled;
+        emit!(ModelEnabledUpdated { model, enabled });
+
+        Ok(())
+    }
+
+    // how validators' outputs are compared, see comparator.rs; applies to
+    // inferences created afterwards
+    pub fn set_model_comparator(
+        ctx: Context<UpdateModel>,
+        model: Pubkey,
+        comparator: u8,
+        tolerance: u64,
+    ) -> Result<()> {
+        msg!("Instruction: Set model comparator");
+
+        validate_comparator(comparator)?;
+        ctx.accounts.model_info.comparator = comparator;
+        ctx.accounts.model_info.tolerance = tolerance;
+        emit!(ModelComparatorUpdated {
+            model,
+            comparator,
+            tolerance,
+        });
+
+        Ok(())
+    }
+
+    // retire model, inactive miners still on it are moved off by reassign_model
+    pub fn retire_model(ctx: Context<RetireModel>, model: Pubkey) -> Result<()> {
+        msg!("Instruction: Retire model");
+
+        if ctx.accounts.miners_of_model.count() > 0 {
+            return Err(SolLearnError::ModelHasMiners.into());
+        }
+
+        match ctx.accounts.models.position(&model) {
+            Some(index) => ctx.accounts.models.remove(index),
+            None => return Err(SolLearnError::ModelNotExist.into()),
         }
 
         ctx.accounts.sol_learn_account.total_models -= 1;
+        emit!(ModelRetired { model });
+
+        Ok(())
+    }
+
+    // Moves a miner whose model was retired to a random registered model, as
+    // miner_register does. Anyone can call it: until then the miner cannot
+    // join, unstake or be undelegated from, their accounts are seeded by the
+    // closed MinersOfModel.
+    pub fn reassign_model(ctx: Context<ReassignModel>) -> Result<()> {
+        msg!("Instruction: Reassign model");
+
+        let miner_account = &mut ctx.accounts.miner_account;
+        let models = &ctx.accounts.models;
+        if models.position(&miner_account.model).is_some() {
+            return Err(SolLearnError::ModelNotRetired.into());
+        }
+        if models.count() == 0 {
+            return Err(SolLearnError::NoModelRegistered.into());
+        }
+
+        let seed = selection_seed(
+            &ctx.accounts.recent_slothashes,
+            miner_account.miner.as_ref(),
+        )?;
+        let model_index = random_number(&seed, 0, models.count() as u64);
+        let old_model = miner_account.model;
+        miner_account.model = models.model_at(model_index as usize);
+
+        emit!(MinerModelReassigned {
+            miner: miner_account.miner,
+            old_model,
+            model: miner_account.model,
+        });
 
         Ok(())
     }
 
-    // epoch update
     // set fine percentage
     // setPenaltyDuration
     // setMinFeeToUse
     // setNewRewardInEpoch
 
+    pub fn set_selection_mode(ctx: Context<UpdateParamsVld>, selection_mode: u8) -> Result<()> {
+        if selection_mode != SELECTION_UNIFORM && selection_mode != SELECTION_STAKE_WEIGHTED {
+            return Err(SolLearnError::InvalidSelectionMode.into());
+        }
+
+        let acc = &mut ctx.accounts.sol_learn_account;
+        acc.selection_mode = selection_mode;
+        emit!(SelectionModeUpdated {
+            new_selection_mode: selection_mode,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_perf_reward_percentage(
+        ctx: Context<UpdateParamsVld>,
+        perf_reward_percentage: u16,
+    ) -> Result<()> {
+        let acc = &mut ctx.accounts.sol_learn_account;
+
+        acc.perf_reward_percentage = perf_reward_percentage;
+        emit!(PerfRewardPercentageUpdated {
+            new_perf_reward_percentage: perf_reward_percentage,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_crank_bounty(ctx: Context<UpdateParamsVld>, crank_bounty: u64) -> Result<()> {
+        let acc = &mut ctx.accounts.sol_learn_account;
+
+        acc.crank_bounty = crank_bounty;
+        emit!(CrankBountyUpdated {
+            new_crank_bounty: crank_bounty,
+        });
+
+        Ok(())
+    }
+
     pub fn set_miner_min_stake(ctx: Context<UpdateParamsVld>, data: u64) -> Result<()> {
         let acc = &mut ctx.accounts.sol_learn_account;
         acc.miner_minimum_stake = data.into();
@@ -465,7 +1028,11 @@ pub mod solearn {
         msg!("Instruction: Get task count");
 
         let t = &ctx.accounts.tasks;
-        Ok((t.values.len() as u64) / 50)
+        Ok(t.pending()?.len() as u64)
+    }
+
+    pub fn get_miner_stats(ctx: Context<ReadMinerStatsVld>, _miner: Pubkey) -> Result<MinerStats> {
+        Ok((**ctx.accounts.miner_stats).clone())
     }
 
     pub fn get_assignment(
@@ -484,48 +1051,141 @@ pub mod solearn {
             "role" => Ok(vec![asgnmt.role]),
             "vote" => Ok(vec![asgnmt.vote]),
             "reveal_nonce" => Ok(asgnmt.reveal_nonce.to_le_bytes().to_vec()),
-            "output" => Ok(asgnmt.output.to_vec()),
+            "output_uri" => Ok(asgnmt.output.uri.as_bytes().to_vec()),
+            "output_hash" => Ok(asgnmt.output.keccak.to_vec()),
+            "output_data" => Ok(asgnmt.output_data.to_bytes().to_vec()),
             "commitment" => Ok(asgnmt.commitment.to_vec()),
             "digest" => Ok(asgnmt.digest.to_vec()),
             _ => Err(SolLearnError::UnknownStructField.into()),
         }
     }
 
-    pub fn infer(
-        ctx: Context<InferVld>,
+    // Allocates a ChunkedData of `len` bytes for an input or an output too
+    // large for a single transaction.
+    pub fn create_data(ctx: Context<CreateDataVld>, data_id: u64, len: u32) -> Result<()> {
+        if len == 0 || len as usize > MAX_DATA_LEN {
+            return Err(SolLearnError::InvalidDataLength.into());
+        }
+        let data = &mut ctx.accounts.data;
+        data.bump = ctx.bumps.data;
+        data.owner = ctx.accounts.signer.key();
+        data.data_id = data_id;
+        data.data = vec![0; len as usize];
+
+        Ok(())
+    }
+
+    pub fn write_data(
+        ctx: Context<WriteDataVld>,
+        _data_id: u64,
+        offset: u32,
+        chunk: Vec<u8>,
+    ) -> Result<()> {
+        let data = &mut ctx.accounts.data;
+        let start = offset as usize;
+        let end = start + chunk.len();
+        if end > data.data.len() {
+            return Err(SolLearnError::InvalidDataRange.into());
+        }
+        data.data[start..end].copy_from_slice(&chunk);
+
+        Ok(())
+    }
+
+    // Seals the data once fully written, `hash` is the keccak of the content
+    // that infer / submit_solution / reveal are then given.
+    pub fn finalize_data(ctx: Context<WriteDataVld>, data_id: u64, hash: [u8; 32]) -> Result<()> {
+        let data = &mut ctx.accounts.data;
+        if anchor_lang::solana_program::keccak::hash(&data.data).to_bytes() != hash {
+            return Err(SolLearnError::DataHashMismatch.into());
+        }
+        data.hash = hash;
+        data.finalized = true;
+
+        emit!(DataFinalized {
+            owner: data.owner,
+            data_id,
+            hash,
+            len: data.data.len() as u32,
+        });
+
+        Ok(())
+    }
+
+    pub fn register_referrer(ctx: Context<RegisterReferrerVld>, code: String) -> Result<()> {
+        validate_referral_code(&code)?;
+        let signer = ctx.accounts.signer.key();
+        let referrer = &mut ctx.accounts.referrer;
+        if !referrer.code.is_empty() {
+            return Err(SolLearnError::ReferrerAlreadyRegistered.into());
+        }
+        referrer.bump = ctx.bumps.referrer;
+        referrer.user = signer;
+        referrer.code = code.clone();
+
+        let referral_code = &mut ctx.accounts.referral_code;
+        referral_code.bump = ctx.bumps.referral_code;
+        referral_code.referrer = signer;
+
+        emit!(ReferrerRegistered {
+            referrer: signer,
+            code,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_referrer(ctx: Context<SetReferrerVld>, code: String) -> Result<()> {
+        validate_referral_code(&code)?;
+        let signer = ctx.accounts.signer.key();
+        let referrer_key = ctx.accounts.referral_code.referrer;
+        if referrer_key == signer {
+            return Err(SolLearnError::SelfReferral.into());
+        }
+        let referee = &mut ctx.accounts.referee;
+        if referee.pubkey != Pubkey::default() {
+            return Err(SolLearnError::ReferrerAlreadySet.into());
+        }
+        // a user with referees of its own could close a cycle
+        if referee.referee_count > 0 {
+            return Err(SolLearnError::ReferralCycle.into());
+        }
+        referee.bump = ctx.bumps.referee;
+        referee.user = signer;
+        referee.pubkey = referrer_key;
+        ctx.accounts.referrer.referee_count += 1;
+
+        emit!(ReferrerSet {
+            user: signer,
+            referrer: referrer_key,
+        });
+
+        Ok(())
+    }
+
+    pub fn infer<'info>(
+        ctx: Context<'_, '_, '_, 'info, InferVld<'info>>,
         inference_id: u64,
         creator: Pubkey,
-        input: Vec<u8>,
+        input: DataRef,
         _value: u64,
         model: Pubkey,
     ) -> Result<u64> {
         let acc = &mut ctx.accounts.sol_learn_account;
-        let mdls = &mut ctx.accounts.models;
+        let model_info = &ctx.accounts.model_info;
         let miners_of_model = &mut ctx.accounts.miners_of_model;
-        // let referrer = &mut ctx.accounts.referrer;
-        // referrer.bump = ctx.bumps.referrer;
 
-        msg!("miner len {}", miners_of_model.data.len());
+        msg!("miner len {}", miners_of_model.count());
         // if model.tier == 0 {
         //     return Err(SolLearnError::Unauthorized.into());
         // }
 
-        // find model
-        for i in 0..mdls.data.len() / 32 {
-            let b: [u8; 32] = mdls.data[i * 32..(i + 1) * 32].try_into().unwrap();
-            let model_pubkey = Pubkey::new_from_array(b);
-            if model_pubkey == model {
-                break;
-            }
-            if i == mdls.data.len() / 32 - 1 {
-                return Err(SolLearnError::ModelNotExist.into());
-            }
-        }
-
-        // let b: [u8; 32] = model.data[0..32].try_into().unwrap();
-        // let model_pubkey = Pubkey::new_from_array(b);
+        if !model_info.enabled {
+            return Err(SolLearnError::ModelPaused.into());
+        }
+        let (input_data, _) = read_data_ref(&input, &ctx.accounts.input_data, None)?;
 
-        let scoring_fee = validate_enough_fee_to_use(acc.min_fee_to_use, _value)?;
+        let scoring_fee = validate_enough_fee_to_use(model_info.minimum_fee, _value)?;
         // let from = ctx.accounts.signer.to_account_info();
         // let to = ctx.accounts.vault_wallet_owner_pda.to_account_info();
         // if **from.try_borrow_lamports()? < _value {
@@ -542,81 +1202,99 @@ pub mod solearn {
         }
         let inference = &mut ctx.accounts.infs;
 
-        let fee_l2 = (value * u64::from(acc.fee_l2_percentage)) / PERCENTAGE_DENOMINATOR;
+        // the crank bounty is set aside before the fees are split
+        let crank_bounty = acc.crank_bounty.min(value);
+        let fee_value = value - crank_bounty;
+        let fee_l2 = (fee_value * u64::from(acc.fee_l2_percentage)) / PERCENTAGE_DENOMINATOR;
         let fee_treasury =
-            (value * u64::from(acc.fee_treasury_percentage)) / PERCENTAGE_DENOMINATOR;
+            (fee_value * u64::from(acc.fee_treasury_percentage)) / PERCENTAGE_DENOMINATOR;
 
         inference.id = inference_id;
         inference.input = input;
+        inference.input_data = input_data;
         inference.fee_l2 = fee_l2;
         inference.fee_treasury = fee_treasury;
-        inference.value = value - fee_l2 - fee_treasury;
+        inference.value = fee_value - fee_l2 - fee_treasury;
+        inference.crank_bounty = crank_bounty;
         inference.creator = creator;
-        // inference.referrer = referrer.pubkey;
+        acc.total_escrow += value;
+        // a user with a referrer can not leave it out, the referee's DAO share
+        // goes to the creator wallet's owner
+        if ctx.accounts.referee.owner == ctx.program_id {
+            let referee = load_referrer(&ctx.accounts.referee, ctx.program_id)?;
+            if referee.pubkey != Pubkey::default() {
+                let referrer = match ctx.accounts.referrer.as_mut() {
+                    Some(referrer) if referrer.user == referee.pubkey => referrer,
+                    _ => return Err(SolLearnError::WrongReferrer.into()),
+                };
+                referrer.referred_inferences += 1;
+                referrer.referred_volume += _value;
+                inference.referrer = referee.pubkey;
+            }
+        }
         inference.model_address = model;
         inference.bump = ctx.bumps.infs;
+        ctx.accounts.dao_receiver_infos.bump = ctx.bumps.dao_receiver_infos;
 
         let slot_number = Clock::get()?.slot;
-        let expired_at = slot_number + acc.submit_duration;
-        let commit_timeout = expired_at + acc.commit_duration;
+        let expired_at = slot_number + model_info.submit_duration;
+        let commit_timeout = expired_at + model_info.commit_duration;
         inference.submit_timeout = expired_at;
         inference.commit_timeout = commit_timeout;
-        inference.reveal_timeout = commit_timeout + acc.reveal_duration;
+        inference.reveal_timeout = commit_timeout + model_info.reveal_duration;
         inference.status = 1;
-        inference.assignments = vec![];
+        let mut list = AssignmentList::default();
+        ctx.accounts.voting_info.comparator = model_info.comparator;
+        ctx.accounts.voting_info.tolerance = model_info.tolerance;
 
-        let n = acc.miner_requirement;
-        let mut selected_miners = Vec::with_capacity(n as usize);
+        let n = model_info.miner_requirement;
         msg!("before init tasks, n: {}", n);
 
         let tasks = &mut ctx.accounts.tasks;
         msg!("tasks len: {}", tasks.values.len());
         msg!("bump: {}", tasks.bump);
 
-        for i in 0..n {
-            let rand_uint = random_number(
-                &&Clock::get()?,
-                i.into(),
-                (miners_of_model.data.len() / 32) as u64,
-            );
-
-            let miner_ind = (rand_uint as usize) % (miners_of_model.data.len() / 32);
-            msg!(
-                "random number {} and miner #{} was chosen",
-                rand_uint,
-                miner_ind
-            );
-
-            let miner_bytes = miners_of_model
-                .data
-                .drain(miner_ind * 32..(miner_ind + 1) * 32)
-                .collect::<Vec<u8>>();
-            let miner = Pubkey::new_from_array(miner_bytes.try_into().unwrap());
-            msg!("miner chosen: {:?}", miner);
+        let seed = selection_seed(&ctx.accounts.recent_slothashes, &inference_id.to_le_bytes())?;
+        let chosen = select_candidates(
+            &seed,
+            &miners_of_model.stakes(),
+            n as usize,
+            acc.selection_mode,
+        )?;
+        for miner_ind in chosen {
+            let miner = miners_of_model.miner_at(miner_ind);
+            msg!("miner #{} was chosen: {:?}", miner_ind, miner);
 
             acc.assignment_number += 1;
             let assignment_id = acc.assignment_number;
 
-            let mut data = vec![];
-            data.extend_from_slice(&assignment_id.to_le_bytes());
-            data.extend_from_slice(&inference_id.to_le_bytes());
-            data.extend_from_slice(&miner.to_bytes());
-            data.push(1);
-
-            tasks.push_task(Task::new(FnType::CreateAssignment, data.clone()));
-            msg!("push task: {:?}", data);
+            let task = Task::CreateAssignment {
+                assignment_id,
+                inference_id,
+                worker: miner,
+                role: 1,
+            };
+            msg!("push task: {:?}", task);
+            tasks.push_task(task);
 
-            selected_miners.push(miner);
-            inference.assignments.push(assignment_id);
+            list.push(assignment_id, [0; 32]);
             // assignments_by_miner[miner].insert(assignment_id);
             // assignments_by_inference[inference_id].insert(assignment_id);
         }
-
-        for miner in selected_miners {
-            miners_of_model
-                .data
-                .extend_from_slice(miner.to_bytes().as_ref());
-        }
+        inference.assignment_count = list.ids.len() as u16;
+        store_assignment_list(
+            ctx.remaining_accounts,
+            inference_id,
+            &list,
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+            ctx.program_id,
+        )?;
+        fit_tasks(
+            &ctx.accounts.tasks,
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        )?;
 
         let cpi_accounts = Transfer {
             from: ctx.accounts.miner_staking_wallet.to_account_info(),
@@ -633,6 +1311,7 @@ pub mod solearn {
             creator,
             model_address: model,
             value,
+            seed,
         });
 
         Ok(0)
@@ -640,28 +1319,19 @@ pub mod solearn {
 
     pub fn create_assignment(ctx: Context<CreateAssignmentVld>, assignment_id: u64) -> Result<()> {
         let tasks = &mut ctx.accounts.tasks;
-        let task;
-        match tasks.receive_task() {
-            Some(t) => task = t,
-            None => return Err(SolLearnError::NoValidTask.into()),
-        }
-        if task.fn_type() != FnType::CreateAssignment {
-            return Err(SolLearnError::NoValidTask.into());
-        }
-        let data = task.data();
-        let mut assignment_id_bytes = [0u8; 8];
-        assignment_id_bytes.copy_from_slice(&data[0..8]);
-        let check_assignment_id = u64::from_le_bytes(assignment_id_bytes);
+        let (check_assignment_id, inference_id, worker, role) = match tasks.receive_task()? {
+            Task::CreateAssignment {
+                assignment_id,
+                inference_id,
+                worker,
+                role,
+            } => (assignment_id, inference_id, worker, role),
+            _ => return Err(SolLearnError::NoValidTask.into()),
+        };
         if check_assignment_id != assignment_id {
             return Err(SolLearnError::WrongAssignmentId.into());
         }
 
-        let mut inference_id_bytes = [0u8; 8];
-        inference_id_bytes.copy_from_slice(&data[8..16]);
-        let inference_id = u64::from_le_bytes(inference_id_bytes);
-        let worker = Pubkey::try_from(&data[16..48]).unwrap();
-        let role = data[48];
-
         let assignment = &mut ctx.accounts.assignment;
         assignment.inference_id = inference_id;
         assignment.worker = worker;
@@ -700,6 +1370,7 @@ pub mod solearn {
         }
 
         inference.value += value;
+        ctx.accounts.sol_learn_account.total_escrow += value;
 
         let cpi_accounts = Transfer {
             from: ctx.accounts.miner_staking_wallet.to_account_info(),
@@ -748,6 +1419,11 @@ pub mod solearn {
 
         assignment.role = 2;
         inference.processed_miner = ctx.accounts.signer.key();
+        let stats = &mut ctx.accounts.miner_stats;
+        stats.seized += 1;
+        stats.last_active_slot = Clock::get()?.slot;
+
+        inference.mining_assignment = assignment_id;
 
         emit!(MinerRoleSeized {
             assignment_id,
@@ -758,11 +1434,11 @@ pub mod solearn {
         Ok(())
     }
 
-    pub fn submit_solution(
-        ctx: Context<SeizeMinerRoleVld>,
+    pub fn submit_solution<'info>(
+        ctx: Context<'_, '_, '_, 'info, SeizeMinerRoleVld<'info>>,
         assignment_id: u64,
         inference_id: u64,
-        data: Vec<u8>,
+        output: DataRef,
     ) -> Result<()> {
         let acc = &mut ctx.accounts.sol_learn_account;
         // only_updated_epoch(acc)?;
@@ -785,7 +1461,7 @@ pub mod solearn {
             msg!("role: {}", assignment.role);
             return Err(SolLearnError::Unauthorized.into());
         }
-        if !assignment.output.is_empty() {
+        if assignment.output.keccak != [0; 32] {
             msg!("output: {:?}", assignment.output);
             return Err(SolLearnError::Unauthorized.into());
         }
@@ -794,16 +1470,25 @@ pub mod solearn {
             return Err(SolLearnError::Unauthorized.into());
         }
 
-        let mut concatenated: Vec<u8> = infer_id.to_le_bytes().to_vec();
-        concatenated.extend(data.clone());
-        let digest = hash(&mut concatenated);
-        assignment.digest = digest.to_bytes();
-        assignment.commitment = digest.to_bytes();
-        assignment.output = data.clone();
+        let (output_data, content) =
+            read_data_ref(&output, &ctx.accounts.output_data, Some(assignment.worker))?;
+        let digest = exact_digest(infer_id, &output.keccak);
+        assignment.digest = digest;
+        assignment.commitment = digest;
+        assignment.output_data = output_data;
 
         inference.status = 2;
         // inference.assignments.push(assignment.id);
-        inference.digests.values.push(digest.to_bytes());
+        let voting_info = &mut ctx.accounts.voting_info;
+        let vote = match content {
+            Some(data) => voting_info.add_vote(infer_id, data)?,
+            None => voting_info.add_ref_vote(infer_id, &output.keccak)?,
+        };
+        assignment.output = output;
+        set_assignment_digest(ctx.remaining_accounts, inference, assignment_id, vote, ctx.program_id)?;
+        let stats = &mut ctx.accounts.miner_stats;
+        stats.submitted += 1;
+        stats.last_active_slot = Clock::get()?.slot;
 
         emit!(SolutionSubmission {
             assignment_id,
@@ -871,7 +1556,7 @@ pub mod solearn {
         assignment.commitment = commitment;
         voting_info.total_commit += 1;
 
-        if voting_info.total_commit as usize == inference.assignments.len() - 1 {
+        if voting_info.total_commit == inference.assignment_count - 1 {
             inference.status = 3;
             emit!(InferenceStatusUpdate {
                 inference_id: infer_id,
@@ -887,12 +1572,12 @@ pub mod solearn {
         Ok(())
     }
 
-    pub fn reveal(
-        ctx: Context<UpdateAssignmentVld>,
+    pub fn reveal<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpdateAssignmentVld<'info>>,
         assignment_id: u64,
         inference_id: u64,
         nonce: u64,
-        data: Vec<u8>,
+        output: DataRef,
     ) -> Result<()> {
         let acc = &mut ctx.accounts.sol_learn_account;
         // only_updated_epoch(acc)?;
@@ -929,20 +1614,28 @@ pub mod solearn {
         let mut concatenated: Vec<u8> = nonce.to_le_bytes().to_vec();
         let sender_key = ctx.accounts.signer.key().clone();
         concatenated.extend(ctx.accounts.signer.key().to_bytes().to_vec());
-        concatenated.extend(data.clone());
+        concatenated.extend(output.keccak);
         let reveal_hash = hash(&mut concatenated);
         if assignment.commitment != reveal_hash.to_bytes() {
             return Err(SolLearnError::InvalidReveal.into());
         }
 
-        let digest = hash(&mut [infer_id.to_le_bytes().to_vec(), data.clone()].concat());
+        let (output_data, content) =
+            read_data_ref(&output, &ctx.accounts.output_data, Some(assignment.worker))?;
         assignment.reveal_nonce = nonce;
-        assignment.output = data.clone();
-        assignment.digest = digest.to_bytes();
+        assignment.output_data = output_data;
+        assignment.output = output.clone();
+        assignment.digest = exact_digest(infer_id, &output.keccak);
         voting_info.total_reveal += 1;
+        let vote = match content {
+            Some(data) => voting_info.add_vote(infer_id, data)?,
+            None => voting_info.add_ref_vote(infer_id, &output.keccak)?,
+        };
 
-        inference.digests.values.push(digest.to_bytes());
-        if voting_info.total_reveal as usize == inference.assignments.len() - 1 {
+        set_assignment_digest(ctx.remaining_accounts, inference, assignment_id, vote, ctx.program_id)?;
+        ctx.accounts.miner_stats.revealed += 1;
+        ctx.accounts.miner_stats.last_active_slot = slot_number;
+        if voting_info.total_reveal == inference.assignment_count - 1 {
             resolve_inference(ctx, assignment_id, infer_id)?;
         }
 
@@ -950,14 +1643,14 @@ pub mod solearn {
             assignment_id,
             sender: sender_key,
             nonce,
-            data,
+            output,
         });
 
         Ok(())
     }
 
-    pub fn resolve_inference(
-        ctx: Context<UpdateAssignmentVld>,
+    pub fn resolve_inference<'info>(
+        ctx: Context<'_, '_, '_, 'info, UpdateAssignmentVld<'info>>,
         assignment_id: u64,
         inference_id: u64,
     ) -> Result<()> {
@@ -980,6 +1673,7 @@ pub mod solearn {
         if inference.id != infer_id || inference.id != inference_id {
             return Err(SolLearnError::Unauthorized.into());
         }
+        let mut list = load_assignment_list(ctx.remaining_accounts, inference, ctx.program_id)?;
 
         msg!("begin status: {}", inference.status);
         if inference.status == 1 {
@@ -1004,6 +1698,7 @@ pub mod solearn {
                 let cpi_program = ctx.accounts.token_program.to_account_info();
                 let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts).with_signer(signer_seeds);
                 token::transfer(cpi_ctx, value)?;
+                acc.total_escrow = acc.total_escrow.saturating_sub(value);
 
                 // let from = ctx.accounts.vault_wallet_owner_pda.to_account_info();
                 // let to = ctx.accounts.recipient.to_account_info();
@@ -1015,11 +1710,13 @@ pub mod solearn {
 
                 // _slash_miner(inference.processedMiner, true);
                 let tasks = &mut ctx.accounts.tasks;
-                let mut data = vec![];
-                data.push(1);
-                data.extend_from_slice(&inference.processed_miner.to_bytes());
-
-                tasks.push_task(Task::new(FnType::SlashMiner, data));
+                tasks.push_task(Task::SlashMiner {
+                    target: AssignmentOrPubkey::Pubkey(inference.processed_miner),
+                    is_fined: true,
+                    check_empty_commit: false,
+                    vote: 0,
+                    reason: SlashReason::SubmitTimeout,
+                });
             }
         } else if inference.status == 2 {
             msg!(
@@ -1029,7 +1726,7 @@ pub mod solearn {
             );
             if Clock::get()?.slot > inference.commit_timeout {
                 msg!("total_commit: {}", voting_info.total_commit);
-                if voting_info.total_commit + 1 >= inference.assignments.len() as u8 {
+                if voting_info.total_commit + 1 >= inference.assignment_count {
                     inference.status = 3;
                 } else {
                     inference.status = 4;
@@ -1045,6 +1742,7 @@ pub mod solearn {
                     let cpi_program = ctx.accounts.token_program.to_account_info();
                     let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts).with_signer(signer_seeds);
                     token::transfer(cpi_ctx, value)?;
+                    acc.total_escrow = acc.total_escrow.saturating_sub(value);
 
                     // let from = ctx.accounts.vault_wallet_owner_pda.to_account_info();
                     // let to = ctx.accounts.recipient.to_account_info();
@@ -1054,17 +1752,17 @@ pub mod solearn {
                     // **from.try_borrow_mut_lamports()? -= value;
                     // **to.try_borrow_mut_lamports()? += value;
 
-                    for i in 0..inference.assignments.len() {
+                    for i in 0..list.ids.len() {
                         // _slash_miner(assignment.worker, false);
                         // create new task
                         let tasks = &mut ctx.accounts.tasks;
-                        let mut data = vec![];
-                        data.push(0);
-                        data.extend_from_slice(&inference.assignments[i].to_le_bytes());
-                        data.push(0);
-                        data.push(1);
-                        data.push(0);
-                        tasks.push_task(Task::new(FnType::SlashMiner, data));
+                        tasks.push_task(Task::SlashMiner {
+                            target: AssignmentOrPubkey::Assignment(list.ids[i]),
+                            is_fined: false,
+                            check_empty_commit: true,
+                            vote: 0,
+                            reason: SlashReason::CommitTimeout,
+                        });
                     }
                 }
                 msg!("final inference status {}", inference.status);
@@ -1075,48 +1773,76 @@ pub mod solearn {
                 || voting_info.total_reveal == voting_info.total_commit
             {
                 let tasks = &mut ctx.accounts.tasks;
-                if !filter_commitment(acc, inference, assignment, tasks)? {
-                    //  handle_not_enough_vote(ctx.accounts.infs.id);
-                    let value = inference.value + inference.fee_l2 + inference.fee_treasury;
-                    let cpi_accounts = Transfer {
-                        from: ctx.accounts.vault_staking_wallet.to_account_info(),
-                        to: ctx.accounts.token_recipient.to_account_info(),
-                        authority: ctx.accounts.vault_wallet_owner_pda.to_account_info(),
-                    };
-                    let k = acc.key();
-                    let seeds = [b"vault", k.as_ref(), &[ctx.accounts.vault_wallet_owner_pda.bump]];
-                    let signer_seeds = &[&seeds[..]];
-                    let cpi_program = ctx.accounts.token_program.to_account_info();
-                    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts).with_signer(signer_seeds);
-                    token::transfer(cpi_ctx, value)?;
-
-                    // let from = ctx.accounts.vault_wallet_owner_pda.to_account_info();
-                    // let to = ctx.accounts.recipient.to_account_info();
-                    // if **from.try_borrow_lamports()? < value {
-                    //     return Err(SolLearnError::InsufficientFunds.into());
-                    // }
-                    // **from.try_borrow_mut_lamports()? -= value;
-                    // **to.try_borrow_mut_lamports()? += value;
-
-                    for i in 0..inference.assignments.len() {
-                        let dig = inference.digests.values[i];
-                        if dig == [0; 32] {
-                            // _slash_miner(ctx, ctx.accounts.assignments[assignment_id].worker, false)?;
-
-                            let mut data = vec![];
-                            data.push(0);
-                            data.extend_from_slice(&inference.assignments[i].to_le_bytes());
-                            data.push(0);
-                            data.push(0);
-                            data.push(0);
-                            tasks.push_task(Task::new(FnType::SlashMiner, data));
+                let dao_receivers = &mut ctx.accounts.dao_receiver_infos;
+                if !filter_commitment(acc, inference, voting_info, &list, tasks, dao_receivers)? {
+                    let mut domain = infer_id.to_le_bytes().to_vec();
+                    domain.push(inference.round + 1);
+                    let seed = selection_seed(&ctx.accounts.recent_slothashes, &domain)?;
+                    let miners_of_model = &ctx.accounts.miners_of_model;
+                    if escalate(acc, inference, voting_info, &mut list, tasks, miners_of_model, &seed)? {
+                        store_assignment_list(
+                            ctx.remaining_accounts,
+                            infer_id,
+                            &list,
+                            ctx.accounts.signer.to_account_info(),
+                            ctx.accounts.system_program.to_account_info(),
+                            ctx.program_id,
+                        )?;
+                    } else {
+                        //  handle_not_enough_vote(ctx.accounts.infs.id);
+                        let mut value = inference.value + inference.fee_l2 + inference.fee_treasury;
+                        // nothing left to crank, the bounty goes back too
+                        if !list.digests.contains(&[0; 32]) {
+                            value += inference.crank_bounty;
+                            inference.crank_bounty = 0;
+                        }
+                        let cpi_accounts = Transfer {
+                            from: ctx.accounts.vault_staking_wallet.to_account_info(),
+                            to: ctx.accounts.token_recipient.to_account_info(),
+                            authority: ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                        };
+                        let k = acc.key();
+                        let seeds = [b"vault", k.as_ref(), &[ctx.accounts.vault_wallet_owner_pda.bump]];
+                        let signer_seeds = &[&seeds[..]];
+                        let cpi_program = ctx.accounts.token_program.to_account_info();
+                        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts).with_signer(signer_seeds);
+                        token::transfer(cpi_ctx, value)?;
+                        acc.total_escrow = acc.total_escrow.saturating_sub(value);
+
+                        // let from = ctx.accounts.vault_wallet_owner_pda.to_account_info();
+                        // let to = ctx.accounts.recipient.to_account_info();
+                        // if **from.try_borrow_lamports()? < value {
+                        //     return Err(SolLearnError::InsufficientFunds.into());
+                        // }
+                        // **from.try_borrow_mut_lamports()? -= value;
+                        // **to.try_borrow_mut_lamports()? += value;
+
+                        for i in 0..list.ids.len() {
+                            let dig = list.digests[i];
+                            if dig == [0; 32] {
+                                // _slash_miner(ctx, ctx.accounts.assignments[assignment_id].worker, false)?;
+
+                                tasks.push_task(Task::SlashMiner {
+                                    target: AssignmentOrPubkey::Assignment(list.ids[i]),
+                                    is_fined: false,
+                                    check_empty_commit: false,
+                                    vote: 0,
+                                    reason: SlashReason::RevealTimeout,
+                                });
+                            }
                         }
+                        inference.status = 4;
                     }
-                    inference.status = 4;
                 }
             }
         }
 
+        fit_tasks(
+            &ctx.accounts.tasks,
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        )?;
+
         emit!(InferenceStatusUpdate {
             inference_id: infer_id,
             status: inference.status,
@@ -1129,47 +1855,52 @@ pub mod solearn {
         let tasks = &mut ctx.accounts.tasks;
         let assignment = &mut ctx.accounts.assignment;
 
-        let task;
-        match tasks.receive_task() {
-            Some(t) => task = t,
-            None => return Err(SolLearnError::NoValidTask.into()),
-        }
-        if task.fn_type() != FnType::PayMiner {
-            return Err(SolLearnError::NoValidTask.into());
-        }
-        let data = task.data();
-        let use_assignment = data[0] == 1;
-        let value = if use_assignment {
-            let _assignment_id = u64::from_le_bytes(data[1..9].try_into().unwrap());
-
-            let pubkey = assignment.worker;
-            msg!("assignment_id: {} recipient {:?}", assignment_id, pubkey);
-            let mut value_bytes = [0u8; 8];
-            value_bytes.copy_from_slice(&data[9..17]);
-            let v = u64::from_le_bytes(value_bytes);
+        let (recipient, value, set_vote) = match tasks.receive_task()? {
+            Task::PayMiner {
+                recipient,
+                amount,
+                vote,
+            } => (recipient, amount, vote),
+            _ => return Err(SolLearnError::NoValidTask.into()),
+        };
+        let use_assignment = match recipient {
+            AssignmentOrPubkey::Assignment(_assignment_id) => {
+                if _assignment_id != assignment_id {
+                    return Err(SolLearnError::WrongAssignmentId.into());
+                }
 
-            if ctx.accounts.token_recipient.owner.key() != pubkey {
-                return Err(SolLearnError::WrongRecipient.into());
-            }
-            let set_vote = data[16];
-            if set_vote > 0 {
-                assignment.vote = set_vote;
+                let pubkey = assignment.worker;
+                msg!("assignment_id: {} recipient {:?}", assignment_id, pubkey);
+                if ctx.accounts.token_recipient.owner.key() != pubkey {
+                    return Err(SolLearnError::WrongRecipient.into());
+                }
+                if set_vote > 0 {
+                    assignment.vote = set_vote;
+                }
+                true
             }
-            v
-        } else {
-            let mut pubkey_bytes = [0u8; 32];
-            pubkey_bytes.copy_from_slice(&data[1..33]);
-            let pubkey = Pubkey::new_from_array(pubkey_bytes);
-            let mut value_bytes = [0u8; 8];
-            value_bytes.copy_from_slice(&data[33..41]);
-            let v = u64::from_le_bytes(value_bytes);
-            msg!("no assignment id, recipient {:?}", pubkey);
-
-            if ctx.accounts.token_recipient.owner.key() != pubkey {
-                return Err(SolLearnError::WrongRecipient.into());
+            AssignmentOrPubkey::Pubkey(pubkey) => {
+                msg!("no assignment id, recipient {:?}", pubkey);
+                if ctx.accounts.token_recipient.owner.key() != pubkey {
+                    return Err(SolLearnError::WrongRecipient.into());
+                }
+                false
             }
-            v
         };
+        let mut paid = value;
+        if use_assignment {
+            record_task_completed(
+                &mut ctx.accounts.sol_learn_account,
+                &mut ctx.accounts.miner_account,
+            );
+            ctx.accounts.miner_stats.record_win();
+            paid = credit_delegators(
+                &mut ctx.accounts.sol_learn_account,
+                &mut ctx.accounts.miner_account,
+                value,
+            );
+        }
+
         let cpi_accounts = Transfer {
             from: ctx.accounts.vault_staking_wallet.to_account_info(),
             to: ctx.accounts.token_recipient.to_account_info(),
@@ -1184,7 +1915,9 @@ pub mod solearn {
         let signer_seeds = &[&seeds[..]];
         let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts).with_signer(signer_seeds);
-        token::transfer(cpi_ctx, value)?;
+        token::transfer(cpi_ctx, paid)?;
+        ctx.accounts.sol_learn_account.total_escrow =
+            ctx.accounts.sol_learn_account.total_escrow.saturating_sub(value);
 
         // let from = ctx.accounts.vault_wallet_owner_pda.to_account_info();
         // let to = ctx.accounts.recipient.to_account_info();
@@ -1215,7 +1948,22 @@ pub mod solearn {
             return Err(SolLearnError::Unauthorized.into());
         }
 
-        _slash_miner(miner, is_fined, acc, miner_addresses)?;
+        let (fine, delegated, forced) = _slash_miner(miner, is_fined, acc, miner_addresses)?;
+        let mut record = record_slash(
+            miner,
+            ctx.accounts.slash_record.key(),
+            ctx.bumps.slash_record,
+            0,
+            0,
+            SlashReason::Admin,
+            fine,
+        );
+        record.forced_unstake = forced;
+        ctx.accounts.miner_stats.record_slash(SlashReason::Admin);
+        // an admin fine has no creator share, all of it goes to the treasury
+        // once executed
+        hold_fine(acc, &mut record, delegated, Pubkey::default())?;
+        ctx.accounts.slash_record.set_inner(record);
 
         Ok(())
     }
@@ -1227,88 +1975,634 @@ pub mod solearn {
         let assignment = &mut ctx.accounts.assignment;
 
         let tasks = &mut ctx.accounts.tasks;
-        let task;
-        match tasks.receive_task() {
-            Some(t) => task = t,
-            None => return Err(SolLearnError::NoValidTask.into()),
-        }
-        if task.fn_type() != FnType::SlashMiner {
-            return Err(SolLearnError::NoValidTask.into());
-        }
-        let data = task.data();
-        let slashing_processed_miner = data[0] == 1;
-        let token_fine = if slashing_processed_miner {
-            let mut pubkey_bytes = [0u8; 32];
-            pubkey_bytes.copy_from_slice(&data[1..33]);
-            let pubkey = Pubkey::new_from_array(pubkey_bytes);
-            let is_fined = data[33] == 1;
-            if pubkey != miner.miner {
-                return Err(SolLearnError::Unauthorized.into());
-            }
+        let (target, is_fined, check_empty_commit, set_vote, reason) = match tasks.receive_task()? {
+            Task::SlashMiner {
+                target,
+                is_fined,
+                check_empty_commit,
+                vote,
+                reason,
+            } => (target, is_fined, check_empty_commit, vote, reason),
+            _ => return Err(SolLearnError::NoValidTask.into()),
+        };
+        let (token_fine, delegated, forced) = match target {
+            AssignmentOrPubkey::Pubkey(pubkey) => {
+                // the processed miner is slashed, with its own assignment
+                if pubkey != miner.miner || assignment.worker != pubkey {
+                    return Err(SolLearnError::Unauthorized.into());
+                }
 
-            _slash_miner(miner, is_fined, acc, miner_addresses)?
-        } else {
-            let _assignment_id: u64 = u64::from_le_bytes(data[1..9].try_into().unwrap());
-            let is_fined = data[9] == 1;
-            let check_empty_commit = data[10] == 1;
-            if assignment_id != assignment.id || _assignment_id != assignment_id {
-                return Err(SolLearnError::Unauthorized.into());
+                _slash_miner(miner, is_fined, acc, miner_addresses)?
             }
-            if check_empty_commit {
-                if assignment.commitment != [0; 32] {
-                    return Ok(()); // not slashed
+            AssignmentOrPubkey::Assignment(_assignment_id) => {
+                if assignment_id != assignment.id || _assignment_id != assignment_id {
+                    return Err(SolLearnError::Unauthorized.into());
+                }
+                if check_empty_commit && assignment.commitment != [0; 32] {
+                    // not slashed, nothing to record
+                    let signer = ctx.accounts.signer.to_account_info();
+                    return ctx.accounts.slash_record.close(signer);
                 }
-            }
 
-            let set_vote = data[11];
-            if set_vote > 0 {
-                assignment.vote = set_vote;
-            }
-            let pubkey = assignment.worker;
-            if pubkey != miner.miner {
-                return Err(SolLearnError::Unauthorized.into());
+                if set_vote > 0 {
+                    assignment.vote = set_vote;
+                }
+                let pubkey = assignment.worker;
+                if pubkey != miner.miner {
+                    return Err(SolLearnError::Unauthorized.into());
+                }
+                _slash_miner(miner, is_fined, acc, miner_addresses)?
             }
-            _slash_miner(miner, is_fined, acc, miner_addresses)?
         };
-        if token_fine > 0 {
-            if ctx.accounts.sol_learn_account.treasury == ctx.accounts.token_recipient.key() {
-                return Err(SolLearnError::Unauthorized.into());
-            }
+        let mut record = record_slash(
+            miner,
+            ctx.accounts.slash_record.key(),
+            ctx.bumps.slash_record,
+            assignment.inference_id,
+            assignment.id,
+            reason,
+            token_fine,
+        );
+        record.forced_unstake = forced;
+        ctx.accounts.miner_stats.record_slash(reason);
+        let due = hold_fine(acc, &mut record, delegated, ctx.accounts.creator_wallet.key())?;
+        ctx.accounts.slash_record.set_inner(record);
+        if !due {
+            return Ok(());
+        }
+
+        let (treasury_fine, creator_fine) = split_fine(acc, token_fine);
+        let k = acc.key();
+        let vault_bump = ctx.accounts.vault_wallet_owner_pda.bump;
+        if treasury_fine > 0 {
+            transfer_from_vault(
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.vault_staking_wallet.to_account_info(),
+                ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                ctx.accounts.token_recipient.to_account_info(),
+                &k,
+                vault_bump,
+                treasury_fine,
+            )?;
+        }
+        if creator_fine > 0 {
+            transfer_from_vault(
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.vault_staking_wallet.to_account_info(),
+                ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                ctx.accounts.creator_wallet.to_account_info(),
+                &k,
+                vault_bump,
+                creator_fine,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // The slashed miner contests a pending slash before its window closes by
+    // posting dispute_bond, the fine then waits for the arbiter or the admin.
+    pub fn open_dispute(ctx: Context<OpenDisputeVld>, index: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp as u64;
+        let record = &mut ctx.accounts.slash_record;
+        if record.status != SLASH_PENDING || now >= record.executable_at {
+            return Err(SolLearnError::NotDisputable.into());
+        }
+        msg!("dispute of slash record {} of {:?}", index, record.miner);
+
+        let bond = ctx.accounts.sol_learn_account.dispute_bond;
+        if bond > 0 {
             let cpi_accounts = Transfer {
-                from: ctx.accounts.vault_staking_wallet.to_account_info(),
-                to: ctx.accounts.token_recipient.to_account_info(),
-                authority: ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                from: ctx.accounts.miner_wallet.to_account_info(),
+                to: ctx.accounts.vault_staking_wallet.to_account_info(),
+                authority: ctx.accounts.signer.to_account_info(),
             };
-            let k = ctx.accounts.sol_learn_account.key();
-            let seeds = [
-                b"vault",
-                k.as_ref(),
-                &[ctx.accounts.vault_wallet_owner_pda.bump],
-            ];
-            let signer_seeds = &[&seeds[..]];
             let cpi_program = ctx.accounts.token_program.to_account_info();
-            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts).with_signer(signer_seeds);
-            token::transfer(cpi_ctx, token_fine)?;
+            token::transfer(CpiContext::new(cpi_program, cpi_accounts), bond)?;
         }
+        record.status = SLASH_DISPUTED;
+        record.bond = bond;
+        ctx.accounts.sol_learn_account.total_escrow += bond;
+
+        emit!(DisputeOpened {
+            miner: record.miner,
+            slash_record: record.key(),
+            bond,
+        });
 
         Ok(())
     }
 
-    pub fn calculate_user_dao_token_received(
-        ctx: Context<UpdateEpochVld>,
-        score: u8,
-    ) -> Result<u64> {
-        let acc = &ctx.accounts.sol_learn_account;
-        let mut user_dao_token_receive = 0;
+    // Settles a dispute. An overturned slash gives the fine back to the stake
+    // it was taken from, lifts the penalty and refunds the bond; an upheld one
+    // forfeits the bond to the treasury and lets the fine be executed. The
+    // miner left MinersOfModel when it was slashed and stays out, it calls
+    // join_for_minting again to mine, which it can do at once.
+    pub fn resolve_dispute(
+        ctx: Context<ResolveDisputeVld>,
+        index: u64,
+        overturned: bool,
+    ) -> Result<()> {
+        let record = &mut ctx.accounts.slash_record;
+        if record.status != SLASH_DISPUTED {
+            return Err(SolLearnError::NoOpenDispute.into());
+        }
+        msg!("resolve slash record {} of {:?}", index, record.miner);
+
+        let acc = &mut ctx.accounts.sol_learn_account;
+        let bond_recipient = if overturned {
+            let miner = &mut ctx.accounts.miner_account;
+            miner.restore_fine(record.amount, record.delegated_amount);
+            miner.cancel_forced_unstake(&record.forced_unstake);
+            miner.active_time = 0;
+            acc.total_stake += record.amount;
+            acc.total_escrow = acc.total_escrow.saturating_sub(record.amount);
+            record.status = SLASH_OVERTURNED;
+            ctx.accounts.miner_wallet.to_account_info()
+        } else {
+            record.status = SLASH_UPHELD;
+            ctx.accounts.treasury_wallet.to_account_info()
+        };
+        if record.bond > 0 {
+            transfer_from_vault(
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.vault_staking_wallet.to_account_info(),
+                ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                bond_recipient,
+                &acc.key(),
+                ctx.accounts.vault_wallet_owner_pda.bump,
+                record.bond,
+            )?;
+            acc.total_escrow = acc.total_escrow.saturating_sub(record.bond);
+        }
+
+        emit!(DisputeResolved {
+            miner: record.miner,
+            slash_record: record.key(),
+            overturned,
+        });
+
+        Ok(())
+    }
+
+    // Pays out the fine of a slash whose dispute window passed undisputed or
+    // whose dispute was lost, anyone can call it.
+    pub fn execute_slash(ctx: Context<ExecuteSlashVld>, index: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp as u64;
+        let record = &mut ctx.accounts.slash_record;
+        let executable = record.status == SLASH_UPHELD
+            || (record.status == SLASH_PENDING && now >= record.executable_at);
+        if !executable {
+            return Err(SolLearnError::SlashNotExecutable.into());
+        }
+        msg!("execute slash record {} of {:?}", index, record.miner);
+
+        let acc = &mut ctx.accounts.sol_learn_account;
+        let (treasury_fine, creator_fine) = if record.creator_wallet == Pubkey::default() {
+            (record.amount, 0)
+        } else {
+            split_fine(acc, record.amount)
+        };
+        let k = acc.key();
+        for (to, amount) in [
+            (ctx.accounts.treasury_wallet.to_account_info(), treasury_fine),
+            (ctx.accounts.creator_wallet.to_account_info(), creator_fine),
+        ] {
+            if amount > 0 {
+                transfer_from_vault(
+                    ctx.accounts.token_program.to_account_info(),
+                    ctx.accounts.vault_staking_wallet.to_account_info(),
+                    ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                    to,
+                    &k,
+                    ctx.accounts.vault_wallet_owner_pda.bump,
+                    amount,
+                )?;
+            }
+        }
+        acc.total_escrow = acc.total_escrow.saturating_sub(record.amount);
+        record.status = SLASH_EXECUTED;
+
+        emit!(SlashExecuted {
+            miner: record.miner,
+            slash_record: record.key(),
+            treasury_fine,
+            creator_fine,
+        });
+
+        Ok(())
+    }
+
+    // Runs queued tasks of an inference in order, for as long as the compute
+    // budget and the passed accounts allow. Whoever empties the queue of a
+    // resolved inference gets its crank bounty.
+    pub fn crank_tasks<'info>(
+        ctx: Context<'_, '_, '_, 'info, CrankTasksVld<'info>>,
+        inference_id: u64,
+    ) -> Result<()> {
+        let program_id = ctx.program_id;
+        let sol_learn_key = ctx.accounts.sol_learn_account.key();
+        let token = ctx.accounts.sol_learn_account.token;
+        let vault_bump = ctx.accounts.vault_wallet_owner_pda.bump;
+        let mut remaining = ctx.remaining_accounts;
+        let mut processed = 0;
+
+        while !ctx.accounts.tasks.values.is_empty() {
+            if processed > 0 && sol_remaining_compute_units() < CRANK_TASK_COMPUTE {
+                break;
+            }
+            let task = ctx.accounts.tasks.peek_task()?;
+            let needed = task_account_count(&task);
+            if remaining.len() < needed {
+                if processed == 0 {
+                    return Err(SolLearnError::MissingTaskAccounts.into());
+                }
+                break;
+            }
+            let (infos, rest) = remaining.split_at(needed);
+            remaining = rest;
+
+            match task {
+                Task::CreateAssignment {
+                    assignment_id,
+                    inference_id: task_inference_id,
+                    worker,
+                    role,
+                } => {
+                    let id_bytes = assignment_id.to_le_bytes();
+                    let (expected, bump) =
+                        Pubkey::find_program_address(&[b"assignment", id_bytes.as_ref()], program_id);
+                    if expected != infos[0].key() {
+                        return Err(SolLearnError::WrongAssignmentId.into());
+                    }
+                    create_pda_account(
+                        ctx.accounts.system_program.to_account_info(),
+                        ctx.accounts.signer.to_account_info(),
+                        infos[0].clone(),
+                        &[b"assignment", id_bytes.as_ref(), &[bump]],
+                        ANCHOR_DISCRIMINATOR + Assignment::INIT_SPACE,
+                        program_id,
+                    )?;
+                    let assignment = Assignment {
+                        bump,
+                        id: assignment_id,
+                        inference_id: task_inference_id,
+                        commitment: [0; 32],
+                        digest: [0; 32],
+                        reveal_nonce: 0,
+                        worker,
+                        role,
+                        vote: 0,
+                        output: DataRef::default(),
+                        output_data: Pubkey::default(),
+                    };
+                    store_account(&infos[0], &assignment)?;
+
+                    emit!(NewAssignment {
+                        assignment_id,
+                        inference_id: task_inference_id,
+                        worker,
+                    });
+                }
+                Task::PayMiner {
+                    recipient,
+                    amount,
+                    vote,
+                } => {
+                    let mut paid = amount;
+                    let to = match recipient {
+                        AssignmentOrPubkey::Assignment(assignment_id) => {
+                            let mut assignment = load_assignment(&infos[0], program_id)?;
+                            if assignment.id != assignment_id {
+                                return Err(SolLearnError::WrongAssignmentId.into());
+                            }
+                            let mut miner = load_miner(&infos[1], &sol_learn_key, program_id)?;
+                            if miner.miner != assignment.worker
+                                || load_wallet(&infos[2], &token)?.owner != assignment.worker
+                            {
+                                return Err(SolLearnError::WrongRecipient.into());
+                            }
+                            if vote > 0 {
+                                assignment.vote = vote;
+                                store_account(&infos[0], &assignment)?;
+                            }
+                            let mut stats = load_miner_stats(&infos[3], &sol_learn_key, program_id)?;
+                            if stats.miner != assignment.worker {
+                                return Err(SolLearnError::Unauthorized.into());
+                            }
+                            record_task_completed(&mut ctx.accounts.sol_learn_account, &mut miner);
+                            stats.record_win();
+                            paid = credit_delegators(
+                                &mut ctx.accounts.sol_learn_account,
+                                &mut miner,
+                                amount,
+                            );
+                            store_account(&infos[1], &miner)?;
+                            store_account(&infos[3], &stats)?;
+                            &infos[2]
+                        }
+                        AssignmentOrPubkey::Pubkey(pubkey) => {
+                            if load_wallet(&infos[0], &token)?.owner != pubkey {
+                                return Err(SolLearnError::WrongRecipient.into());
+                            }
+                            &infos[0]
+                        }
+                    };
+                    transfer_from_vault(
+                        ctx.accounts.token_program.to_account_info(),
+                        ctx.accounts.vault_staking_wallet.to_account_info(),
+                        ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                        to.clone(),
+                        &sol_learn_key,
+                        vault_bump,
+                        paid,
+                    )?;
+                    ctx.accounts.sol_learn_account.total_escrow =
+                        ctx.accounts.sol_learn_account.total_escrow.saturating_sub(amount);
+                }
+                Task::MintDaoToken {
+                    recipient,
+                    amount,
+                    role,
+                } => {
+                    let dao_token = match &ctx.accounts.dao_token {
+                        Some(dao_token) => dao_token,
+                        None => return Err(SolLearnError::MissingTaskAccounts.into()),
+                    };
+                    let (receiver, to, owner) = match recipient {
+                        AssignmentOrPubkey::Assignment(assignment_id) => {
+                            let assignment = load_assignment(&infos[0], program_id)?;
+                            if assignment.id != assignment_id {
+                                return Err(SolLearnError::WrongAssignmentId.into());
+                            }
+                            (assignment.worker, &infos[1], &infos[2])
+                        }
+                        // the creator is a wallet, its owner receives
+                        AssignmentOrPubkey::Pubkey(pubkey) if pubkey == ctx.accounts.infs.creator => {
+                            (ctx.accounts.creator_wallet.owner, &infos[0], &infos[1])
+                        }
+                        AssignmentOrPubkey::Pubkey(pubkey) => (pubkey, &infos[0], &infos[1]),
+                    };
+                    if owner.key() != receiver {
+                        return Err(SolLearnError::WrongRecipient.into());
+                    }
+                    ensure_dao_wallet(
+                        ctx.accounts
+                            .associated_token_program
+                            .as_ref()
+                            .map(|program| program.to_account_info()),
+                        ctx.accounts.token_program.to_account_info(),
+                        ctx.accounts.system_program.to_account_info(),
+                        ctx.accounts.signer.to_account_info(),
+                        dao_token.to_account_info(),
+                        to.clone(),
+                        owner.clone(),
+                    )?;
+                    if load_wallet(to, &dao_token.key())?.owner != receiver {
+                        return Err(SolLearnError::WrongRecipient.into());
+                    }
+                    mint_from_vault(
+                        ctx.accounts.token_program.to_account_info(),
+                        dao_token.to_account_info(),
+                        ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                        to.clone(),
+                        &sol_learn_key,
+                        vault_bump,
+                        amount,
+                    )?;
+
+                    if role == DAOTokenReceiverRole::Referrer as u8 {
+                        let mut referrer = load_referrer(&infos[2], program_id)?;
+                        if referrer.user != receiver {
+                            return Err(SolLearnError::WrongReferrer.into());
+                        }
+                        referrer.earned_rewards += amount;
+                        store_account(&infos[2], &referrer)?;
+                    }
+
+                    emit!(DAOTokenMinted {
+                        inference_id,
+                        model_address: ctx.accounts.infs.model_address,
+                        receiver,
+                        amount,
+                        role,
+                    });
+                }
+                Task::SlashMiner {
+                    target,
+                    is_fined,
+                    check_empty_commit,
+                    vote,
+                    reason,
+                } => {
+                    let slashed = match target {
+                        AssignmentOrPubkey::Assignment(assignment_id) => {
+                            let mut assignment = load_assignment(&infos[0], program_id)?;
+                            if assignment.id != assignment_id {
+                                return Err(SolLearnError::WrongAssignmentId.into());
+                            }
+                            if check_empty_commit && assignment.commitment != [0; 32] {
+                                None // not slashed
+                            } else {
+                                if vote > 0 {
+                                    assignment.vote = vote;
+                                    store_account(&infos[0], &assignment)?;
+                                }
+                                Some((&infos[1], &infos[2], &infos[3], assignment.worker, assignment_id))
+                            }
+                        }
+                        AssignmentOrPubkey::Pubkey(pubkey) => {
+                            let assignment_id = ctx.accounts.infs.mining_assignment;
+                            Some((&infos[0], &infos[1], &infos[2], pubkey, assignment_id))
+                        }
+                    };
+                    if let Some((miner_info, record_info, stats_info, worker, assignment_id)) = slashed {
+                        let mut miner = load_miner(miner_info, &sol_learn_key, program_id)?;
+                        let mut stats = load_miner_stats(stats_info, &sol_learn_key, program_id)?;
+                        if miner.miner != worker || stats.miner != worker {
+                            return Err(SolLearnError::Unauthorized.into());
+                        }
+                        let (fine, delegated, forced) = _slash_miner(
+                            &mut miner,
+                            is_fined,
+                            &mut ctx.accounts.sol_learn_account,
+                            &mut ctx.accounts.miners_of_model,
+                        )?;
+
+                        let miner_key = miner_info.key();
+                        let count = miner.slash_count.to_le_bytes();
+                        let (expected, bump) = Pubkey::find_program_address(
+                            &[b"slash_record", miner_key.as_ref(), count.as_ref()],
+                            program_id,
+                        );
+                        if expected != record_info.key() {
+                            return Err(SolLearnError::InvalidSlashRecord.into());
+                        }
+                        create_pda_account(
+                            ctx.accounts.system_program.to_account_info(),
+                            ctx.accounts.signer.to_account_info(),
+                            record_info.clone(),
+                            &[b"slash_record", miner_key.as_ref(), count.as_ref(), &[bump]],
+                            ANCHOR_DISCRIMINATOR + SlashRecord::INIT_SPACE,
+                            program_id,
+                        )?;
+                        let mut record =
+                            record_slash(&mut miner, expected, bump, inference_id, assignment_id, reason, fine);
+                        record.forced_unstake = forced;
+                        let due = hold_fine(
+                            &mut ctx.accounts.sol_learn_account,
+                            &mut record,
+                            delegated,
+                            ctx.accounts.creator_wallet.key(),
+                        )?;
+                        store_account(record_info, &record)?;
+                        store_account(miner_info, &miner)?;
+                        stats.record_slash(reason);
+                        store_account(stats_info, &stats)?;
+
+                        let (treasury_fine, creator_fine) =
+                            split_fine(&ctx.accounts.sol_learn_account, fine);
+                        for (to, amount) in [
+                            (ctx.accounts.treasury_wallet.to_account_info(), treasury_fine),
+                            (ctx.accounts.creator_wallet.to_account_info(), creator_fine),
+                        ] {
+                            if due && amount > 0 {
+                                transfer_from_vault(
+                                    ctx.accounts.token_program.to_account_info(),
+                                    ctx.accounts.vault_staking_wallet.to_account_info(),
+                                    ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                                    to,
+                                    &sol_learn_key,
+                                    vault_bump,
+                                    amount,
+                                )?;
+                            }
+                        }
+                    }
+                }
+            }
+
+            ctx.accounts.tasks.receive_task()?;
+            processed += 1;
+        }
+
+        let inference = &mut ctx.accounts.infs;
+        let resolved = inference.status == 4 || inference.status == 5;
+        let mut bounty = 0;
+        if processed > 0 && resolved && ctx.accounts.tasks.values.is_empty() {
+            bounty = inference.crank_bounty;
+            inference.crank_bounty = 0;
+        }
+        if bounty > 0 {
+            transfer_from_vault(
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.vault_staking_wallet.to_account_info(),
+                ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                ctx.accounts.cranker_wallet.to_account_info(),
+                &sol_learn_key,
+                vault_bump,
+                bounty,
+            )?;
+            ctx.accounts.sol_learn_account.total_escrow =
+                ctx.accounts.sol_learn_account.total_escrow.saturating_sub(bounty);
+        }
+
+        emit!(TasksCranked {
+            inference_id,
+            cranker: ctx.accounts.signer.key(),
+            processed,
+            remaining: ctx.accounts.tasks.pending()?.len() as u64,
+            bounty,
+        });
+
+        Ok(())
+    }
+
+    // The creator rates a solved inference once, from 1 to
+    // MAX_INFERENCE_SCORE. The rating moves the quality scores of the model
+    // and of the miner that solved it, and mints the user's DAO share.
+    pub fn rate_inference(ctx: Context<RateInferenceVld>, inference_id: u64, score: u8) -> Result<()> {
+        if score == 0 || score > MAX_INFERENCE_SCORE {
+            return Err(SolLearnError::InvalidScore.into());
+        }
+        let inference = &mut ctx.accounts.infs;
+        if !inference.solved {
+            return Err(SolLearnError::InferenceNotSolved.into());
+        }
+        if inference.score != 0 {
+            return Err(SolLearnError::AlreadyRated.into());
+        }
+        inference.score = score;
+
+        let model_info = &mut ctx.accounts.model_info;
+        model_info.quality_score = rolling_quality(model_info.quality_score, model_info.rating_count, score);
+        model_info.rating_count += 1;
+
+        let quality_weight = ctx.accounts.sol_learn_account.quality_weight;
+        let miner_account = &mut ctx.accounts.miner_account;
+        miner_account.quality_score =
+            rolling_quality(miner_account.quality_score, miner_account.rating_count, score);
+        miner_account.rating_count += 1;
+        if miner_account.is_active {
+            let miners_of_model = &mut ctx.accounts.miners_of_model;
+            if let Some(index) = miners_of_model.position(&miner_account.miner) {
+                miners_of_model.set_stake(index, miner_account.selection_weight(quality_weight));
+            }
+        }
+
+        let signer = ctx.accounts.signer.key();
+        let sol_learn_key = ctx.accounts.sol_learn_account.key();
+        let acc = &mut ctx.accounts.sol_learn_account;
+        let mut amount = 0;
+        if acc.dao_token != Pubkey::default() {
+            acc.roll_dao_token_epoch();
+            amount = user_dao_token_received(acc, score).min(acc.dao_token_mintable());
+        }
+        if amount > 0 {
+            let (dao_token, user_dao_wallet) =
+                match (&ctx.accounts.dao_token, &ctx.accounts.user_dao_wallet) {
+                    (Some(dao_token), Some(user_dao_wallet)) => (dao_token, user_dao_wallet),
+                    _ => return Err(SolLearnError::MissingDaoTokenAccounts.into()),
+                };
+            if user_dao_wallet.mint != dao_token.key() || user_dao_wallet.owner != signer {
+                return Err(SolLearnError::WrongRecipient.into());
+            }
+            mint_from_vault(
+                ctx.accounts.token_program.to_account_info(),
+                dao_token.to_account_info(),
+                ctx.accounts.vault_wallet_owner_pda.to_account_info(),
+                user_dao_wallet.to_account_info(),
+                &sol_learn_key,
+                ctx.accounts.vault_wallet_owner_pda.bump,
+                amount,
+            )?;
+            acc.record_dao_token_minted(amount);
+            ctx.accounts.dao_receiver_infos.values.push(DAOTokenReceiverInfo {
+                receiver: AssignmentOrPubkey::Pubkey(inference.creator),
+                amount,
+                role: DAOTokenReceiverRole::User as u8,
+            });
 
-        if score >= 1 && score <= 10 {
-            user_dao_token_receive = (((acc.dao_token_percentage.user_percentage as u64)
-                * ((score as u64) * (acc.dao_token_reward as u64)))
-                / 10)
-                / PERCENTAGE_DENOMINATOR;
+            emit!(DAOTokenMinted {
+                inference_id,
+                model_address: inference.model_address,
+                receiver: signer,
+                amount,
+                role: DAOTokenReceiverRole::User as u8,
+            });
         }
 
-        Ok(user_dao_token_receive.into())
+        emit!(InferenceRated {
+            inference_id,
+            model_address: inference.model_address,
+            miner: inference.processed_miner,
+            score,
+            model_quality_score: model_info.quality_score,
+            miner_quality_score: miner_account.quality_score,
+            dao_token_amount: amount,
+        });
+
+        Ok(())
     }
 
     pub fn set_fine_percentage(ctx: Context<UpdateParamsVld>, fine_percentage: u16) -> Result<()> {
@@ -1323,6 +2617,83 @@ pub mod solearn {
         Ok(())
     }
 
+    pub fn set_fine_model(ctx: Context<UpdateParamsVld>, fine_model: u8) -> Result<()> {
+        if fine_model > FINE_ESCALATING {
+            return Err(SolLearnError::InvalidFineModel.into());
+        }
+
+        let acc = &mut ctx.accounts.sol_learn_account;
+        acc.fine_model = fine_model;
+        emit!(FineModelUpdated {
+            new_fine_model: fine_model,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_fine_creator_percentage(
+        ctx: Context<UpdateParamsVld>,
+        fine_creator_percentage: u16,
+    ) -> Result<()> {
+        if fine_creator_percentage as u64 > PERCENTAGE_DENOMINATOR {
+            return Err(SolLearnError::InvalidPercentage.into());
+        }
+        let acc = &mut ctx.accounts.sol_learn_account;
+
+        acc.fine_creator_percentage = fine_creator_percentage;
+        emit!(FineCreatorPercentageUpdated {
+            new_fine_creator_percentage: fine_creator_percentage,
+        });
+
+        Ok(())
+    }
+
+    // validators of an inconclusive round are not paid, see SolLearnInfo
+    pub fn set_escalation(
+        ctx: Context<UpdateParamsVld>,
+        escalation_rounds: u8,
+        escalation_validators: u8,
+    ) -> Result<()> {
+        let acc = &mut ctx.accounts.sol_learn_account;
+
+        acc.escalation_rounds = escalation_rounds;
+        acc.escalation_validators = escalation_validators;
+        emit!(EscalationUpdated {
+            new_escalation_rounds: escalation_rounds,
+            new_escalation_validators: escalation_validators,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_dispute_params(
+        ctx: Context<UpdateParamsVld>,
+        dispute_window: u64,
+        dispute_bond: u64,
+    ) -> Result<()> {
+        let acc = &mut ctx.accounts.sol_learn_account;
+
+        acc.dispute_window = dispute_window;
+        acc.dispute_bond = dispute_bond;
+        emit!(DisputeParamsUpdated {
+            new_dispute_window: dispute_window,
+            new_dispute_bond: dispute_bond,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_arbiter(ctx: Context<UpdateParamsVld>, arbiter: Pubkey) -> Result<()> {
+        let acc = &mut ctx.accounts.sol_learn_account;
+
+        acc.arbiter = arbiter;
+        emit!(ArbiterUpdated {
+            new_arbiter: arbiter,
+        });
+
+        Ok(())
+    }
+
     pub fn set_penalty_duration(
         ctx: Context<UpdateParamsVld>,
         penalty_duration: u64,
@@ -1362,17 +2733,18 @@ pub mod solearn {
         Ok(())
     }
 
-    // pub fn set_dao_token(ctx: Context<UpdateParamsVld>, dao_token_address: Pubkey) -> Result<()> {
-    //     let acc = &mut ctx.accounts.sol_learn_account;
-    //     only_updated_epoch(acc)?;
+    pub fn set_dao_token(ctx: Context<SetDaoTokenVld>) -> Result<()> {
+        let acc = &mut ctx.accounts.sol_learn_account;
+        // only_updated_epoch(acc)?;
 
-    //     acc.dao_token = dao_token_address;
-    //     emit!(DaoTokenUpdated {
-    //         new_dao_token: dao_token_address,
-    //     });
+        let dao_token_address = ctx.accounts.dao_token.key();
+        acc.dao_token = dao_token_address;
+        emit!(DaoTokenUpdated {
+            new_dao_token: dao_token_address,
+        });
 
-    //     Ok(())
-    // }
+        Ok(())
+    }
 
     pub fn set_treasury_address(
         ctx: Context<UpdateParamsVld>,
@@ -1418,4 +2790,51 @@ pub mod solearn {
 
         Ok(())
     }
+
+    // 0 lifts the cap; a cap below what is already minted stops minting
+    pub fn set_dao_token_max_emission(
+        ctx: Context<UpdateParamsVld>,
+        new_dao_token_max_emission: u64,
+    ) -> Result<()> {
+        let acc = &mut ctx.accounts.sol_learn_account;
+        // only_updated_epoch(acc)?;
+
+        acc.dao_token_max_emission = new_dao_token_max_emission;
+        emit!(DaoTokenMaxEmissionUpdated {
+            new_dao_token_max_emission,
+        });
+
+        Ok(())
+    }
+
+    // Takes effect for a miner the next time its selection weight is
+    // written, on a stake change or a rating.
+    pub fn set_quality_weight(ctx: Context<UpdateParamsVld>, new_quality_weight: u16) -> Result<()> {
+        if u64::from(new_quality_weight) > PERCENTAGE_DENOMINATOR {
+            return Err(SolLearnError::InvalidPercentage.into());
+        }
+        let acc = &mut ctx.accounts.sol_learn_account;
+        // only_updated_epoch(acc)?;
+
+        acc.quality_weight = new_quality_weight;
+        emit!(QualityWeightUpdated { new_quality_weight });
+
+        Ok(())
+    }
+
+    // 0 lifts the per-epoch limit
+    pub fn set_dao_token_epoch_budget(
+        ctx: Context<UpdateParamsVld>,
+        new_dao_token_epoch_budget: u64,
+    ) -> Result<()> {
+        let acc = &mut ctx.accounts.sol_learn_account;
+        // only_updated_epoch(acc)?;
+
+        acc.dao_token_epoch_budget = new_dao_token_epoch_budget;
+        emit!(DaoTokenEpochBudgetUpdated {
+            new_dao_token_epoch_budget,
+        });
+
+        Ok(())
+    }
 }
@@ -0,0 +1,172 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak::hashv;
+
+use crate::errors::*;
+
+// SlotHashes sysvar layout: u64 entry count, then (slot: u64, hash: [u8; 32])
+// entries ordered from the most recent slot.
+const SLOT_HASHES_HEADER: usize = 8;
+const SLOT_HASH_ENTRY: usize = 8 + 32;
+
+// Reads the most recent (slot, hash) pair of the SlotHashes sysvar.
+pub fn most_recent_slot_hash(slot_hashes: &AccountInfo) -> Result<(u64, [u8; 32])> {
+    let data = slot_hashes.try_borrow_data()?;
+    if data.len() < SLOT_HASHES_HEADER + SLOT_HASH_ENTRY {
+        return Err(SolLearnError::InvalidSlotHashes.into());
+    }
+
+    let count = u64::from_le_bytes(data[0..8].try_into().unwrap());
+    if count == 0 {
+        return Err(SolLearnError::InvalidSlotHashes.into());
+    }
+
+    let slot = u64::from_le_bytes(data[8..16].try_into().unwrap());
+    let hash: [u8; 32] = data[16..48].try_into().unwrap();
+
+    Ok((slot, hash))
+}
+
+// Seed of a draw: keccak(slot || slot_hash || domain). The domain binds the
+// draw to what is being selected for (inference id, miner key), so two draws
+// in the same slot never share a seed.
+pub fn derive_seed(slot: u64, slot_hash: &[u8; 32], domain: &[u8]) -> [u8; 32] {
+    hashv(&[&slot.to_le_bytes(), slot_hash, domain]).to_bytes()
+}
+
+pub fn selection_seed(slot_hashes: &AccountInfo, domain: &[u8]) -> Result<[u8; 32]> {
+    let (slot, slot_hash) = most_recent_slot_hash(slot_hashes)?;
+    Ok(derive_seed(slot, &slot_hash, domain))
+}
+
+// The i-th draw of a seed: rightmost 8 bytes of keccak(seed || nonce) read as
+// little endian, reduced to [0, range). An off-chain verifier only needs the
+// seed emitted in the event to replay every draw.
+pub fn random_number(seed: &[u8; 32], nonce: u64, range: u64) -> u64 {
+    if range == 0 {
+        return 0;
+    }
+
+    let res = hashv(&[seed, &nonce.to_le_bytes()]).to_bytes();
+    let rightmost: [u8; 8] = res[24..].try_into().unwrap();
+
+    u64::from_le_bytes(rightmost) % range
+}
+
+pub const SELECTION_UNIFORM: u8 = 0;
+pub const SELECTION_STAKE_WEIGHTED: u8 = 1;
+
+// Draws `n` distinct candidates without replacement and returns their indices
+// in `weights`. Draw i uses random_number(seed, i, total) where total is the
+// number of remaining candidates (uniform) or their remaining weight (stake
+// weighted), then walks the remaining candidates in list order.
+pub fn select_candidates(seed: &[u8; 32], weights: &[u64], n: usize, mode: u8) -> Result<Vec<usize>> {
+    if n > weights.len() {
+        return Err(SolLearnError::NoMinerAvailable.into());
+    }
+
+    let mut remaining: Vec<usize> = (0..weights.len()).collect();
+    let mut selected = Vec::with_capacity(n);
+    for i in 0..n {
+        let pos = match mode {
+            SELECTION_UNIFORM => random_number(seed, i as u64, remaining.len() as u64) as usize,
+            SELECTION_STAKE_WEIGHTED => {
+                let total: u64 = remaining.iter().map(|&j| weights[j]).sum();
+                if total == 0 {
+                    return Err(SolLearnError::NoMinerAvailable.into());
+                }
+
+                let mut r = random_number(seed, i as u64, total);
+                let mut pos = 0;
+                while r >= weights[remaining[pos]] {
+                    r -= weights[remaining[pos]];
+                    pos += 1;
+                }
+                pos
+            }
+            _ => return Err(SolLearnError::InvalidSelectionMode.into()),
+        };
+        selected.push(remaining.remove(pos));
+    }
+
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed_of(k: u64) -> [u8; 32] {
+        derive_seed(k, &[7; 32], &k.to_le_bytes())
+    }
+
+    #[test]
+    fn draws_are_reproducible_from_the_seed() {
+        let weights = [5, 1, 9, 3, 0, 4];
+        for k in 0..100 {
+            let a = select_candidates(&seed_of(k), &weights, 3, SELECTION_STAKE_WEIGHTED).unwrap();
+            let b = select_candidates(&seed_of(k), &weights, 3, SELECTION_STAKE_WEIGHTED).unwrap();
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn selection_is_without_replacement() {
+        let weights = [1, 1_000_000, 1, 1];
+        for mode in [SELECTION_UNIFORM, SELECTION_STAKE_WEIGHTED] {
+            for k in 0..200 {
+                let mut picked = select_candidates(&seed_of(k), &weights, 4, mode).unwrap();
+                picked.sort();
+                assert_eq!(picked, vec![0, 1, 2, 3]);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_stake_is_never_picked_when_weighted() {
+        let weights = [10, 0, 10];
+        for k in 0..500 {
+            let picked = select_candidates(&seed_of(k), &weights, 2, SELECTION_STAKE_WEIGHTED).unwrap();
+            assert!(!picked.contains(&1));
+        }
+    }
+
+    #[test]
+    fn too_few_candidates_is_rejected() {
+        assert!(select_candidates(&seed_of(0), &[1, 1], 3, SELECTION_UNIFORM).is_err());
+        assert!(select_candidates(&seed_of(0), &[0, 0], 1, SELECTION_STAKE_WEIGHTED).is_err());
+        assert!(select_candidates(&seed_of(0), &[1, 1], 1, 9).is_err());
+    }
+
+    // First pick frequencies over 20k seeds must match stake shares
+    // (10% / 30% / 60%) within one percentage point.
+    #[test]
+    fn first_pick_follows_stake_distribution() {
+        let weights = [100, 300, 600];
+        let rounds = 20_000;
+        let mut hits = [0u64; 3];
+        for k in 0..rounds {
+            let picked = select_candidates(&seed_of(k), &weights, 1, SELECTION_STAKE_WEIGHTED).unwrap();
+            hits[picked[0]] += 1;
+        }
+
+        for (i, w) in weights.iter().enumerate() {
+            let expected = rounds * w / 1000;
+            assert!(hits[i].abs_diff(expected) < rounds / 100, "candidate {} hit {} times", i, hits[i]);
+        }
+    }
+
+    #[test]
+    fn uniform_ignores_stake() {
+        let weights = [1, 1_000, 1_000_000];
+        let rounds = 30_000;
+        let mut hits = [0u64; 3];
+        for k in 0..rounds {
+            let picked = select_candidates(&seed_of(k), &weights, 1, SELECTION_UNIFORM).unwrap();
+            hits[picked[0]] += 1;
+        }
+
+        for h in hits {
+            assert!(h.abs_diff(rounds / 3) < rounds / 100);
+        }
+    }
+}
@@ -1,7 +1,14 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+use anchor_lang::Discriminator;
 use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 
-use crate::{DAOTokenPercentage, Tasks};
+use crate::errors::SolLearnError;
+use crate::utils::PERCENTAGE_DENOMINATOR;
+use crate::{
+    DAOTokenPercentage, ForcedUnstake, SlashReason, UnstakeRequest, ANCHOR_DISCRIMINATOR, MAX_ASSIGNMENTS,
+    MAX_UNSTAKE_REQUESTS,
+};
 
 // init pda to store list of models
 #[derive(Accounts)]
@@ -54,7 +61,7 @@ pub struct InitializeExtra<'info> {
 
 #[derive(Accounts)]
 #[instruction(model: Pubkey)]
-pub struct AddModel<'info> {
+pub struct CreateModel<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
     /// CHECK:
@@ -77,12 +84,56 @@ pub struct AddModel<'info> {
         bump
     )]
     pub miners_of_model: Box<Account<'info, MinersOfModel>>,
+    #[account(
+        init, 
+        payer = admin, 
+        space = 8 + ModelInfo::LEN,
+        seeds = [b"model_info", sol_learn_account.key().as_ref(), model.key().as_ref()], 
+        bump
+    )]
+    pub model_info: Box<Account<'info, ModelInfo>>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 #[instruction(model: Pubkey)]
-pub struct RemoveModel<'info> {
+pub struct InitModelInfoVld<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = sol_learn_account.admin == admin.key())]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(seeds = [b"models", sol_learn_account.key().as_ref()], bump)]
+    pub models: Box<Account<'info, Models>>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ModelInfo::LEN,
+        seeds = [b"model_info", sol_learn_account.key().as_ref(), model.key().as_ref()],
+        bump
+    )]
+    pub model_info: Box<Account<'info, ModelInfo>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(model: Pubkey)]
+pub struct UpdateModel<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    /// CHECK:
+    #[account(constraint = sol_learn_account.admin == admin.key())]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        mut,
+        seeds = [b"model_info", sol_learn_account.key().as_ref(), model.key().as_ref()], 
+        bump = model_info.bump
+    )]
+    pub model_info: Box<Account<'info, ModelInfo>>,
+}
+
+#[derive(Accounts)]
+#[instruction(model: Pubkey)]
+pub struct RetireModel<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
     /// CHECK:
@@ -90,7 +141,7 @@ pub struct RemoveModel<'info> {
     pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
     #[account(
         mut,
-        realloc = 8 + 1 + 4 + 8 + 8 + models.data.len() + 32,
+        realloc = 8 + Models::LEN + models.data.len().saturating_sub(32),
         realloc::payer = admin,
         realloc::zero = false,
         // seeds = [b"models", sol_learn_account.key().as_ref()], 
@@ -101,12 +152,37 @@ pub struct RemoveModel<'info> {
         mut, 
         close = admin, 
         seeds = [b"models", sol_learn_account.key().as_ref(), model.key().as_ref()], 
-        bump = miners_of_model.bump
+        bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
     )]
     pub miners_of_model: Box<Account<'info, MinersOfModel>>,
+    #[account(
+        mut, 
+        close = admin, 
+        seeds = [b"model_info", sol_learn_account.key().as_ref(), model.key().as_ref()], 
+        bump = model_info.bump
+    )]
+    pub model_info: Box<Account<'info, ModelInfo>>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct ReassignModel<'info> {
+    pub signer: Signer<'info>,
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        mut,
+        seeds = [b"miner", miner_account.miner.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_account.bump,
+    )]
+    pub miner_account: Account<'info, MinerInfo>,
+    #[account(seeds = [b"models", sol_learn_account.key().as_ref()], bump)]
+    pub models: Box<Account<'info, Models>>,
+    /// CHECK: address is checked against the SlotHashes sysvar id
+    #[account(address = slot_hashes::ID)]
+    pub recent_slothashes: UncheckedAccount<'info>,
+}
+
 #[derive(Accounts)]
 pub struct MinerRegister<'info> {
     #[account(mut)]
@@ -127,6 +203,14 @@ pub struct MinerRegister<'info> {
         bump,
     )]
     pub miner_account: Account<'info, MinerInfo>,
+    #[account(
+        init,
+        payer = miner,
+        space = ANCHOR_DISCRIMINATOR + MinerStats::LEN,
+        seeds = [b"miner_stats", miner.key().as_ref(), sol_learn_account.key().as_ref()],
+        bump,
+    )]
+    pub miner_stats: Box<Account<'info, MinerStats>>,
     #[account(mut)]
     pub miner_staking_wallet: InterfaceAccount<'info, TokenAccount>,
     #[account(
@@ -138,18 +222,61 @@ pub struct MinerRegister<'info> {
     pub vault_staking_wallet: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
     pub staking_token: InterfaceAccount<'info, Mint>,
+    /// CHECK: address is checked against the SlotHashes sysvar id
+    #[account(address = slot_hashes::ID)]
+    pub recent_slothashes: UncheckedAccount<'info>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
     pub sysvar_clock: Sysvar<'info, Clock>,
 }
 
+#[derive(Accounts)]
+pub struct InitMinerStatsVld<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        seeds = [b"miner", miner_account.miner.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_account.bump,
+    )]
+    pub miner_account: Box<Account<'info, MinerInfo>>,
+    #[account(
+        init,
+        payer = signer,
+        space = ANCHOR_DISCRIMINATOR + MinerStats::LEN,
+        seeds = [b"miner_stats", miner_account.miner.as_ref(), sol_learn_account.key().as_ref()],
+        bump,
+    )]
+    pub miner_stats: Box<Account<'info, MinerStats>>,
+    pub system_program: Program<'info, System>,
+}
+
+// the MinerInfo of every miner of the list follows in remaining accounts, in
+// list order
+#[derive(Accounts)]
+#[instruction(model: Pubkey)]
+pub struct MigrateMinersOfModelVld<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    /// CHECK: of the old layout, read by MinersOfModel::legacy_miners
+    #[account(
+        mut,
+        owner = crate::ID,
+        seeds = [b"models", sol_learn_account.key().as_ref(), model.as_ref()],
+        bump,
+    )]
+    pub miners_of_model: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 pub struct Topup<'info> {
     /// CHECK:
     #[account(mut)]
     pub miner: Signer<'info>,
     /// CHECK:
-    #[account()]
+    #[account(mut)]
     pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
     #[account(
         mut,
@@ -157,6 +284,13 @@ pub struct Topup<'info> {
         bump = miner_account.bump,
     )]
     pub miner_account: Account<'info, MinerInfo>,
+    #[account(
+        mut,
+        seeds = [b"models", sol_learn_account.key().as_ref(), miner_account.model.key().as_ref()], 
+        bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
+    )]
+    pub miners_of_model: Box<Account<'info, MinersOfModel>>,
     #[account(mut)]
     pub miner_staking_wallet: InterfaceAccount<'info, TokenAccount>,
     #[account(
@@ -176,7 +310,7 @@ pub struct MinerUnStaking<'info> {
     #[account(mut)]
     pub miner: Signer<'info>,
     /// CHECK:
-    #[account()]
+    #[account(mut)]
     pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
     #[account(
         mut,
@@ -188,6 +322,7 @@ pub struct MinerUnStaking<'info> {
         mut,
         seeds = [b"models", sol_learn_account.key().as_ref(), miner_account.model.key().as_ref()], 
         bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
     )]
     pub miners_of_model: Box<Account<'info, MinersOfModel>>,
     pub system_program: Program<'info, System>,
@@ -199,7 +334,7 @@ pub struct MinerClaimReward<'info> {
     #[account(mut)]
     pub miner: Signer<'info>,
     /// CHECK:
-    #[account()]
+    #[account(mut)]
     pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
     #[account(
         mut,
@@ -209,6 +344,17 @@ pub struct MinerClaimReward<'info> {
     pub miner_account: Account<'info, MinerInfo>,
     #[account(mut)]
     pub miner_staking_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"reward_pool", sol_learn_account.key().as_ref()], 
+        bump = reward_pool_pda.bump,
+    )]
+    pub reward_pool_pda: Account<'info, VaultAccount>,
+    #[account(
+        mut, 
+        constraint = reward_pool_wallet.owner == reward_pool_pda.key(),
+        constraint = reward_pool_wallet.mint == sol_learn_account.token,
+    )]
+    pub reward_pool_wallet: InterfaceAccount<'info, TokenAccount>,
     #[account(
         seeds = [b"vault", sol_learn_account.key().as_ref()], 
         bump = vault_wallet_owner_pda.bump,
@@ -223,12 +369,53 @@ pub struct MinerClaimReward<'info> {
     pub token_program: Interface<'info, TokenInterface>,
 }
 
+#[derive(Accounts)]
+pub struct InitRewardPool<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    /// CHECK:
+    #[account(constraint = sol_learn_account.admin == admin.key())]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        init, 
+        payer = admin, 
+        space = 8 + VaultAccount::LEN,
+        seeds = [b"reward_pool", sol_learn_account.key().as_ref()], 
+        bump
+    )]
+    pub reward_pool_pda: Account<'info, VaultAccount>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundRewardPool<'info> {
+    #[account(mut)]
+    pub funder: Signer<'info>,
+    /// CHECK:
+    #[account()]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(mut)]
+    pub funder_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"reward_pool", sol_learn_account.key().as_ref()], 
+        bump = reward_pool_pda.bump,
+    )]
+    pub reward_pool_pda: Account<'info, VaultAccount>,
+    #[account(
+        mut, 
+        constraint = reward_pool_wallet.owner == reward_pool_pda.key(),
+        constraint = reward_pool_wallet.mint == sol_learn_account.token,
+    )]
+    pub reward_pool_wallet: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
 #[derive(Accounts)]
 pub struct MinerClaim<'info> {
     #[account(mut)]
     pub miner: Signer<'info>,
     /// CHECK:
-    #[account()]
+    #[account(mut)]
     pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
     #[account(
         mut,
@@ -252,10 +439,123 @@ pub struct MinerClaim<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct SetCommission<'info> {
+    pub miner: Signer<'info>,
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        mut,
+        seeds = [b"miner", miner.key().as_ref(), sol_learn_account.key().as_ref()], 
+        bump = miner_account.bump,
+    )]
+    pub miner_account: Account<'info, MinerInfo>,
+}
+
+#[derive(Accounts)]
+pub struct Delegate<'info> {
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+    #[account(mut)]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        mut,
+        seeds = [b"miner", miner_account.miner.as_ref(), sol_learn_account.key().as_ref()], 
+        bump = miner_account.bump,
+    )]
+    pub miner_account: Box<Account<'info, MinerInfo>>,
+    #[account(
+        mut,
+        seeds = [b"models", sol_learn_account.key().as_ref(), miner_account.model.key().as_ref()], 
+        bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
+    )]
+    pub miners_of_model: Box<Account<'info, MinersOfModel>>,
+    #[account(
+        init_if_needed,
+        payer = delegator,
+        space = 8 + Delegation::LEN,
+        seeds = [b"delegation", delegator.key().as_ref(), miner_account.key().as_ref()], 
+        bump,
+    )]
+    pub delegation: Account<'info, Delegation>,
+    #[account(mut)]
+    pub delegator_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"vault", sol_learn_account.key().as_ref()], 
+        bump = vault_wallet_owner_pda.bump,
+    )]
+    pub vault_wallet_owner_pda: Account<'info, VaultAccount>,
+    #[account(mut, constraint = vault_staking_wallet.owner == vault_wallet_owner_pda.key())]
+    pub vault_staking_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub staking_token: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Undelegate<'info> {
+    pub delegator: Signer<'info>,
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        seeds = [b"miner", miner_account.miner.as_ref(), sol_learn_account.key().as_ref()], 
+        bump = miner_account.bump,
+    )]
+    pub miner_account: Box<Account<'info, MinerInfo>>,
+    #[account(
+        mut,
+        seeds = [b"delegation", delegator.key().as_ref(), miner_account.key().as_ref()], 
+        bump = delegation.bump,
+    )]
+    pub delegation: Account<'info, Delegation>,
+    pub sysvar_clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimUndelegated<'info> {
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+    #[account(mut)]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        mut,
+        seeds = [b"miner", miner_account.miner.as_ref(), sol_learn_account.key().as_ref()], 
+        bump = miner_account.bump,
+    )]
+    pub miner_account: Box<Account<'info, MinerInfo>>,
+    #[account(
+        mut,
+        seeds = [b"models", sol_learn_account.key().as_ref(), miner_account.model.key().as_ref()], 
+        bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
+    )]
+    pub miners_of_model: Box<Account<'info, MinersOfModel>>,
+    #[account(
+        mut,
+        seeds = [b"delegation", delegator.key().as_ref(), miner_account.key().as_ref()], 
+        bump = delegation.bump,
+    )]
+    pub delegation: Account<'info, Delegation>,
+    #[account(mut)]
+    pub delegator_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"vault", sol_learn_account.key().as_ref()], 
+        bump = vault_wallet_owner_pda.bump,
+    )]
+    pub vault_wallet_owner_pda: Account<'info, VaultAccount>,
+    #[account(mut, constraint = vault_staking_wallet.owner == vault_wallet_owner_pda.key())]
+    pub vault_staking_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub staking_token: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub sysvar_clock: Sysvar<'info, Clock>,
+}
+
 #[derive(Accounts)]
 pub struct JoinForMinting<'info> {
     #[account(mut)]
     pub miner: Signer<'info>,
+    #[account(mut)]
     pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
     /// CHECK:
     #[account(
@@ -266,11 +566,12 @@ pub struct JoinForMinting<'info> {
     pub miner_account: Account<'info, MinerInfo>,
     #[account(
         mut, 
-        realloc = 8 + 1 + 4 + miners_of_model.data.len() + 32,
+        realloc = 8 + MinersOfModel::LEN + miners_of_model.data.len() + MinersOfModel::ENTRY_LEN,
         realloc::payer = miner,
         realloc::zero = false,
         seeds = [b"models", sol_learn_account.key().as_ref(), miner_account.model.key().as_ref()], 
-        bump = miners_of_model.bump
+        bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
     )]
     pub miners_of_model: Box<Account<'info, MinersOfModel>>,
     pub models: Box<Account<'info, Models>>,
@@ -311,11 +612,104 @@ pub struct SolLearnInfo {
     pub fine_percentage: u16,
     pub dao_token_reward: u64,
     pub dao_token_percentage: DAOTokenPercentage,
+    pub selection_mode: u8,
+    pub total_active_miner: u64,
+    pub epoch_task_completed: u64,
+    pub perf_reward_percentage: u16,
+    // liabilities: stake held for miners and inference value held in escrow
+    // by the vault, rewards owed to miners by the reward pool. They are only
+    // right when counted from initialize on, see version.
+    pub total_stake: u64,
+    pub total_escrow: u64,
+    pub unclaimed_rewards: u64,
+    pub crank_bounty: u64,
+    // share of fines refunded to the inference creator, the rest goes to the treasury
+    pub fine_creator_percentage: u16,
+    pub fine_model: u8,
+    // fines wait dispute_window seconds in the vault before they are paid out
+    // (0 pays them at once), a dispute costs dispute_bond and is settled by
+    // the arbiter or the admin
+    pub dispute_window: u64,
+    pub dispute_bond: u64,
+    pub arbiter: Pubkey,
+    // an inconclusive vote starts up to escalation_rounds new rounds, each
+    // with escalation_validators more validators than the last. Only the
+    // round that agrees shares inference.value: validators that revealed in
+    // an inconclusive round are not paid, nor when the inference is refunded
+    pub escalation_rounds: u8,
+    pub escalation_validators: u8,
+    // mint of the DAO token, its mint authority is the vault PDA; nothing is
+    // minted while unset
+    pub dao_token: Pubkey,
+    // DAO token emission limits, 0 for none. Amounts are counted when an
+    // inference is resolved, before the crank mints them. The max emission
+    // caps what this program mints in total, not the supply of the mint:
+    // tokens minted before the mint was set or by anyone else do not count.
+    pub dao_token_max_emission: u64,
+    pub dao_token_epoch_budget: u64,
+    pub dao_token_minted: u64,
+    pub dao_token_epoch_minted: u64,
+    // epoch dao_token_epoch_minted is counted for
+    pub dao_token_budget_epoch: u64,
+    // part of a miner's selection weight scaled by its quality score, in
+    // basis points; 0 leaves selection to the stake alone
+    pub quality_weight: u16,
+    // miners that joined during the current epoch, they share its reward
+    // only from the next one
+    pub epoch_joined_miners: u64,
+    // SOL_LEARN_INFO_VERSION, set by initialize. An account of an older
+    // layout is shorter and does not load; the liability checks refuse any
+    // other version, so liabilities that were not counted from initialize on
+    // are never trusted.
+    pub version: u8,
 }
 
+// layout counting the liabilities, and every field added after them
+pub const SOL_LEARN_INFO_VERSION: u8 = 1;
+
 impl SolLearnInfo {
     pub const LEN: usize = 32 + 32 + 8 * 10
-            + 8 * 11 + 1 + 2 * 10 + 32 * 2;
+            + 8 * 11 + 1 + 2 * 10 + 32 * 2
+            + 1
+            + 8 * 2 + 2
+            + 8 * 3
+            + 8
+            + 2
+            + 1
+            + 8 * 2 + 32
+            + 1 + 1
+            + 32
+            + 8 * 5
+            + 2
+            + 8
+            + 1;
+
+    // restarts the epoch count of the DAO token once last_epoch moves on
+    pub fn roll_dao_token_epoch(&mut self) {
+        if self.dao_token_budget_epoch != self.last_epoch {
+            self.dao_token_budget_epoch = self.last_epoch;
+            self.dao_token_epoch_minted = 0;
+        }
+    }
+
+    // DAO token that may still be minted under the max emission and the
+    // budget of the epoch, see roll_dao_token_epoch
+    pub fn dao_token_mintable(&self) -> u64 {
+        let left = |limit: u64, minted: u64| {
+            if limit == 0 {
+                u64::MAX
+            } else {
+                limit.saturating_sub(minted)
+            }
+        };
+        left(self.dao_token_max_emission, self.dao_token_minted)
+            .min(left(self.dao_token_epoch_budget, self.dao_token_epoch_minted))
+    }
+
+    pub fn record_dao_token_minted(&mut self, amount: u64) {
+        self.dao_token_minted += amount;
+        self.dao_token_epoch_minted += amount;
+    }
 }
 
 #[account]
@@ -330,10 +724,359 @@ pub struct MinerInfo {
     pub is_active: bool,
     pub unstaking_time: u64,
     pub reward: u64,
+    pub deactivated_epoch: u64,
+    pub task_history: [EpochTasks; TASK_HISTORY_LEN],
+    pub unstake_requests: [UnstakeRequest; MAX_UNSTAKE_REQUESTS],
+    // cut of the delegators' rewards kept by the miner, in basis points
+    pub commission: u16,
+    // stake delegated to the miner and the delegation shares it backs
+    pub delegated_stake: u64,
+    pub delegation_shares: u64,
+    // number of slash records of the miner, seeds the next one
+    pub slash_count: u64,
+    // fined slashes so far, raises the fine under FINE_ESCALATING
+    pub offence_count: u64,
+    // rolling rating of the inferences the miner solved, see rolling_quality
+    pub quality_score: u16,
+    pub rating_count: u64,
+    // epoch of the last join, the miner shares epoch rewards after it
+    pub joined_epoch: u64,
+}
+
+// how a fine is computed from fine_percentage
+pub const FINE_FIXED: u8 = 0; // of the minimum stake
+pub const FINE_PROPORTIONAL: u8 = 1; // of the stake the miner can be fined from
+pub const FINE_ESCALATING: u8 = 2; // fixed, times the number of the offence
+
+// number of not yet claimed epochs a miner keeps task counts for
+pub const TASK_HISTORY_LEN: usize = 8;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct EpochTasks {
+    pub epoch: u64,
+    pub count: u64,
+}
+
+impl EpochTasks {
+    pub const LEN: usize = 8 + 8;
 }
 
 impl MinerInfo {
-    pub const LEN: usize = 1 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 8;
+    pub const LEN: usize = 1 + 32 + 32 + 8 + 8 + 8 + 8 + 1 + 8 + 8
+            + 8 + EpochTasks::LEN * TASK_HISTORY_LEN
+            + UnstakeRequest::LEN * MAX_UNSTAKE_REQUESTS
+            + 2 + 8 + 8
+            + 8 + 8
+            + 2 + 8
+            + 8;
+
+    // Counts one completed task in `epoch`. Entries are kept oldest first;
+    // when every slot holds an unclaimed epoch the task is not recorded and
+    // false is returned, the miner has to claim its rewards to free a slot.
+    pub fn record_task(&mut self, epoch: u64) -> bool {
+        let used = self.task_history.iter().filter(|t| t.count > 0).count();
+        if used > 0 && self.task_history[used - 1].epoch == epoch {
+            self.task_history[used - 1].count += 1;
+            return true;
+        }
+        if used == TASK_HISTORY_LEN {
+            return false;
+        }
+
+        self.task_history[used] = EpochTasks { epoch, count: 1 };
+        true
+    }
+
+    // Removes and returns the tasks recorded up to and including `epoch`.
+    pub fn take_tasks(&mut self, epoch: u64) -> u64 {
+        let mut tasks = 0;
+        let mut kept = [EpochTasks::default(); TASK_HISTORY_LEN];
+        let mut used = 0;
+        for t in self.task_history.iter().filter(|t| t.count > 0) {
+            if t.epoch <= epoch {
+                tasks += t.count;
+            } else {
+                kept[used] = *t;
+                used += 1;
+            }
+        }
+        self.task_history = kept;
+
+        tasks
+    }
+
+    // Oldest epoch with recorded tasks, if any.
+    pub fn first_task_epoch(&self) -> Option<u64> {
+        self.task_history
+            .iter()
+            .filter(|t| t.count > 0)
+            .map(|t| t.epoch)
+            .min()
+    }
+
+    // Queues `amount` for withdrawal once `unlock_time` has passed.
+    pub fn push_unstake_request(&mut self, amount: u64, unlock_time: u64) -> Result<()> {
+        UnstakeRequest::push(&mut self.unstake_requests, amount, unlock_time)
+    }
+
+    // Stake queued for withdrawal and not claimed yet.
+    pub fn pending_unstake(&self) -> u64 {
+        self.unstake_requests.iter().map(|r| r.amount).sum()
+    }
+
+    // Removes and returns the requests unlocked at `now`.
+    pub fn take_matured_unstakes(&mut self, now: u64) -> u64 {
+        UnstakeRequest::take_matured(&mut self.unstake_requests, now)
+    }
+
+    // Moves every pending request back into the stake.
+    pub fn cancel_unstakes(&mut self) {
+        self.stake_amount += self.pending_unstake();
+        self.unstake_requests = [UnstakeRequest::default(); MAX_UNSTAKE_REQUESTS];
+    }
+
+    // Takes up to `amount` from the stake, then from pending requests
+    // newest first, so unstaking does not dodge a fine.
+    pub fn take_stake(&mut self, amount: u64) -> u64 {
+        let mut taken = amount.min(self.stake_amount);
+        self.stake_amount -= taken;
+
+        let mut requests: Vec<&mut UnstakeRequest> =
+            self.unstake_requests.iter_mut().filter(|r| r.amount > 0).collect();
+        requests.sort_by_key(|r| std::cmp::Reverse(r.unlock_time));
+        for r in requests {
+            let part = (amount - taken).min(r.amount);
+            r.amount -= part;
+            taken += part;
+        }
+        taken
+    }
+
+    // Stake used for weighted selection, the miner's own plus delegations.
+    pub fn weight(&self) -> u64 {
+        self.stake_amount + self.delegated_stake
+    }
+
+    // Weight kept in MinersOfModel: `quality_weight` of the stake follows the
+    // quality score, a miner not rated yet keeps its whole stake.
+    pub fn selection_weight(&self, quality_weight: u16) -> u64 {
+        if self.rating_count == 0 {
+            return self.weight();
+        }
+        let quality_weight = u128::from(quality_weight);
+        let factor = (PERCENTAGE_DENOMINATOR as u128 - quality_weight) * MAX_QUALITY_SCORE as u128
+            + quality_weight * u128::from(self.quality_score);
+        (self.weight() as u128 * factor / (PERCENTAGE_DENOMINATOR as u128 * MAX_QUALITY_SCORE as u128)) as u64
+    }
+
+    // Part of `amount` earned by the delegators, pro rata to the delegated
+    // part of the stake and after the miner's commission.
+    pub fn delegators_cut(&self, amount: u64) -> u64 {
+        let weight = self.weight();
+        if self.delegated_stake == 0 || weight == 0 {
+            return 0;
+        }
+        let share = (amount as u128 * self.delegated_stake as u128 / weight as u128) as u64;
+        share - (share as u128 * self.commission as u128 / PERCENTAGE_DENOMINATOR as u128) as u64
+    }
+
+    // Value of `shares` in delegated stake.
+    pub fn delegation_value(&self, shares: u64) -> u64 {
+        if self.delegation_shares == 0 {
+            return 0;
+        }
+        (shares as u128 * self.delegated_stake as u128 / self.delegation_shares as u128) as u64
+    }
+
+    // Adds `amount` to the delegated stake and returns the shares minted for
+    // it at the current share value.
+    pub fn add_delegation(&mut self, amount: u64) -> Result<u64> {
+        let shares = if self.delegation_shares == 0 {
+            amount
+        } else if self.delegated_stake == 0 {
+            return Err(SolLearnError::DelegationDrained.into());
+        } else {
+            (amount as u128 * self.delegation_shares as u128 / self.delegated_stake as u128) as u64
+        };
+        if shares == 0 {
+            return Err(SolLearnError::StakingZeroValue.into());
+        }
+        self.delegated_stake += amount;
+        self.delegation_shares += shares;
+        Ok(shares)
+    }
+
+    // Burns `shares` and returns the delegated stake they were worth.
+    pub fn remove_delegation(&mut self, shares: u64) -> u64 {
+        let amount = self.delegation_value(shares);
+        self.delegated_stake -= amount;
+        self.delegation_shares -= shares;
+        amount
+    }
+
+    // Own stake, pending requests included, and delegated stake, all of which
+    // a fine can take from.
+    pub fn slashable_stake(&self) -> u64 {
+        self.stake_amount + self.pending_unstake() + self.delegated_stake
+    }
+
+    // Fine for the next offence under `fine_model`.
+    pub fn fine(&self, fine_model: u8, min_stake: u64, fine_percentage: u16) -> u64 {
+        let percentage = fine_percentage as u128;
+        let fixed = min_stake as u128 * percentage / PERCENTAGE_DENOMINATOR as u128;
+        let fine = match fine_model {
+            FINE_PROPORTIONAL => {
+                self.slashable_stake() as u128 * percentage / PERCENTAGE_DENOMINATOR as u128
+            }
+            FINE_ESCALATING => fixed * (self.offence_count as u128 + 1),
+            _ => fixed,
+        };
+        fine.min(u64::MAX as u128) as u64
+    }
+
+    // Queues the whole stake for withdrawal, into the newest request when
+    // every slot is taken, and returns the request it went to.
+    pub fn force_unstake(&mut self, unlock_time: u64) -> ForcedUnstake {
+        let amount = self.stake_amount;
+        if amount == 0 {
+            return ForcedUnstake::default();
+        }
+        self.stake_amount = 0;
+        let slot = match self.unstake_requests.iter().position(|r| r.amount == 0) {
+            Some(free) => free,
+            None => (0..MAX_UNSTAKE_REQUESTS)
+                .max_by_key(|&i| self.unstake_requests[i].unlock_time)
+                .unwrap_or_default(),
+        };
+        let request = &mut self.unstake_requests[slot];
+        request.amount += amount;
+        request.unlock_time = unlock_time;
+        self.unstaking_time = unlock_time;
+        ForcedUnstake {
+            slot: slot as u8,
+            amount,
+            unlock_time,
+        }
+    }
+
+    // Takes up to `amount` pro rata from the miner's own stake, pending
+    // requests included, and from the stake delegated to it.
+    pub fn take_fine(&mut self, amount: u64) -> u64 {
+        let backing = self.slashable_stake();
+        if backing == 0 {
+            return 0;
+        }
+        let delegated = ((amount as u128 * self.delegated_stake as u128 / backing as u128) as u64)
+            .min(self.delegated_stake);
+        self.delegated_stake -= delegated;
+        delegated + self.take_stake(amount - delegated)
+    }
+
+    // Gives back a fine overturned on dispute, `delegated` of it to the
+    // delegators and the rest as own stake.
+    pub fn restore_fine(&mut self, amount: u64, delegated: u64) {
+        self.delegated_stake += delegated;
+        self.stake_amount += amount - delegated;
+        self.offence_count = self.offence_count.saturating_sub(1);
+    }
+
+    // Takes the unstake forced by an overturned fine back into the stake and
+    // returns how much came back. Only the request `forced` went to counts,
+    // so later requests stay queued; what was claimed already, or taken by a
+    // later fine, stays gone.
+    pub fn cancel_forced_unstake(&mut self, forced: &ForcedUnstake) -> u64 {
+        let Some(request) = self.unstake_requests.get_mut(forced.slot as usize) else {
+            return 0;
+        };
+        if request.amount == 0 || request.unlock_time != forced.unlock_time {
+            return 0;
+        }
+        let taken = request.amount.min(forced.amount);
+        request.amount -= taken;
+        if request.amount == 0 {
+            *request = UnstakeRequest::default();
+        }
+        self.stake_amount += taken;
+        if self.pending_unstake() == 0 {
+            self.unstaking_time = 0;
+        }
+        taken
+    }
+}
+
+// one per delegator and miner, shares stay in the miner's pool until the
+// undelegation is claimed so they keep earning and being fined meanwhile
+#[account]
+pub struct Delegation {
+    pub bump: u8,
+    pub delegator: Pubkey,
+    pub miner: Pubkey,
+    pub shares: u64,
+    // undelegated shares, each request unlocks on its own
+    pub unstake_requests: [UnstakeRequest; MAX_UNSTAKE_REQUESTS],
+}
+
+impl Delegation {
+    pub const LEN: usize = 1 + 32 + 32 + 8 + UnstakeRequest::LEN * MAX_UNSTAKE_REQUESTS;
+
+    // Shares queued for withdrawal and not claimed yet.
+    pub fn pending_shares(&self) -> u64 {
+        self.unstake_requests.iter().map(|r| r.amount).sum()
+    }
+}
+
+// performance history of a miner, seeded by [b"miner_stats", miner, sol_learn_account]
+#[account]
+pub struct MinerStats {
+    pub bump: u8,
+    pub miner: Pubkey,
+    pub seized: u64,
+    pub submitted: u64,
+    pub revealed: u64,
+    pub wins: u64,
+    pub slashes: u64,
+    // slashes by reason, admin and legacy slashes only count in `slashes`
+    pub submit_timeouts: u64,
+    pub missed_commits: u64,
+    pub missed_reveals: u64,
+    pub wrong_results: u64,
+    // rolling share of paid outcomes in basis points, see record_outcome
+    pub success_rate: u16,
+    pub outcome_count: u64,
+    pub last_active_slot: u64,
+}
+
+impl MinerStats {
+    pub const LEN: usize = 1 + 32 + 8 * 9 + 2 + 8 + 8;
+
+    // Rolls a paid (or slashed) assignment into the success rate the same way
+    // ratings roll into the quality score; the first outcome sets it.
+    pub fn record_outcome(&mut self, success: bool) {
+        let sample = if success { PERCENTAGE_DENOMINATOR } else { 0 };
+        self.success_rate = if self.outcome_count == 0 {
+            sample as u16
+        } else {
+            ((u64::from(self.success_rate) * (QUALITY_WINDOW - 1) + sample) / QUALITY_WINDOW) as u16
+        };
+        self.outcome_count += 1;
+    }
+
+    pub fn record_win(&mut self) {
+        self.wins += 1;
+        self.record_outcome(true);
+    }
+
+    pub fn record_slash(&mut self, reason: SlashReason) {
+        self.slashes += 1;
+        match reason {
+            SlashReason::SubmitTimeout => self.submit_timeouts += 1,
+            SlashReason::CommitTimeout => self.missed_commits += 1,
+            SlashReason::RevealTimeout => self.missed_reveals += 1,
+            SlashReason::WrongResult => self.wrong_results += 1,
+            SlashReason::Admin | SlashReason::Legacy => {}
+        }
+        self.record_outcome(false);
+    }
 }
 
 #[account]
@@ -355,16 +1098,175 @@ pub struct Models {
 
 impl Models {
     pub const LEN: usize = 1 + 4 + 8 + 8;
+
+    pub fn count(&self) -> usize {
+        self.data.len() / 32
+    }
+
+    pub fn model_at(&self, index: usize) -> Pubkey {
+        Pubkey::try_from(&self.data[index * 32..(index + 1) * 32]).unwrap()
+    }
+
+    pub fn position(&self, model: &Pubkey) -> Option<usize> {
+        self.data.chunks(32).position(|chunk| chunk == model.as_ref())
+    }
+
+    pub fn push(&mut self, model: &Pubkey) {
+        self.data.extend_from_slice(model.as_ref());
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.data.drain(index * 32..(index + 1) * 32);
+    }
+}
+
+// per model configuration, seeded by [b"model_info", sol_learn_account, model].
+// Models added by the old add_model get theirs from init_model_info.
+#[account]
+pub struct ModelInfo {
+    pub bump: u8,
+    pub model: Pubkey,
+    pub minimum_fee: u64,
+    pub tier: u32,
+    pub miner_requirement: u8,
+    pub submit_duration: u64,
+    pub commit_duration: u64,
+    pub reveal_duration: u64,
+    pub enabled: bool,
+    // COMPARATOR_* of comparator.rs, the tolerance is for numeric outputs
+    pub comparator: u8,
+    pub tolerance: u64,
+    // rolling rating of the model's inferences, see rolling_quality
+    pub quality_score: u16,
+    pub rating_count: u64,
+}
+
+impl ModelInfo {
+    pub const LEN: usize = 1 + 32 + 8 + 4 + 1 + 8 * 3 + 1 + 1 + 8 + 2 + 8;
+
+    pub fn set_params(&mut self, params: &ModelParams) -> Result<()> {
+        if params.miner_requirement == 0 || params.miner_requirement as usize > MAX_ASSIGNMENTS {
+            return Err(SolLearnError::InvalidMinerRequirement.into());
+        }
+
+        self.minimum_fee = params.minimum_fee;
+        self.tier = params.tier;
+        self.miner_requirement = params.miner_requirement;
+        self.submit_duration = params.submit_duration;
+        self.commit_duration = params.commit_duration;
+        self.reveal_duration = params.reveal_duration;
+        Ok(())
+    }
+}
+
+// settings of a model given to create_model and update_model
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ModelParams {
+    pub minimum_fee: u64,
+    pub tier: u32,
+    pub miner_requirement: u8,
+    pub submit_duration: u64,
+    pub commit_duration: u64,
+    pub reveal_duration: u64,
+}
+
+// Scores given by rate_inference run from 1 to MAX_INFERENCE_SCORE, quality
+// scores are kept in basis points of the top score.
+pub const MAX_INFERENCE_SCORE: u8 = 10;
+pub const MAX_QUALITY_SCORE: u16 = 10_000;
+// a rating moves the quality score by 1 / QUALITY_WINDOW of the difference
+pub const QUALITY_WINDOW: u64 = 8;
+
+// Quality score after one more rating of `score`; the first rating sets it.
+pub fn rolling_quality(quality_score: u16, rating_count: u64, score: u8) -> u16 {
+    let sample = u64::from(score) * u64::from(MAX_QUALITY_SCORE) / u64::from(MAX_INFERENCE_SCORE);
+    if rating_count == 0 {
+        return sample as u16;
+    }
+    ((u64::from(quality_score) * (QUALITY_WINDOW - 1) + sample) / QUALITY_WINDOW) as u16
 }
 
 #[account]
 pub struct MinersOfModel {
     pub bump: u8, 
+    // MINERS_OF_MODEL_VERSION, checked wherever the list is loaded
+    pub version: u8,
     pub data: Vec<u8>,
 }
 
+// layout with selection weights; the lists before it have no version and
+// key only entries, migrate_miners_of_model rewrites them
+pub const MINERS_OF_MODEL_VERSION: u8 = 1;
+
+// each entry is the miner key followed by its selection weight (u64, little
+// endian, see MinerInfo::selection_weight) so stake weighted selection does
+// not need to load every MinerInfo
 impl MinersOfModel {
-    pub const LEN: usize = 1 + 4;
+    pub const LEN: usize = 1 + 1 + 4;
+    pub const ENTRY_LEN: usize = 32 + 8;
+
+    // Bump and miner keys of a list of the old layout: discriminator, bump,
+    // then a Vec<u8> of 32 byte keys. The low byte of that length, a multiple
+    // of 32, lies where `version` is now, so a current list never reads as an
+    // old one.
+    pub fn legacy_miners(data: &[u8]) -> Result<(u8, Vec<Pubkey>)> {
+        if data.len() < 8 + 1 + 4
+            || data[..8] != Self::DISCRIMINATOR
+            || data[9] == MINERS_OF_MODEL_VERSION
+        {
+            return Err(SolLearnError::AlreadyMigrated.into());
+        }
+        let len = u32::from_le_bytes(data[9..13].try_into().unwrap()) as usize;
+        let keys = data
+            .get(13..13 + len)
+            .ok_or(SolLearnError::AlreadyMigrated)?
+            .chunks_exact(32);
+        if !keys.remainder().is_empty() {
+            return Err(SolLearnError::AlreadyMigrated.into());
+        }
+        let miners = keys.map(|key| Pubkey::try_from(key).unwrap()).collect();
+
+        Ok((data[8], miners))
+    }
+
+    pub fn count(&self) -> usize {
+        self.data.len() / Self::ENTRY_LEN
+    }
+
+    pub fn miner_at(&self, index: usize) -> Pubkey {
+        let start = index * Self::ENTRY_LEN;
+        Pubkey::try_from(&self.data[start..start + 32]).unwrap()
+    }
+
+    pub fn stake_at(&self, index: usize) -> u64 {
+        let start = index * Self::ENTRY_LEN + 32;
+        u64::from_le_bytes(self.data[start..start + 8].try_into().unwrap())
+    }
+
+    pub fn stakes(&self) -> Vec<u64> {
+        (0..self.count()).map(|i| self.stake_at(i)).collect()
+    }
+
+    pub fn position(&self, miner: &Pubkey) -> Option<usize> {
+        self.data
+            .chunks(Self::ENTRY_LEN)
+            .position(|entry| &entry[..32] == miner.as_ref())
+    }
+
+    pub fn push(&mut self, miner: &Pubkey, stake: u64) {
+        self.data.extend_from_slice(miner.as_ref());
+        self.data.extend_from_slice(&stake.to_le_bytes());
+    }
+
+    pub fn set_stake(&mut self, index: usize, stake: u64) {
+        let start = index * Self::ENTRY_LEN + 32;
+        self.data[start..start + 8].copy_from_slice(&stake.to_le_bytes());
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.data
+            .drain(index * Self::ENTRY_LEN..(index + 1) * Self::ENTRY_LEN);
+    }
 }
 
 #[account]
@@ -400,4 +1302,416 @@ pub struct MinerReJoin {
 pub struct MinerTopup {
     pub miner: Pubkey,
     pub amount: u64,
-}
\ No newline at end of file
+}
+
+#[event]
+pub struct MinerUnstakeRequested {
+    pub miner: Pubkey,
+    pub amount: u64,
+    pub unlock_time: u64,
+}
+
+#[event]
+pub struct MinerUnstakeClaimed {
+    pub miner: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CommissionUpdated {
+    pub miner: Pubkey,
+    pub commission: u16,
+}
+
+#[event]
+pub struct Delegated {
+    pub delegator: Pubkey,
+    pub miner: Pubkey,
+    pub amount: u64,
+    pub shares: u64,
+}
+
+#[event]
+pub struct UndelegationRequested {
+    pub delegator: Pubkey,
+    pub miner: Pubkey,
+    pub shares: u64,
+    pub unlock_time: u64,
+}
+
+#[event]
+pub struct UndelegationClaimed {
+    pub delegator: Pubkey,
+    pub miner: Pubkey,
+    pub shares: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct DelegatorsRewarded {
+    pub miner: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct NewModel {
+    pub model: Pubkey,
+    pub minimum_fee: u64,
+    pub tier: u32,
+    pub miner_requirement: u8,
+}
+
+#[event]
+pub struct ModelUpdated {
+    pub model: Pubkey,
+    pub minimum_fee: u64,
+    pub tier: u32,
+    pub miner_requirement: u8,
+    pub submit_duration: u64,
+    pub commit_duration: u64,
+    pub reveal_duration: u64,
+}
+
+#[event]
+pub struct ModelEnabledUpdated {
+    pub model: Pubkey,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct ModelComparatorUpdated {
+    pub model: Pubkey,
+    pub comparator: u8,
+    pub tolerance: u64,
+}
+
+#[event]
+pub struct ModelRetired {
+    pub model: Pubkey,
+}
+
+#[event]
+pub struct MinerModelReassigned {
+    pub miner: Pubkey,
+    pub old_model: Pubkey,
+    pub model: Pubkey,
+}
+
+#[event]
+pub struct EscalationUpdated {
+    pub new_escalation_rounds: u8,
+    pub new_escalation_validators: u8,
+}
+
+#[event]
+pub struct FineModelUpdated {
+    pub new_fine_model: u8,
+}
+
+#[event]
+pub struct MinerStakeExhausted {
+    pub miner: Pubkey,
+    pub unstaked: u64,
+    pub unlock_time: u64,
+}
+
+#[event]
+pub struct SelectionModeUpdated {
+    pub new_selection_mode: u8,
+}
+
+#[event]
+pub struct EpochUpdated {
+    pub epoch_id: u64,
+    pub epoch_reward: u64,
+    pub perf_reward: u64,
+    pub total_miner: u64,
+    pub total_task_completed: u64,
+}
+
+#[event]
+pub struct MinerRewardClaimed {
+    pub miner: Pubkey,
+    pub reward: u64,
+    pub last_epoch: u64,
+}
+
+#[event]
+pub struct PerfRewardPercentageUpdated {
+    pub new_perf_reward_percentage: u16,
+}
+
+#[event]
+pub struct RewardPoolFunded {
+    pub funder: Pubkey,
+    pub amount: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn miner(stake_amount: u64) -> MinerInfo {
+        MinerInfo {
+            bump: 0,
+            miner: Pubkey::new_unique(),
+            model: Pubkey::new_unique(),
+            model_index: 0,
+            stake_amount,
+            last_epoch: 0,
+            active_time: 0,
+            is_active: true,
+            unstaking_time: 0,
+            reward: 0,
+            deactivated_epoch: 0,
+            task_history: [EpochTasks::default(); TASK_HISTORY_LEN],
+            unstake_requests: [UnstakeRequest::default(); MAX_UNSTAKE_REQUESTS],
+            commission: 0,
+            delegated_stake: 0,
+            delegation_shares: 0,
+            slash_count: 0,
+            offence_count: 0,
+            quality_score: 0,
+            rating_count: 0,
+            joined_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn full_task_history_refuses_new_epochs() {
+        let mut m = miner(0);
+        for epoch in 0..TASK_HISTORY_LEN as u64 {
+            assert!(m.record_task(epoch));
+        }
+        assert!(m.record_task(TASK_HISTORY_LEN as u64 - 1));
+        assert!(!m.record_task(TASK_HISTORY_LEN as u64));
+        assert_eq!(m.first_task_epoch(), Some(0));
+
+        assert_eq!(m.take_tasks(0), 1);
+        assert!(m.record_task(TASK_HISTORY_LEN as u64));
+    }
+
+    #[test]
+    fn undelegated_shares_keep_their_own_unlock_time() {
+        let mut d = Delegation::deserialize(&mut &[0u8; Delegation::LEN][..]).unwrap();
+        d.shares = 100;
+        UnstakeRequest::push(&mut d.unstake_requests, 30, 100).unwrap();
+        UnstakeRequest::push(&mut d.unstake_requests, 20, 200).unwrap();
+
+        assert_eq!(UnstakeRequest::take_matured(&mut d.unstake_requests, 150), 30);
+        assert_eq!(d.pending_shares(), 20);
+        assert_eq!(UnstakeRequest::take_matured(&mut d.unstake_requests, 199), 0);
+    }
+
+    #[test]
+    fn claims_only_matured_requests() {
+        let mut m = miner(0);
+        m.push_unstake_request(10, 100).unwrap();
+        m.push_unstake_request(20, 200).unwrap();
+        m.push_unstake_request(30, 100).unwrap();
+
+        assert_eq!(m.take_matured_unstakes(99), 0);
+        assert_eq!(m.take_matured_unstakes(150), 40);
+        assert_eq!(m.pending_unstake(), 20);
+        assert_eq!(m.take_matured_unstakes(200), 20);
+        assert_eq!(m.pending_unstake(), 0);
+    }
+
+    #[test]
+    fn dao_token_emission_stops_at_the_tighter_limit() {
+        let mut acc = SolLearnInfo::deserialize(&mut &[0u8; SolLearnInfo::LEN][..]).unwrap();
+        assert_eq!(acc.dao_token_mintable(), u64::MAX);
+
+        acc.dao_token_max_emission = 1_000;
+        acc.dao_token_epoch_budget = 300;
+        acc.record_dao_token_minted(200);
+        assert_eq!(acc.dao_token_mintable(), 100);
+        acc.record_dao_token_minted(100);
+        assert_eq!(acc.dao_token_mintable(), 0);
+
+        // a new epoch restores the budget, the emission cap still counts
+        acc.last_epoch = 1;
+        assert_eq!(acc.dao_token_mintable(), 0);
+        acc.roll_dao_token_epoch();
+        assert_eq!(acc.dao_token_mintable(), 300);
+        acc.dao_token_max_emission = 400;
+        assert_eq!(acc.dao_token_mintable(), 100);
+    }
+
+    #[test]
+    fn outcomes_roll_into_the_success_rate() {
+        let mut stats = MinerStats::deserialize(&mut &[0u8; MinerStats::LEN][..]).unwrap();
+        stats.record_win();
+        assert_eq!(stats.success_rate, 10_000);
+
+        stats.record_slash(SlashReason::RevealTimeout);
+        assert_eq!(stats.success_rate, 8_750);
+        assert_eq!((stats.wins, stats.slashes, stats.missed_reveals), (1, 1, 1));
+        assert_eq!(stats.outcome_count, 2);
+    }
+
+    #[test]
+    fn ratings_roll_into_the_quality_score() {
+        assert_eq!(rolling_quality(0, 0, 8), 8_000);
+        // one eighth of the way to the new rating
+        assert_eq!(rolling_quality(8_000, 1, 10), 8_250);
+        assert_eq!(rolling_quality(8_000, 5, 0), 7_000);
+    }
+
+    #[test]
+    fn quality_weight_scales_rated_miners_only() {
+        let mut m = miner(1_000);
+        assert_eq!(m.selection_weight(10_000), 1_000);
+
+        m.rating_count = 1;
+        m.quality_score = 6_000;
+        assert_eq!(m.selection_weight(0), 1_000);
+        assert_eq!(m.selection_weight(5_000), 800);
+        assert_eq!(m.selection_weight(10_000), 600);
+    }
+
+    #[test]
+    fn request_slots_are_reused() {
+        let mut m = miner(0);
+        for i in 0..MAX_UNSTAKE_REQUESTS as u64 {
+            m.push_unstake_request(1, i).unwrap();
+        }
+        assert!(m.push_unstake_request(1, 0).is_err());
+        m.take_matured_unstakes(0);
+        m.push_unstake_request(5, 9).unwrap();
+        assert_eq!(m.pending_unstake(), MAX_UNSTAKE_REQUESTS as u64 - 1 + 5);
+    }
+
+    #[test]
+    fn fines_reach_pending_requests_newest_first() {
+        let mut m = miner(10);
+        m.push_unstake_request(20, 100).unwrap();
+        m.push_unstake_request(30, 200).unwrap();
+
+        assert_eq!(m.take_stake(45), 45);
+        assert_eq!(m.stake_amount, 0);
+        assert_eq!(m.unstake_requests[0].amount, 15);
+        assert_eq!(m.unstake_requests[1].amount, 0);
+        // a fine larger than everything takes what is left
+        assert_eq!(m.take_stake(100), 15);
+        assert_eq!(m.pending_unstake(), 0);
+    }
+
+    #[test]
+    fn cancel_moves_requests_back_into_the_stake() {
+        let mut m = miner(0);
+        m.push_unstake_request(7, 100).unwrap();
+        m.push_unstake_request(8, 100).unwrap();
+        m.cancel_unstakes();
+        assert_eq!(m.stake_amount, 15);
+        assert_eq!(m.pending_unstake(), 0);
+    }
+
+    #[test]
+    fn delegation_shares_follow_rewards_and_fines() {
+        let mut m = miner(100);
+        assert_eq!(m.add_delegation(100).unwrap(), 100);
+        // rewards raise the value of every share
+        m.delegated_stake += 100;
+        assert_eq!(m.add_delegation(100).unwrap(), 50);
+        assert_eq!(m.delegation_value(100), 200);
+
+        // a fine is split pro rata with the miner's own stake
+        assert_eq!(m.take_fine(40), 40);
+        assert_eq!(m.stake_amount, 90);
+        assert_eq!(m.delegated_stake, 270);
+        assert_eq!(m.remove_delegation(50), 90);
+        assert_eq!(m.weight(), 270);
+    }
+
+    #[test]
+    fn delegators_cut_is_pro_rata_after_commission() {
+        let mut m = miner(300);
+        assert_eq!(m.delegators_cut(1000), 0);
+        m.add_delegation(100).unwrap();
+        assert_eq!(m.delegators_cut(1000), 250);
+        m.commission = 10_00;
+        assert_eq!(m.delegators_cut(1000), 225);
+    }
+
+    #[test]
+    fn drained_delegation_rejects_new_delegators() {
+        let mut m = miner(0);
+        m.add_delegation(50).unwrap();
+        assert_eq!(m.take_fine(80), 50);
+        assert!(m.add_delegation(10).is_err());
+        assert_eq!(m.remove_delegation(50), 0);
+        assert_eq!(m.add_delegation(10).unwrap(), 10);
+    }
+
+    #[test]
+    fn fine_models() {
+        let mut m = miner(3_000);
+        m.add_delegation(1_000).unwrap();
+        assert_eq!(m.fine(FINE_FIXED, 1_000, 10_00), 100);
+        assert_eq!(m.fine(FINE_PROPORTIONAL, 1_000, 10_00), 400);
+        assert_eq!(m.fine(FINE_ESCALATING, 1_000, 10_00), 100);
+        m.offence_count = 2;
+        assert_eq!(m.fine(FINE_ESCALATING, 1_000, 10_00), 300);
+    }
+
+    #[test]
+    fn forced_unstake_queues_the_whole_stake() {
+        let mut m = miner(40);
+        for i in 0..MAX_UNSTAKE_REQUESTS as u64 {
+            m.push_unstake_request(1, i).unwrap();
+        }
+        // no free slot, the newest request takes it
+        m.force_unstake(100);
+        assert_eq!(m.stake_amount, 0);
+        assert_eq!(m.pending_unstake(), MAX_UNSTAKE_REQUESTS as u64 + 40);
+        assert_eq!(m.take_matured_unstakes(99), MAX_UNSTAKE_REQUESTS as u64 - 1);
+        assert_eq!(m.take_matured_unstakes(100), 41);
+        assert_eq!(m.unstaking_time, 100);
+    }
+
+    #[test]
+    fn cancelled_unstake_leaves_older_requests_queued() {
+        let mut m = miner(40);
+        m.push_unstake_request(5, 10).unwrap();
+        let forced = m.force_unstake(100);
+        assert_eq!(m.cancel_forced_unstake(&forced), 40);
+        assert_eq!(m.stake_amount, 40);
+        assert_eq!(m.pending_unstake(), 5);
+        assert_eq!(m.unstaking_time, 100);
+    }
+
+    #[test]
+    fn cancelled_unstake_only_takes_the_forced_request() {
+        let mut m = miner(40);
+        let forced = m.force_unstake(100);
+        // a later request of the miner stays queued
+        m.push_unstake_request(7, 200).unwrap();
+        assert_eq!(m.cancel_forced_unstake(&forced), 40);
+        assert_eq!((m.stake_amount, m.pending_unstake()), (40, 7));
+
+        // once claimed, and its slot reused, nothing comes back
+        let mut m = miner(40);
+        let forced = m.force_unstake(100);
+        assert_eq!(m.take_matured_unstakes(100), 40);
+        m.push_unstake_request(3, 300).unwrap();
+        assert_eq!(m.cancel_forced_unstake(&forced), 0);
+        assert_eq!((m.stake_amount, m.pending_unstake()), (0, 3));
+    }
+
+    #[test]
+    fn overturned_fine_is_restored_to_its_owners() {
+        let mut m = miner(300);
+        m.add_delegation(100).unwrap();
+        m.offence_count = 1;
+        let before = m.delegated_stake;
+        assert_eq!(m.take_fine(40), 40);
+        let delegated = before - m.delegated_stake;
+        assert_eq!(delegated, 10);
+
+        m.restore_fine(40, delegated);
+        assert_eq!(m.stake_amount, 300);
+        assert_eq!(m.delegation_value(100), 100);
+        assert_eq!(m.offence_count, 0);
+    }
+}
@@ -1,6 +1,15 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
-use crate::{MinerInfo, MinersOfModel, Models, SolLearnInfo, VaultAccount};
+use crate::comparator::{
+    exact_digest, parse_output, within_tolerance, COMPARATOR_EXACT, COMPARATOR_NUMERIC,
+};
+use crate::errors::SolLearnError;
+use crate::{
+    MinerInfo, MinerStats, MinersOfModel, ModelInfo, SolLearnInfo, VaultAccount,
+    MINERS_OF_MODEL_VERSION,
+};
 
 pub const ANCHOR_DISCRIMINATOR: usize = 8;
 
@@ -34,6 +43,17 @@ pub struct ReadTasksVld<'info> {
     pub tasks: Box<Account<'info, Tasks>>,
 }
 
+#[derive(Accounts)]
+#[instruction(miner: Pubkey)]
+pub struct ReadMinerStatsVld<'info> {
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        seeds = [b"miner_stats", miner.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_stats.bump
+    )]
+    pub miner_stats: Box<Account<'info, MinerStats>>,
+}
+
 #[account]
 pub struct Pubkeys {
     pub values: Vec<Pubkey>,
@@ -45,13 +65,51 @@ impl Pubkeys {
     }
 }
 
-// #[account]
-// pub struct Models {
-//     pub minimum_fee: u64,
-//     pub tier: u32,
-//     pub address: Pubkey,
-// }
-pub struct UnstakeRequest {}
+// stake waiting out the unstake delay, an empty slot has amount 0
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct UnstakeRequest {
+    pub amount: u64,
+    pub unlock_time: u64,
+}
+
+impl UnstakeRequest {
+    pub const LEN: usize = 8 + 8;
+
+    // Queues `amount` in the first empty slot of `requests`.
+    pub fn push(requests: &mut [Self], amount: u64, unlock_time: u64) -> Result<()> {
+        match requests.iter_mut().find(|r| r.amount == 0) {
+            Some(slot) => {
+                *slot = UnstakeRequest { amount, unlock_time };
+                Ok(())
+            }
+            None => Err(SolLearnError::TooManyUnstakeRequests.into()),
+        }
+    }
+
+    // Empties the requests unlocked at `now` and returns their amount.
+    pub fn take_matured(requests: &mut [Self], now: u64) -> u64 {
+        let mut amount = 0;
+        for r in requests.iter_mut() {
+            if r.amount > 0 && r.unlock_time <= now {
+                amount += r.amount;
+                *r = UnstakeRequest::default();
+            }
+        }
+        amount
+    }
+}
+
+// the unstake request a fine forced, `slot` in the miner's unstake_requests;
+// an amount of 0 means the fine forced none
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, InitSpace)]
+pub struct ForcedUnstake {
+    pub slot: u8,
+    pub amount: u64,
+    pub unlock_time: u64,
+}
+
+// pending unstake requests a miner can hold at once
+pub const MAX_UNSTAKE_REQUESTS: usize = 4;
 
 pub enum InferenceStatus {
     Nil,
@@ -69,16 +127,24 @@ pub enum AssignmentRole {
     Mining,
 }
 
+// assignments of an inference, the mining assignment included
+pub const MAX_ASSIGNMENTS: usize = 33;
+// assignments held by one AssignmentPage
+pub const ASSIGNMENTS_PER_PAGE: usize = 8;
+pub const MAX_ASSIGNMENT_PAGES: usize = MAX_ASSIGNMENTS.div_ceil(ASSIGNMENTS_PER_PAGE);
+
 #[account]
 #[derive(InitSpace)]
 pub struct Inference {
     pub bump: u8,
     pub id: u64,
-    #[max_len(5)]
-    pub assignments: Vec<u64>,
-    pub digests: Hashes,
-    #[max_len(256)]
-    pub input: Vec<u8>,
+    // assignments of the current round, stored in AssignmentPage accounts
+    pub assignment_count: u16,
+    // set when a worker seizes the miner role
+    pub mining_assignment: u64,
+    pub input: DataRef,
+    // the ChunkedData of an Inline input
+    pub input_data: Pubkey,
     pub value: u64,
     pub fee_l2: u64,
     pub fee_treasury: u64,
@@ -90,16 +156,57 @@ pub struct Inference {
     pub creator: Pubkey,
     pub processed_miner: Pubkey,
     pub referrer: Pubkey,
+    // set aside from the fee at infer, paid to whoever cranks the last task
+    pub crank_bounty: u64,
+    // validation rounds started after an inconclusive vote
+    pub round: u8,
+    // given by the creator with rate_inference, 0 until rated
+    pub score: u8,
+    // set when the vote pays the miner, a refunded inference ends in status 4 too
+    pub solved: bool,
 }
 
+pub const MAX_REFERRAL_CODE_LEN: usize = 32;
+
+// Referral state of a user, at [b"referrer", user]. `pubkey` is whoever
+// referred the user: it is set once, and only while the user has no
+// referees, so that the referrals never form a cycle.
 #[account]
+#[derive(InitSpace)]
 pub struct Referrer {
 	pub bump: u8,
 	pub pubkey: Pubkey,
+	pub user: Pubkey,
+	// set by register_referrer, referees pass it to set_referrer
+	#[max_len(32)]
+	pub code: String,
+	pub referee_count: u64,
+	pub referred_inferences: u64,
+	// fees paid for the referred inferences
+	pub referred_volume: u64,
+	// DAO token minted to the user as a referrer
+	pub earned_rewards: u64,
 }
 
+// The referrer a code belongs to, at [b"referral_code", code]
+#[account]
+#[derive(InitSpace)]
+pub struct ReferralCode {
+    pub bump: u8,
+    pub referrer: Pubkey,
+}
+
+pub fn validate_referral_code(code: &str) -> Result<()> {
+    if code.is_empty() || code.len() > MAX_REFERRAL_CODE_LEN {
+        return Err(SolLearnError::InvalidReferralCode.into());
+    }
+    Ok(())
+}
+
+// The assignment pages of the inference follow in remaining accounts, in
+// page order; infer creates them.
 #[derive(Accounts)]
-#[instruction(inference_id: u64, creator: Pubkey)]
+#[instruction(inference_id: u64, creator: Pubkey, input: DataRef, _value: u64, model: Pubkey)]
 pub struct InferVld<'info> {
     #[account(
         init,
@@ -118,9 +225,9 @@ pub struct InferVld<'info> {
     #[account(init, payer = signer, space = ANCHOR_DISCRIMINATOR + VotingInfo::INIT_SPACE,
         seeds = [b"voting_info", inference_id.to_le_bytes().as_ref()], bump )]
     pub voting_info: Box<Account<'info, VotingInfo>>,
-    // #[account(init, payer = signer, space = ANCHOR_DISCRIMINATOR + DAOTokenReceiverInfos::INIT_SPACE,
-    //     seeds = [b"dao_receiver_infos", sol_learn_account.key().as_ref(), inference_id.to_le_bytes().as_ref()], bump)]
-    // pub dao_receiver_infos: Box<Account<'info, DAOTokenReceiverInfos>>,
+    #[account(init, payer = signer, space = ANCHOR_DISCRIMINATOR + DAOTokenReceiverInfos::INIT_SPACE,
+        seeds = [b"dao_receiver_infos", sol_learn_account.key().as_ref(), inference_id.to_le_bytes().as_ref()], bump)]
+    pub dao_receiver_infos: Box<Account<'info, DAOTokenReceiverInfos>>,
     #[account(
         init, 
         payer = signer, 
@@ -129,11 +236,24 @@ pub struct InferVld<'info> {
         bump
     )]
 	pub tasks: Box<Account<'info, Tasks>>,
-	#[account(mut)]
-	pub models: Box<Account<'info, Models>>,
-	// #[account(mut, seeds = [b"referrer", creator.to_bytes().as_ref()], bump)]
-	// pub referrer: Account<'info, Referrer>,
+    #[account(
+        seeds = [b"model_info", sol_learn_account.key().as_ref(), model.as_ref()],
+        bump = model_info.bump
+    )]
+	pub model_info: Box<Account<'info, ModelInfo>>,
+    /// CHECK: referral state of the signer, read by infer once it exists.
+    // When it names a referrer the inference is referred and `referrer` must
+    // be the referrer's own state, whose stats are updated.
+    #[account(seeds = [b"referrer", signer.key().as_ref()], bump)]
+    pub referee: UncheckedAccount<'info>,
     #[account(mut)]
+    pub referrer: Option<Box<Account<'info, Referrer>>>,
+    #[account(
+        mut,
+        seeds = [b"models", sol_learn_account.key().as_ref(), model.as_ref()],
+        bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
+    )]
     pub miners_of_model: Box<Account<'info, MinersOfModel>>,
     #[account(mut)]
     pub signer: Signer<'info>,
@@ -146,13 +266,20 @@ pub struct InferVld<'info> {
     pub vault_staking_wallet: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
     pub miner_staking_wallet: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: address is checked against the SlotHashes sysvar id
+    #[account(address = slot_hashes::ID)]
+    pub recent_slothashes: UncheckedAccount<'info>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+    // required for an Inline input
+    pub input_data: Option<Box<Account<'info, ChunkedData>>>,
 }
 
 #[derive(Accounts)]
 #[instruction(inference_id: u64)]
 pub struct UpdateInferVld<'info> {
+    #[account(mut)]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
     #[account(mut)]
     pub infs: Box<Account<'info, Inference>>,
     pub signer: Signer<'info>,
@@ -166,12 +293,125 @@ pub struct UpdateInferVld<'info> {
     pub system_program: Program<'info, System>,
 }
 
+// a group of equivalent outputs: the digest and values of the first one
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq, InitSpace)]
+pub struct DigestTally {
+    pub digest: [u8; 32],
+    pub count: u16,
+    #[max_len(8)]
+    pub values: Vec<i64>,
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct VotingInfo {
     pub bump: u8,
-    pub total_commit: u8,
-    pub total_reveal: u8,
+    pub total_commit: u16,
+    pub total_reveal: u16,
+    // comparator and tolerance of the model when the inference was created
+    pub comparator: u8,
+    pub tolerance: u64,
+    // votes of the current round per group of equivalent outputs, the
+    // miner's included, in the order the groups were first seen
+    #[max_len(33)]
+    pub tallies: Vec<DigestTally>,
+}
+
+impl VotingInfo {
+    // Counts a vote for `output`, returns the digest of the group it joined.
+    pub fn add_vote(&mut self, inference_id: u64, output: &[u8]) -> Result<[u8; 32]> {
+        let (digest, values) = parse_output(self.comparator, inference_id, output)?;
+        Ok(self.tally(digest, values))
+    }
+
+    // Counts a vote for an output known only by its hash, see DataRef.
+    pub fn add_ref_vote(&mut self, inference_id: u64, content_hash: &[u8; 32]) -> Result<[u8; 32]> {
+        if self.comparator != COMPARATOR_EXACT {
+            return Err(SolLearnError::InlineOutputRequired.into());
+        }
+        Ok(self.tally(exact_digest(inference_id, content_hash), vec![]))
+    }
+
+    fn tally(&mut self, digest: [u8; 32], values: Vec<i64>) -> [u8; 32] {
+        let tolerance = self.tolerance;
+        let comparator = self.comparator;
+        let group = self.tallies.iter_mut().find(|t| {
+            t.digest == digest
+                || (comparator == COMPARATOR_NUMERIC && within_tolerance(&t.values, &values, tolerance))
+        });
+        match group {
+            Some(tally) => {
+                tally.count += 1;
+                tally.digest
+            }
+            None => {
+                self.tallies.push(DigestTally {
+                    digest,
+                    count: 1,
+                    values,
+                });
+                digest
+            }
+        }
+    }
+
+    // the digest with the most votes and its count, the first seen on a tie
+    pub fn most_voted(&self) -> ([u8; 32], u64) {
+        let mut most_voted = ([0; 32], 0);
+        for tally in self.tallies.iter() {
+            if tally.count as u64 > most_voted.1 {
+                most_voted = (tally.digest, tally.count as u64);
+            }
+        }
+        most_voted
+    }
+
+    // starts a new round with only the vote of the group `digest`
+    pub fn keep_only(&mut self, digest: [u8; 32]) {
+        self.tallies.retain(|t| t.digest == digest);
+        for tally in self.tallies.iter_mut() {
+            tally.count = 1;
+        }
+    }
+}
+
+// One page of the assignments of an inference, seeded by
+// [b"assignment_page", inference_id, page]. Page n holds the assignments
+// n * ASSIGNMENTS_PER_PAGE.. of the current round with their digests, empty
+// until the assignment submits or reveals.
+#[account]
+#[derive(InitSpace)]
+pub struct AssignmentPage {
+    pub bump: u8,
+    pub inference_id: u64,
+    pub page: u8,
+    #[max_len(8)]
+    pub assignments: Vec<u64>,
+    #[max_len(8)]
+    pub digests: Vec<[u8; 32]>,
+}
+
+// the assignments of an inference read from all its pages, in order
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AssignmentList {
+    pub ids: Vec<u64>,
+    pub digests: Vec<[u8; 32]>,
+}
+
+impl AssignmentList {
+    pub fn push(&mut self, assignment_id: u64, digest: [u8; 32]) {
+        self.ids.push(assignment_id);
+        self.digests.push(digest);
+    }
+
+    pub fn position(&self, assignment_id: u64) -> Option<usize> {
+        self.ids.iter().position(|&id| id == assignment_id)
+    }
+
+    // empty for an assignment that is not in the list
+    pub fn digest_of(&self, assignment_id: u64) -> [u8; 32] {
+        self.position(assignment_id).map_or([0; 32], |i| self.digests[i])
+    }
 }
 pub struct Bytes32 {}
 pub type Bytes32Set = Vec<Bytes32>;
@@ -193,22 +433,39 @@ pub enum DAOTokenReceiverRole {
     L2Owner,
 }
 
+// an assignment receives through its worker; the creator of the inference
+// is a wallet of the staking token and receives through its owner
 #[account]
 #[derive(InitSpace)]
 pub struct DAOTokenReceiverInfo {
-    pub receiver: Pubkey,
+    pub receiver: AssignmentOrPubkey,
     pub amount: u64,
     pub role: u8,
 }
 
+// DAO tokens an inference mints once resolved, one MintDaoToken task each
 #[account]
 #[derive(InitSpace)]
 pub struct DAOTokenReceiverInfos {
     pub bump: u8,
-    #[max_len(5)]
+    // the miner, up to 32 validators, user, referrer, referee and l2 owner
+    #[max_len(37)]
     pub values: Vec<DAOTokenReceiverInfo>,
 }
 
+// Scales the amounts down pro rata when they add up to more than `mintable`.
+// Returns the total left to mint.
+pub fn prorate_dao_token(receivers: &mut [DAOTokenReceiverInfo], mintable: u64) -> u64 {
+    let total: u64 = receivers.iter().map(|r| r.amount).sum();
+    if total <= mintable {
+        return total;
+    }
+    for receiver in receivers.iter_mut() {
+        receiver.amount = (receiver.amount as u128 * mintable as u128 / total as u128) as u64;
+    }
+    receivers.iter().map(|r| r.amount).sum()
+}
+
 pub enum Vote {
     Nil,
     Disapproval,
@@ -239,24 +496,169 @@ pub struct Assignment {
     pub worker: Pubkey,
     pub role: u8,
     pub vote: u8,
-    #[max_len(256)]
-    pub output: Vec<u8>,
+    pub output: DataRef,
+    // the ChunkedData of an Inline output
+    pub output_data: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace)]
+pub enum DataScheme {
+    // in a finalized ChunkedData of the program
+    #[default]
+    Inline,
+    // ar://<transaction id>
+    Arweave,
+    // ipfs://<cid>
+    Ipfs,
+    // https://<url>
+    Https,
 }
 
+pub const MAX_URI_LEN: usize = 128;
+
+// Where an inference input or an assignment output lives and the keccak of
+// its content. Off-chain content is never read by the program: votes on it
+// are grouped by the hash alone, which only the exact comparator allows.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq, Eq, InitSpace)]
+pub struct DataRef {
+    pub scheme: DataScheme,
+    // empty for Inline
+    #[max_len(128)]
+    pub uri: String,
+    pub keccak: [u8; 32],
+}
+
+impl DataRef {
+    pub fn inline(keccak: [u8; 32]) -> Self {
+        DataRef {
+            scheme: DataScheme::Inline,
+            uri: String::new(),
+            keccak,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let prefix = match self.scheme {
+            DataScheme::Inline if self.uri.is_empty() => return Ok(()),
+            DataScheme::Inline => return Err(SolLearnError::InvalidDataRef.into()),
+            DataScheme::Arweave => "ar://",
+            DataScheme::Ipfs => "ipfs://",
+            DataScheme::Https => "https://",
+        };
+        if self.uri.len() > MAX_URI_LEN
+            || self.uri.len() == prefix.len()
+            || !self.uri.starts_with(prefix)
+        {
+            return Err(SolLearnError::InvalidDataRef.into());
+        }
+        Ok(())
+    }
+}
+
+// largest payload of a ChunkedData, accounts created by CPI are capped at
+// 10 KiB
+pub const MAX_DATA_LEN: usize = 10_000;
+
+// Payload too large for an instruction (an inference input, an assignment
+// output), seeded by [b"data", owner, data_id]. The owner allocates it with
+// its full length, writes it in chunks over several transactions and then
+// finalizes it against the keccak of the content, after which it can no
+// longer change.
 #[account]
+pub struct ChunkedData {
+    pub bump: u8,
+    pub owner: Pubkey,
+    pub data_id: u64,
+    pub hash: [u8; 32],
+    pub finalized: bool,
+    pub data: Vec<u8>,
+}
+
+impl ChunkedData {
+    pub const HEADER_LEN: usize = 1 + 32 + 8 + 32 + 1 + 4;
+
+    // the finalized content with the given hash, as infer / submit /
+    // reveal take it
+    pub fn content(&self, hash: &[u8; 32]) -> Result<&[u8]> {
+        if !self.finalized {
+            return Err(SolLearnError::DataNotFinalized.into());
+        }
+        if self.hash != *hash {
+            return Err(SolLearnError::DataHashMismatch.into());
+        }
+        Ok(&self.data)
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(data_id: u64, len: u32)]
+pub struct CreateDataVld<'info> {
+    #[account(
+        init,
+        payer = signer,
+        space = ANCHOR_DISCRIMINATOR + ChunkedData::HEADER_LEN + len as usize,
+        seeds = [b"data", signer.key().as_ref(), data_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub data: Box<Account<'info, ChunkedData>>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(data_id: u64)]
+pub struct WriteDataVld<'info> {
+    #[account(
+        mut,
+        seeds = [b"data", signer.key().as_ref(), data_id.to_le_bytes().as_ref()],
+        bump = data.bump,
+        constraint = !data.finalized
+    )]
+    pub data: Box<Account<'info, ChunkedData>>,
+    pub signer: Signer<'info>,
+}
+
+// status of a slash record
+pub const SLASH_PENDING: u8 = 0; // fine held in the vault until executable_at
+pub const SLASH_DISPUTED: u8 = 1; // waiting for the arbiter
+pub const SLASH_UPHELD: u8 = 2; // dispute lost, the fine can be paid out
+pub const SLASH_EXECUTED: u8 = 3; // fine paid out
+pub const SLASH_OVERTURNED: u8 = 4; // dispute won, the fine went back to the stake
+
+// audit trail of one slash, seeded by [b"slash_record", miner_account, n]
+// where n counts the slashes of that miner; admin slashes have no inference
+// and no creator wallet
+#[account]
+#[derive(InitSpace)]
+pub struct SlashRecord {
+    pub bump: u8,
+    pub miner: Pubkey,
+    pub inference_id: u64,
+    pub assignment_id: u64,
+    pub reason: SlashReason,
+    pub amount: u64,
+    // part of amount taken from delegated stake
+    pub delegated_amount: u64,
+    pub creator_wallet: Pubkey,
+    pub status: u8,
+    pub executable_at: u64,
+    pub bond: u64,
+    // stake queued for withdrawal because the fine left the miner under the
+    // minimum stake, taken back if the fine is overturned
+    pub forced_unstake: ForcedUnstake,
+}
+
+// reward snapshot of a finished epoch, seeded by [b"reward_in_epoch", epoch_id]
+#[account]
+#[derive(InitSpace)]
 pub struct MinerEpochState {
     pub perf_reward: u64,
     pub epoch_reward: u64,
     pub total_task_completed: u64,
     pub total_miner: u64,
     pub bump: u8,
-}
-
-#[account]
-#[derive(InitSpace)]
-pub struct Hashes {
-    #[max_len(5)]
-    pub values: Vec<[u8; 32]>,
+    pub epoch_id: u64,
 }
 
 #[account]
@@ -268,6 +670,34 @@ pub struct DAOTokenPercentage {
     pub l2_owner_percentage: u16,
 }
 
+#[derive(Accounts)]
+#[instruction(epoch_id: u64)]
+pub struct NewEpochVld<'info> {
+    #[account(mut)]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(init,
+        payer = signer,
+        space = ANCHOR_DISCRIMINATOR + MinerEpochState::INIT_SPACE,
+        seeds = [b"reward_in_epoch", epoch_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub miner_reward: Account<'info, MinerEpochState>,
+    #[account(
+        seeds = [b"reward_pool", sol_learn_account.key().as_ref()],
+        bump = reward_pool_pda.bump,
+    )]
+    pub reward_pool_pda: Account<'info, VaultAccount>,
+    #[account(
+        constraint = reward_pool_wallet.owner == reward_pool_pda.key(),
+        constraint = reward_pool_wallet.mint == sol_learn_account.token,
+    )]
+    pub reward_pool_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub sysvar_clock: Sysvar<'info, Clock>,
+}
+
 #[derive(Accounts)]
 #[instruction(epoch_id: u64)]
 pub struct UpdateEpochVld<'info> {
@@ -290,12 +720,26 @@ pub struct SlashMinerByAdminVld<'info> {
     pub miner_reward: Account<'info, MinerEpochState>,
     #[account(mut)]
     pub miner_account: Account<'info, MinerInfo>,
-    #[account(mut)]
+    #[account(mut,
+        seeds = [b"miner_stats", miner_account.miner.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_stats.bump
+    )]
+    pub miner_stats: Box<Account<'info, MinerStats>>,
+    #[account(mut, constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION)]
     pub miners_of_model: Box<Account<'info, MinersOfModel>>,
     /// CHECK:
     #[account(mut, constraint = sol_learn_account.admin == signer.key())]
     pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(mut)]
     pub signer: Signer<'info>,
+    #[account(
+        init,
+        payer = signer,
+        space = ANCHOR_DISCRIMINATOR + SlashRecord::INIT_SPACE,
+        seeds = [b"slash_record", miner_account.key().as_ref(), miner_account.slash_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub slash_record: Box<Account<'info, SlashRecord>>,
 }
 
 #[derive(Accounts)]
@@ -320,13 +764,23 @@ pub struct CreateAssignmentVld<'info> {
 pub struct PayMinerVld<'info> {
 	#[account(mut)]
 	pub tasks: Box<Account<'info, Tasks>>,
-    #[account()]
+    #[account(mut)]
     pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
 	#[account(mut,
         seeds = [b"assignment", assignment_id.to_le_bytes().as_ref()],
         bump = assignment.bump
     )]
     pub assignment: Box<Account<'info, Assignment>>,
+    #[account(mut,
+        seeds = [b"miner", assignment.worker.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_account.bump
+    )]
+    pub miner_account: Box<Account<'info, MinerInfo>>,
+    #[account(mut,
+        seeds = [b"miner_stats", assignment.worker.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_stats.bump
+    )]
+    pub miner_stats: Box<Account<'info, MinerStats>>,
 	#[account(mut)]
     pub vault_wallet_owner_pda: Account<'info, VaultAccount>,
     #[account(mut, constraint = vault_staking_wallet.owner == vault_wallet_owner_pda.key())]
@@ -347,11 +801,16 @@ pub struct SlashMinerVld<'info> {
 	// pub miner_addresses: Account<'info, Pubkeys>,
 	#[account(mut)]
 	pub miner_account: Account<'info, MinerInfo>,
+    #[account(mut,
+        seeds = [b"miner_stats", miner_account.miner.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_stats.bump
+    )]
+    pub miner_stats: Box<Account<'info, MinerStats>>,
 	#[account(mut)]
 	pub tasks: Box<Account<'info, Tasks>>,
 	#[account(mut)]
     pub assignment: Box<Account<'info, Assignment>>,
-    #[account(mut)]
+    #[account(mut, constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION)]
     pub miners_of_model: Box<Account<'info, MinersOfModel>>,
 	// #[account(mut)]
 	// pub signer: Signer<'info>,
@@ -363,10 +822,130 @@ pub struct SlashMinerVld<'info> {
     pub staking_token: InterfaceAccount<'info, Mint>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
-	#[account(mut)]
+	#[account(mut, constraint = token_recipient.owner == sol_learn_account.treasury)]
     pub token_recipient: InterfaceAccount<'info, TokenAccount>,
+    #[account(seeds = [b"inference", assignment.inference_id.to_le_bytes().as_ref()], bump = infs.bump)]
+    pub infs: Box<Account<'info, Inference>>,
+    #[account(mut, address = infs.creator)]
+    pub creator_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        init,
+        payer = signer,
+        space = ANCHOR_DISCRIMINATOR + SlashRecord::INIT_SPACE,
+        seeds = [b"slash_record", miner_account.key().as_ref(), miner_account.slash_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub slash_record: Box<Account<'info, SlashRecord>>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct OpenDisputeVld<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(mut)]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        seeds = [b"miner", signer.key().as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_account.bump,
+    )]
+    pub miner_account: Account<'info, MinerInfo>,
+    #[account(
+        mut,
+        seeds = [b"slash_record", miner_account.key().as_ref(), index.to_le_bytes().as_ref()],
+        bump = slash_record.bump,
+    )]
+    pub slash_record: Box<Account<'info, SlashRecord>>,
+    #[account(mut)]
+    pub miner_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"vault", sol_learn_account.key().as_ref()],
+        bump = vault_wallet_owner_pda.bump,
+    )]
+    pub vault_wallet_owner_pda: Account<'info, VaultAccount>,
+    #[account(mut, constraint = vault_staking_wallet.owner == vault_wallet_owner_pda.key())]
+    pub vault_staking_wallet: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct ResolveDisputeVld<'info> {
+    #[account(
+        constraint = signer.key() == sol_learn_account.arbiter
+            || signer.key() == sol_learn_account.admin
+    )]
+    pub signer: Signer<'info>,
+    #[account(mut)]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        mut,
+        seeds = [b"miner", slash_record.miner.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_account.bump,
+    )]
+    pub miner_account: Account<'info, MinerInfo>,
+    #[account(
+        mut,
+        seeds = [b"slash_record", miner_account.key().as_ref(), index.to_le_bytes().as_ref()],
+        bump = slash_record.bump,
+    )]
+    pub slash_record: Box<Account<'info, SlashRecord>>,
+    // gets the bond back when the slash is overturned
+    #[account(mut, constraint = miner_wallet.owner == slash_record.miner)]
+    pub miner_wallet: InterfaceAccount<'info, TokenAccount>,
+    // gets the bond when the slash is upheld
+    #[account(mut, constraint = treasury_wallet.owner == sol_learn_account.treasury)]
+    pub treasury_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"vault", sol_learn_account.key().as_ref()],
+        bump = vault_wallet_owner_pda.bump,
+    )]
+    pub vault_wallet_owner_pda: Account<'info, VaultAccount>,
+    #[account(mut, constraint = vault_staking_wallet.owner == vault_wallet_owner_pda.key())]
+    pub vault_staking_wallet: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct ExecuteSlashVld<'info> {
+    pub signer: Signer<'info>,
+    #[account(mut)]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        seeds = [b"miner", slash_record.miner.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_account.bump,
+    )]
+    pub miner_account: Account<'info, MinerInfo>,
+    #[account(
+        mut,
+        seeds = [b"slash_record", miner_account.key().as_ref(), index.to_le_bytes().as_ref()],
+        bump = slash_record.bump,
+    )]
+    pub slash_record: Box<Account<'info, SlashRecord>>,
+    #[account(mut, constraint = treasury_wallet.owner == sol_learn_account.treasury)]
+    pub treasury_wallet: InterfaceAccount<'info, TokenAccount>,
+    // any wallet, the treasury's for instance, when the record has no creator
+    #[account(
+        mut,
+        constraint = slash_record.creator_wallet == Pubkey::default()
+            || creator_wallet.key() == slash_record.creator_wallet
+    )]
+    pub creator_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        seeds = [b"vault", sol_learn_account.key().as_ref()],
+        bump = vault_wallet_owner_pda.bump,
+    )]
+    pub vault_wallet_owner_pda: Account<'info, VaultAccount>,
+    #[account(mut, constraint = vault_staking_wallet.owner == vault_wallet_owner_pda.key())]
+    pub vault_staking_wallet: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
+// submit_solution takes the assignment pages of the inference in remaining
+// accounts, in page order.
 #[derive(Accounts)]
 #[instruction(assignment_id: u64, inference_id: u64)]
 pub struct SeizeMinerRoleVld<'info> {
@@ -376,14 +955,27 @@ pub struct SeizeMinerRoleVld<'info> {
     pub infs: Box<Account<'info, Inference>>,
     #[account(mut, seeds = [b"assignment", assignment_id.to_le_bytes().as_ref()], bump = assignment.bump)]
     pub assignment: Box<Account<'info, Assignment>>,
+    #[account(mut,
+        seeds = [b"voting_info", inference_id.to_le_bytes().as_ref()], bump )]
+    pub voting_info: Box<Account<'info, VotingInfo>>,
     #[account(mut)]
     pub miner_account: Account<'info, MinerInfo>,
+    #[account(mut,
+        seeds = [b"miner_stats", assignment.worker.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_stats.bump
+    )]
+    pub miner_stats: Box<Account<'info, MinerStats>>,
     #[account(mut)]
     pub signer: Signer<'info>,
     pub system_program: Program<'info, System>,
+    // the miner's output, for submit_solution only
+    pub output_data: Option<Box<Account<'info, ChunkedData>>>,
 }
 
 
+// The assignment pages of the inference follow in remaining accounts, in
+// page order. Pass all MAX_ASSIGNMENT_PAGES of them: an escalation round
+// creates the pages it needs.
 #[derive(Accounts)]
 #[instruction(assignment_id: u64, inference_id: u64)]
 pub struct UpdateAssignmentVld<'info> {
@@ -399,12 +991,17 @@ pub struct UpdateAssignmentVld<'info> {
     // pub miner_reward: Account<'info, MinerEpochState>,
     #[account(mut)]
     pub miner_account: Account<'info, MinerInfo>,
+    #[account(mut,
+        seeds = [b"miner_stats", assignment.worker.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_stats.bump
+    )]
+    pub miner_stats: Box<Account<'info, MinerStats>>,
     #[account(mut,
         seeds = [b"voting_info", inference_id.to_le_bytes().as_ref()], bump )]
     pub voting_info: Box<Account<'info, VotingInfo>>,
-    // #[account(mut,
-    //     seeds = [b"dao_receiver_infos", sol_learn_account.key().as_ref(), inference_id.to_le_bytes().as_ref()], bump)]
-    // pub dao_receiver_infos: Box<Account<'info, DAOTokenReceiverInfos>>,
+    #[account(mut,
+        seeds = [b"dao_receiver_infos", sol_learn_account.key().as_ref(), inference_id.to_le_bytes().as_ref()], bump = dao_receiver_infos.bump)]
+    pub dao_receiver_infos: Box<Account<'info, DAOTokenReceiverInfos>>,
     #[account(mut, seeds = [b"tasks", sol_learn_account.key().as_ref(), inference_id.to_le_bytes().as_ref()], bump)]
     pub tasks: Box<Account<'info, Tasks>>,
     #[account(mut)]
@@ -417,6 +1014,18 @@ pub struct UpdateAssignmentVld<'info> {
     pub token_recipient: InterfaceAccount<'info, TokenAccount>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+    // validators of an escalation round are drawn from the miners of the model
+    #[account(
+        seeds = [b"models", sol_learn_account.key().as_ref(), infs.model_address.as_ref()],
+        bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
+    )]
+    pub miners_of_model: Box<Account<'info, MinersOfModel>>,
+    /// CHECK: address is checked against the SlotHashes sysvar id
+    #[account(address = slot_hashes::ID)]
+    pub recent_slothashes: UncheckedAccount<'info>,
+    // the validator's output, for reveal only
+    pub output_data: Option<Box<Account<'info, ChunkedData>>>,
 }
 
 #[derive(Accounts)]
@@ -448,6 +1057,169 @@ pub struct CommitVld<'info> {
     pub system_program: Program<'info, System>,
 }
 
+// Task accounts are passed in remaining accounts, in queue order:
+//   CreateAssignment            [assignment]
+//   PayMiner (assignment)       [assignment, miner_account, recipient wallet, miner_stats]
+//   PayMiner (pubkey)           [recipient wallet]
+//   SlashMiner (assignment)     [assignment, miner_account, slash_record, miner_stats]
+//   SlashMiner (pubkey)         [miner_account, slash_record, miner_stats]
+//   MintDaoToken (assignment)   [assignment, DAO token wallet of the worker, worker]
+//   MintDaoToken (pubkey)       [DAO token wallet of the receiver, receiver]
+//   MintDaoToken (referrer)     [DAO token wallet of the referrer, referrer, its Referrer]
+// where slash_record is the next record of the miner, created by the crank,
+// and a DAO token wallet that does not exist yet must be the receiver's
+// associated token account, created by the crank
+#[derive(Accounts)]
+#[instruction(inference_id: u64)]
+pub struct CrankTasksVld<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(mut)]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(mut, seeds = [b"inference", inference_id.to_le_bytes().as_ref()], bump = infs.bump)]
+    pub infs: Box<Account<'info, Inference>>,
+    #[account(mut, seeds = [b"tasks", sol_learn_account.key().as_ref(), inference_id.to_le_bytes().as_ref()], bump)]
+    pub tasks: Box<Account<'info, Tasks>>,
+    #[account(
+        mut,
+        seeds = [b"models", sol_learn_account.key().as_ref(), infs.model_address.as_ref()],
+        bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
+    )]
+    pub miners_of_model: Box<Account<'info, MinersOfModel>>,
+    #[account(
+        seeds = [b"vault", sol_learn_account.key().as_ref()], 
+        bump = vault_wallet_owner_pda.bump,
+    )]
+    pub vault_wallet_owner_pda: Account<'info, VaultAccount>,
+    #[account(mut, constraint = vault_staking_wallet.owner == vault_wallet_owner_pda.key())]
+    pub vault_staking_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = treasury_wallet.owner == sol_learn_account.treasury)]
+    pub treasury_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = infs.creator)]
+    pub creator_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, constraint = cranker_wallet.mint == sol_learn_account.token)]
+    pub cranker_wallet: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    // required once a MintDaoToken task is reached
+    #[account(mut, address = sol_learn_account.dao_token)]
+    pub dao_token: Option<Box<InterfaceAccount<'info, Mint>>>,
+    // required once a MintDaoToken task reaches a missing DAO token wallet
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+}
+
+// The mint must already have the vault PDA as its mint authority.
+// The owner of the creator wallet rates a solved inference. The DAO token
+// accounts are required while the DAO token is set.
+#[derive(Accounts)]
+#[instruction(inference_id: u64)]
+pub struct RateInferenceVld<'info> {
+    pub signer: Signer<'info>,
+    #[account(mut)]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(mut, seeds = [b"inference", inference_id.to_le_bytes().as_ref()], bump = infs.bump)]
+    pub infs: Box<Account<'info, Inference>>,
+    #[account(address = infs.creator, constraint = creator_wallet.owner == signer.key())]
+    pub creator_wallet: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut,
+        seeds = [b"dao_receiver_infos", sol_learn_account.key().as_ref(), inference_id.to_le_bytes().as_ref()],
+        bump = dao_receiver_infos.bump)]
+    pub dao_receiver_infos: Box<Account<'info, DAOTokenReceiverInfos>>,
+    #[account(
+        mut,
+        seeds = [b"model_info", sol_learn_account.key().as_ref(), infs.model_address.as_ref()],
+        bump = model_info.bump
+    )]
+    pub model_info: Box<Account<'info, ModelInfo>>,
+    #[account(
+        mut,
+        seeds = [b"models", sol_learn_account.key().as_ref(), infs.model_address.as_ref()],
+        bump = miners_of_model.bump,
+        constraint = miners_of_model.version == MINERS_OF_MODEL_VERSION,
+    )]
+    pub miners_of_model: Box<Account<'info, MinersOfModel>>,
+    // the miner that solved the inference
+    #[account(
+        mut,
+        seeds = [b"miner", infs.processed_miner.as_ref(), sol_learn_account.key().as_ref()],
+        bump = miner_account.bump
+    )]
+    pub miner_account: Box<Account<'info, MinerInfo>>,
+    #[account(
+        seeds = [b"vault", sol_learn_account.key().as_ref()],
+        bump = vault_wallet_owner_pda.bump,
+    )]
+    pub vault_wallet_owner_pda: Account<'info, VaultAccount>,
+    #[account(mut, address = sol_learn_account.dao_token)]
+    pub dao_token: Option<Box<InterfaceAccount<'info, Mint>>>,
+    #[account(mut)]
+    pub user_dao_wallet: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(code: String)]
+pub struct RegisterReferrerVld<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = ANCHOR_DISCRIMINATOR + Referrer::INIT_SPACE,
+        seeds = [b"referrer", signer.key().as_ref()],
+        bump
+    )]
+    pub referrer: Box<Account<'info, Referrer>>,
+    #[account(
+        init,
+        payer = signer,
+        space = ANCHOR_DISCRIMINATOR + ReferralCode::INIT_SPACE,
+        seeds = [b"referral_code", code.as_bytes()],
+        bump
+    )]
+    pub referral_code: Account<'info, ReferralCode>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(code: String)]
+pub struct SetReferrerVld<'info> {
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = signer,
+        space = ANCHOR_DISCRIMINATOR + Referrer::INIT_SPACE,
+        seeds = [b"referrer", signer.key().as_ref()],
+        bump
+    )]
+    pub referee: Box<Account<'info, Referrer>>,
+    #[account(seeds = [b"referral_code", code.as_bytes()], bump = referral_code.bump)]
+    pub referral_code: Account<'info, ReferralCode>,
+    #[account(
+        mut,
+        seeds = [b"referrer", referral_code.referrer.as_ref()],
+        bump = referrer.bump
+    )]
+    pub referrer: Box<Account<'info, Referrer>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetDaoTokenVld<'info> {
+    pub admin: Signer<'info>,
+    #[account(mut, constraint = sol_learn_account.admin == admin.key())]
+    pub sol_learn_account: Box<Account<'info, SolLearnInfo>>,
+    #[account(
+        seeds = [b"vault", sol_learn_account.key().as_ref()],
+        bump = vault_wallet_owner_pda.bump,
+    )]
+    pub vault_wallet_owner_pda: Account<'info, VaultAccount>,
+    #[account(constraint = dao_token.mint_authority == Some(vault_wallet_owner_pda.key()).into())]
+    pub dao_token: InterfaceAccount<'info, Mint>,
+}
+
 #[derive(Accounts)]
 pub struct UpdateMinerAddressesByModelVld<'info> {
     #[account(mut)]
@@ -522,50 +1294,102 @@ pub struct UpdateMinerAddressesByModelVld<'info> {
 
 // pub type WorkerHubStorage = SolLearnInfo;
 
-#[derive(PartialEq)]
-pub enum FnType {
-	CreateAssignment,
-	PayMiner,
-	SlashMiner,
+// Bump TASK_VERSION whenever the layout of `Task` changes, entries written
+// with another version are rejected instead of being misread. Queues written
+// before slashes had a reason are still read through `TaskV1`.
+pub const TASK_VERSION: u8 = 2;
+pub const TASK_VERSION_V1: u8 = 1;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum AssignmentOrPubkey {
+    Assignment(u64),
+    Pubkey(Pubkey),
 }
 
-#[account]
-pub struct Task {
-    _b: [u8; 50],
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq, InitSpace)]
+pub enum SlashReason {
+    SubmitTimeout,
+    CommitTimeout,
+    RevealTimeout,
+    WrongResult,
+    Admin,
+    // queued by a v1 task, which did not record why
+    Legacy,
 }
 
-impl Task {
-    pub fn fn_type(&self) -> FnType {
-        match self._b[0] {
-            0 => FnType::CreateAssignment,
-            1 => FnType::PayMiner,
-            2 => FnType::SlashMiner,
-            _ => panic!("Invalid task"),
-        }
-    }
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Task {
+    CreateAssignment {
+        assignment_id: u64,
+        inference_id: u64,
+        worker: Pubkey,
+        role: u8,
+    },
+    // an assignment pays its worker and may set its vote, a pubkey is paid
+    // directly (l2 owner, treasury)
+    PayMiner {
+        recipient: AssignmentOrPubkey,
+        amount: u64,
+        vote: u8,
+    },
+    // a pubkey target is the processed miner of the inference
+    SlashMiner {
+        target: AssignmentOrPubkey,
+        is_fined: bool,
+        check_empty_commit: bool,
+        vote: u8,
+        reason: SlashReason,
+    },
+    // mints the DAO token to an assignment's worker or to a pubkey, see
+    // DAOTokenReceiverInfo; role is a DAOTokenReceiverRole
+    MintDaoToken {
+        recipient: AssignmentOrPubkey,
+        amount: u64,
+        role: u8,
+    },
+}
 
-    pub fn data(&self) -> Vec<u8> {
-        let mut data = [0; 49];
-        data.copy_from_slice(&self._b[1..]);
-        data.to_vec()
-    }
+// `Task` as written with TASK_VERSION_V1
+#[derive(AnchorSerialize, AnchorDeserialize)]
+enum TaskV1 {
+    CreateAssignment {
+        assignment_id: u64,
+        inference_id: u64,
+        worker: Pubkey,
+        role: u8,
+    },
+    PayMiner {
+        recipient: AssignmentOrPubkey,
+        amount: u64,
+        vote: u8,
+    },
+    SlashMiner {
+        target: AssignmentOrPubkey,
+        is_fined: bool,
+        check_empty_commit: bool,
+        vote: u8,
+    },
+}
 
-    pub fn new(fn_type: FnType, data: Vec<u8>) -> Self {
-        let mut task = Task {
-            _b: [0; 50],
-        };
-        task._b[0] = match fn_type {
-            FnType::CreateAssignment => 0,
-            FnType::PayMiner => 1,
-            FnType::SlashMiner => 2,
-        };
-        let len = data.len();
-        task._b[1..1 + len].copy_from_slice(&data);
-        task
+impl From<TaskV1> for Task {
+    fn from(task: TaskV1) -> Self {
+        match task {
+            TaskV1::CreateAssignment { assignment_id, inference_id, worker, role } => {
+                Task::CreateAssignment { assignment_id, inference_id, worker, role }
+            }
+            TaskV1::PayMiner { recipient, amount, vote } => Task::PayMiner { recipient, amount, vote },
+            TaskV1::SlashMiner { target, is_fined, check_empty_commit, vote } => Task::SlashMiner {
+                target,
+                is_fined,
+                check_empty_commit,
+                vote,
+                reason: SlashReason::Legacy,
+            },
+        }
     }
 }
 
-
+// Each entry of `values` is TASK_VERSION followed by the borsh encoded task.
 #[account]
 pub struct Tasks {
     pub bump: u8,
@@ -573,19 +1397,48 @@ pub struct Tasks {
 }
 
 impl Tasks {
+    // account size the queue needs as it is now
+    pub fn space(&self) -> usize {
+        ANCHOR_DISCRIMINATOR + 1 + 4 + self.values.len()
+    }
+
     pub fn push_task(&mut self, task: Task) {
-        self.values.extend(task._b.to_vec());
+        self.values.push(TASK_VERSION);
+        task.serialize(&mut self.values).unwrap();
     }
 
-    pub fn receive_task(&mut self) -> Option<Task> {
-        if self.values.len() < 50 {
-            return None;
+    // decodes the entry at the front of `data`, returning the task and the
+    // number of bytes it used
+    fn decode(data: &[u8]) -> Result<(Task, usize)> {
+        let mut rest = data.get(1..).ok_or(SolLearnError::NoValidTask)?;
+        let task = match data[0] {
+            TASK_VERSION => Task::deserialize(&mut rest),
+            TASK_VERSION_V1 => TaskV1::deserialize(&mut rest).map(Task::from),
+            _ => return Err(SolLearnError::UnsupportedTaskVersion.into()),
         }
-        let task: Vec<u8> = self.values.drain(0..50).collect();
-        
-        Some(Task {
-            _b: task.try_into().unwrap(),
-        })
+        .map_err(|_| SolLearnError::NoValidTask)?;
+        Ok((task, data.len() - rest.len()))
+    }
+
+    pub fn peek_task(&self) -> Result<Task> {
+        Ok(Self::decode(&self.values)?.0)
+    }
+
+    pub fn receive_task(&mut self) -> Result<Task> {
+        let (task, len) = Self::decode(&self.values)?;
+        self.values.drain(0..len);
+        Ok(task)
+    }
+
+    pub fn pending(&self) -> Result<Vec<Task>> {
+        let mut tasks = vec![];
+        let mut offset = 0;
+        while offset < self.values.len() {
+            let (task, len) = Self::decode(&self.values[offset..])?;
+            tasks.push(task);
+            offset += len;
+        }
+        Ok(tasks)
     }
 }
 
@@ -596,6 +1449,79 @@ pub struct NewInference {
     pub model_address: Pubkey,
     pub creator: Pubkey,
     pub value: u64,
+    pub seed: [u8; 32],
+}
+
+#[event]
+pub struct InferenceRated {
+    pub inference_id: u64,
+    pub model_address: Pubkey,
+    pub miner: Pubkey,
+    pub score: u8,
+    pub model_quality_score: u16,
+    pub miner_quality_score: u16,
+    pub dao_token_amount: u64,
+}
+
+#[event]
+pub struct QualityWeightUpdated {
+    pub new_quality_weight: u16,
+}
+
+#[event]
+pub struct ReferrerRegistered {
+    pub referrer: Pubkey,
+    pub code: String,
+}
+
+#[event]
+pub struct ReferrerSet {
+    pub user: Pubkey,
+    pub referrer: Pubkey,
+}
+
+#[event]
+pub struct DAOTokenEmissionLimited {
+    pub inference_id: u64,
+    pub requested: u64,
+    pub minted: u64,
+}
+
+#[event]
+pub struct DaoTokenMaxEmissionUpdated {
+    pub new_dao_token_max_emission: u64,
+}
+
+#[event]
+pub struct DaoTokenEpochBudgetUpdated {
+    pub new_dao_token_epoch_budget: u64,
+}
+
+#[event]
+pub struct DAOTokenMinted {
+    pub inference_id: u64,
+    pub model_address: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub role: u8,
+}
+
+#[event]
+pub struct DataFinalized {
+    pub owner: Pubkey,
+    pub data_id: u64,
+    pub hash: [u8; 32],
+    pub len: u32,
+}
+
+#[event]
+pub struct InferenceEscalated {
+    pub inference_id: u64,
+    pub round: u8,
+    pub validators: u8,
+    pub seed: [u8; 32],
+    // validators of the inconclusive round that revealed, they are not paid
+    pub unpaid_validators: u8,
 }
 
 #[event]
@@ -644,7 +1570,7 @@ pub struct RevealSubmission {
     pub sender: Pubkey,
     pub assignment_id: u64,
     pub nonce: u64,
-    pub data: Vec<u8>,
+    pub output: DataRef,
 }
 
 #[event]
@@ -662,6 +1588,54 @@ pub struct MinerDeactivated {
     pub active_time: u64,
 }
 
+#[event]
+pub struct FineCreatorPercentageUpdated {
+    pub new_fine_creator_percentage: u16,
+}
+
+#[event]
+pub struct MinerSlashed {
+    pub miner: Pubkey,
+    pub slash_record: Pubkey,
+    pub inference_id: u64,
+    pub assignment_id: u64,
+    pub reason: SlashReason,
+    pub amount: u64,
+}
+
+#[event]
+pub struct DisputeParamsUpdated {
+    pub new_dispute_window: u64,
+    pub new_dispute_bond: u64,
+}
+
+#[event]
+pub struct ArbiterUpdated {
+    pub new_arbiter: Pubkey,
+}
+
+#[event]
+pub struct DisputeOpened {
+    pub miner: Pubkey,
+    pub slash_record: Pubkey,
+    pub bond: u64,
+}
+
+#[event]
+pub struct DisputeResolved {
+    pub miner: Pubkey,
+    pub slash_record: Pubkey,
+    pub overturned: bool,
+}
+
+#[event]
+pub struct SlashExecuted {
+    pub miner: Pubkey,
+    pub slash_record: Pubkey,
+    pub treasury_fine: u64,
+    pub creator_fine: u64,
+}
+
 #[event]
 pub struct FinePercentageUpdated {
     // pub fine_percentage: u16,
@@ -708,4 +1682,294 @@ pub struct DaoTokenRewardUpdated {
     pub new_dao_token_reward: u64,
 }
 
+#[event]
+pub struct CrankBountyUpdated {
+    pub new_crank_bounty: u64,
+}
+
+#[event]
+pub struct TasksCranked {
+    pub inference_id: u64,
+    pub cranker: Pubkey,
+    pub processed: u64,
+    pub remaining: u64,
+    pub bounty: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comparator::COMPARATOR_EXACT;
+
+    fn round_trip(task: Task) {
+        let mut tasks = Tasks { bump: 0, values: vec![] };
+        tasks.push_task(task.clone());
+        assert_eq!(tasks.values[0], TASK_VERSION);
+        assert_eq!(tasks.pending().unwrap(), vec![task.clone()]);
+        assert_eq!(tasks.receive_task().unwrap(), task);
+        assert!(tasks.values.is_empty());
+    }
+
+    #[test]
+    fn data_ref_uri_follows_the_scheme() {
+        let data_ref = |scheme, uri: &str| DataRef {
+            scheme,
+            uri: uri.to_string(),
+            keccak: [1; 32],
+        };
+        assert!(DataRef::inline([1; 32]).validate().is_ok());
+        assert!(data_ref(DataScheme::Inline, "ipfs://x").validate().is_err());
+        assert!(data_ref(DataScheme::Ipfs, "ipfs://bafy").validate().is_ok());
+        assert!(data_ref(DataScheme::Ipfs, "ipfs://").validate().is_err());
+        assert!(data_ref(DataScheme::Arweave, "https://arweave.net/x").validate().is_err());
+        assert!(data_ref(DataScheme::Https, &format!("https://{}", "a".repeat(MAX_URI_LEN))).validate().is_err());
+    }
+
+    #[test]
+    fn off_chain_votes_join_inline_votes_with_the_same_content() {
+        let mut voting_info = voting_info(COMPARATOR_EXACT, 0);
+        let inline = voting_info.add_vote(1, b"answer").unwrap();
+        let content_hash = anchor_lang::solana_program::keccak::hash(b"answer").to_bytes();
+        assert_eq!(voting_info.add_ref_vote(1, &content_hash).unwrap(), inline);
+        assert_eq!(voting_info.tallies[0].count, 2);
+
+        voting_info.comparator = COMPARATOR_NUMERIC;
+        assert!(voting_info.add_ref_vote(1, &content_hash).is_err());
+    }
+
+    #[test]
+    fn chunked_data_is_read_only_once_finalized() {
+        let mut data = ChunkedData {
+            bump: 0,
+            owner: Pubkey::new_unique(),
+            data_id: 1,
+            hash: [0; 32],
+            finalized: false,
+            data: b"prompt".to_vec(),
+        };
+        assert_eq!(data.try_to_vec().unwrap().len(), ChunkedData::HEADER_LEN + 6);
+
+        let content_hash = anchor_lang::solana_program::keccak::hash(b"prompt").to_bytes();
+        assert!(data.content(&content_hash).is_err());
+        data.hash = content_hash;
+        data.finalized = true;
+        assert_eq!(data.content(&content_hash).unwrap(), b"prompt");
+        assert!(data.content(&[1; 32]).is_err());
+    }
+
+    #[test]
+    fn create_assignment_round_trips() {
+        round_trip(Task::CreateAssignment {
+            assignment_id: 7,
+            inference_id: u64::MAX,
+            worker: Pubkey::new_unique(),
+            role: 1,
+        });
+    }
+
+    #[test]
+    fn pay_miner_round_trips() {
+        round_trip(Task::PayMiner {
+            recipient: AssignmentOrPubkey::Assignment(3),
+            amount: 40_000,
+            vote: 2,
+        });
+        round_trip(Task::PayMiner {
+            recipient: AssignmentOrPubkey::Pubkey(Pubkey::new_unique()),
+            amount: u64::MAX,
+            vote: 0,
+        });
+    }
+
+    #[test]
+    fn slash_miner_round_trips() {
+        round_trip(Task::SlashMiner {
+            target: AssignmentOrPubkey::Assignment(u64::MAX),
+            is_fined: false,
+            check_empty_commit: true,
+            vote: 1,
+            reason: SlashReason::CommitTimeout,
+        });
+        round_trip(Task::SlashMiner {
+            target: AssignmentOrPubkey::Pubkey(Pubkey::new_unique()),
+            is_fined: true,
+            check_empty_commit: false,
+            vote: 0,
+            reason: SlashReason::SubmitTimeout,
+        });
+    }
+
+    #[test]
+    fn referral_codes_fit_a_seed() {
+        assert!(validate_referral_code("friend").is_ok());
+        assert!(validate_referral_code("").is_err());
+        assert!(validate_referral_code(&"a".repeat(MAX_REFERRAL_CODE_LEN)).is_ok());
+        assert!(validate_referral_code(&"a".repeat(MAX_REFERRAL_CODE_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn dao_token_is_prorated_over_the_receivers() {
+        let receiver = |amount| DAOTokenReceiverInfo {
+            receiver: AssignmentOrPubkey::Pubkey(Pubkey::new_unique()),
+            amount,
+            role: DAOTokenReceiverRole::User as u8,
+        };
+        let mut receivers = vec![receiver(600), receiver(300), receiver(100)];
+        assert_eq!(prorate_dao_token(&mut receivers, 2_000), 1_000);
+        assert_eq!(receivers[0].amount, 600);
+
+        assert_eq!(prorate_dao_token(&mut receivers, 500), 500);
+        let amounts: Vec<u64> = receivers.iter().map(|r| r.amount).collect();
+        assert_eq!(amounts, vec![300, 150, 50]);
+
+        assert_eq!(prorate_dao_token(&mut receivers, 0), 0);
+    }
+
+    #[test]
+    fn mint_dao_token_round_trips() {
+        round_trip(Task::MintDaoToken {
+            recipient: AssignmentOrPubkey::Assignment(3),
+            amount: u64::MAX,
+            role: DAOTokenReceiverRole::Validator as u8,
+        });
+        round_trip(Task::MintDaoToken {
+            recipient: AssignmentOrPubkey::Pubkey(Pubkey::new_unique()),
+            amount: 1,
+            role: DAOTokenReceiverRole::Referrer as u8,
+        });
+    }
+
+    fn voting_info(comparator: u8, tolerance: u64) -> VotingInfo {
+        VotingInfo {
+            bump: 0,
+            total_commit: 0,
+            total_reveal: 0,
+            comparator,
+            tolerance,
+            tallies: vec![],
+        }
+    }
+
+    #[test]
+    fn votes_are_tallied_per_digest() {
+        let mut voting_info = voting_info(COMPARATOR_EXACT, 0);
+        assert_eq!(voting_info.most_voted(), ([0; 32], 0));
+        let mut digests = vec![];
+        for output in [b"a", b"b", b"b", b"a", b"b"] {
+            digests.push(voting_info.add_vote(1, output).unwrap());
+        }
+        assert_eq!(voting_info.tallies.len(), 2);
+        assert_eq!(voting_info.most_voted(), (digests[1], 3));
+        voting_info.add_vote(1, b"a").unwrap();
+        // on a tie the digest seen first wins
+        assert_eq!(voting_info.most_voted(), (digests[0], 3));
+
+        voting_info.keep_only(digests[1]);
+        assert_eq!(voting_info.most_voted(), (digests[1], 1));
+        assert_eq!(voting_info.tallies.len(), 1);
+    }
+
+    #[test]
+    fn numeric_votes_join_the_first_group_in_tolerance() {
+        let numeric = |values: &[i64]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+        let mut voting_info = voting_info(COMPARATOR_NUMERIC, 10);
+        let first = voting_info.add_vote(1, &numeric(&[100, 200])).unwrap();
+        assert_eq!(voting_info.add_vote(1, &numeric(&[110, 195])).unwrap(), first);
+        // compared with the first output of the group, not the last one
+        let other = voting_info.add_vote(1, &numeric(&[120, 200])).unwrap();
+        assert_ne!(other, first);
+        assert_eq!(voting_info.most_voted(), (first, 2));
+        assert!(voting_info.add_vote(1, b"not numeric").is_err());
+    }
+
+    #[test]
+    fn tasks_space_follows_the_queue() {
+        let mut tasks = Tasks { bump: 0, values: vec![] };
+        for assignment_id in 0..MAX_ASSIGNMENTS as u64 {
+            tasks.push_task(Task::CreateAssignment {
+                assignment_id,
+                inference_id: 1,
+                worker: Pubkey::new_unique(),
+                role: 1,
+            });
+        }
+        let mut data = vec![];
+        tasks.try_serialize(&mut data).unwrap();
+        assert_eq!(tasks.space(), data.len());
+        assert!(tasks.space() > 512);
+    }
 
+    #[test]
+    fn tasks_are_received_in_order() {
+        let queued = vec![
+            Task::PayMiner {
+                recipient: AssignmentOrPubkey::Assignment(1),
+                amount: 10,
+                vote: 2,
+            },
+            Task::SlashMiner {
+                target: AssignmentOrPubkey::Assignment(2),
+                is_fined: false,
+                check_empty_commit: false,
+                vote: 1,
+                reason: SlashReason::WrongResult,
+            },
+            Task::PayMiner {
+                recipient: AssignmentOrPubkey::Pubkey(Pubkey::new_unique()),
+                amount: 5,
+                vote: 0,
+            },
+        ];
+        let mut tasks = Tasks { bump: 0, values: vec![] };
+        for task in queued.iter() {
+            tasks.push_task(task.clone());
+        }
+        assert_eq!(tasks.pending().unwrap(), queued);
+        for task in queued {
+            assert_eq!(tasks.receive_task().unwrap(), task);
+        }
+        assert!(tasks.receive_task().is_err());
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut tasks = Tasks { bump: 0, values: vec![] };
+        tasks.push_task(Task::PayMiner {
+            recipient: AssignmentOrPubkey::Assignment(1),
+            amount: 10,
+            vote: 2,
+        });
+        tasks.values[0] = TASK_VERSION + 1;
+        assert!(tasks.pending().is_err());
+        assert!(tasks.receive_task().is_err());
+        assert_eq!(tasks.values[0], TASK_VERSION + 1);
+    }
+
+    #[test]
+    fn v1_tasks_are_still_decoded() {
+        let mut tasks = Tasks { bump: 0, values: vec![TASK_VERSION_V1] };
+        TaskV1::SlashMiner {
+            target: AssignmentOrPubkey::Assignment(3),
+            is_fined: true,
+            check_empty_commit: false,
+            vote: 1,
+        }
+        .serialize(&mut tasks.values)
+        .unwrap();
+        tasks.push_task(Task::PayMiner {
+            recipient: AssignmentOrPubkey::Assignment(4),
+            amount: 10,
+            vote: 2,
+        });
+        let slash = Task::SlashMiner {
+            target: AssignmentOrPubkey::Assignment(3),
+            is_fined: true,
+            check_empty_commit: false,
+            vote: 1,
+            reason: SlashReason::Legacy,
+        };
+        assert_eq!(tasks.pending().unwrap().len(), 2);
+        assert_eq!(tasks.receive_task().unwrap(), slash);
+        assert_eq!(tasks.values[0], TASK_VERSION);
+    }
+}
@@ -1,12 +1,19 @@
-use std::collections::HashMap;
-
 use crate::errors::*;
 use crate::state_inf::*;
+use crate::DelegatorsRewarded;
+use crate::EpochUpdated;
 use crate::MinerInfo;
+use crate::MinerStats;
+use crate::MinerStakeExhausted;
 use crate::MinersOfModel;
+use crate::MAX_INFERENCE_SCORE;
 use crate::SolLearnInfo;
+use crate::SOL_LEARN_INFO_VERSION;
+use crate::selection::select_candidates;
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::keccak::hash;
+use anchor_lang::system_program;
+use anchor_spl::associated_token::{self, get_associated_token_address, Create};
+use anchor_spl::token::{self, MintTo, TokenAccount, Transfer};
 
 pub const PERCENTAGE_DENOMINATOR: u64 = 100_00;
 pub const BLOCK_PER_YEAR: u64 = 365 * 24 * 60 * 60 / 2; // 2s per block
@@ -37,15 +44,583 @@ pub fn validate_enough_fee_to_use(minimum_fee: u64, value: u64) -> Result<u64> {
 //     Ok(())
 // }
 
-pub fn only_updated_epoch(es: &mut SolLearnInfo) -> Result<()> {
-    let slot_number = Clock::get()?.slot;
-    let epoch_passed = (slot_number - es.last_block) / es.blocks_per_epoch;
-    if epoch_passed > 0 {
+pub fn only_updated_epoch(es: &SolLearnInfo) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp as u64;
+    if now >= es.last_time + es.epoch_duration {
         return Err(SolLearnError::NeedToWait.into());
     }
     Ok(())
 }
 
+pub fn only_current_version(es: &SolLearnInfo) -> Result<()> {
+    if es.version != SOL_LEARN_INFO_VERSION {
+        return Err(SolLearnError::OutdatedLayout.into());
+    }
+    Ok(())
+}
+
+// Splits reward_per_epoch of a finished epoch into the part shared equally by
+// the active miners and the part shared by completed tasks. Only `available`
+// (reward pool balance not owed yet) can be handed out.
+pub fn split_epoch_reward(
+    es: &SolLearnInfo,
+    total_miner: u64,
+    total_task_completed: u64,
+    available: u64,
+) -> (u64, u64) {
+    let perf_reward = if total_task_completed > 0 {
+        (es.reward_per_epoch * es.perf_reward_percentage as u64) / PERCENTAGE_DENOMINATOR
+    } else {
+        0
+    };
+    let epoch_reward = if total_miner > 0 {
+        es.reward_per_epoch - perf_reward
+    } else {
+        0
+    };
+
+    let perf_reward = perf_reward.min(available);
+    let mut epoch_reward = epoch_reward.min(available - perf_reward);
+    if total_miner > 0 {
+        // keep only what divides evenly between the miners
+        epoch_reward -= epoch_reward % total_miner;
+    }
+
+    (epoch_reward, perf_reward)
+}
+
+// Paying `amount` out of a token account holding `balance` must leave enough
+// to cover the liabilities that remain after the payment.
+pub fn check_solvency(balance: u64, amount: u64, remaining_liabilities: u64) -> Result<()> {
+    if balance < amount || balance - amount < remaining_liabilities {
+        return Err(SolLearnError::NoEnoughVault.into());
+    }
+    Ok(())
+}
+
+// Closes the current epoch and returns its snapshot, bump left to the caller.
+// Miners that joined during the epoch are not counted, they share rewards
+// from the next epoch on.
+pub fn close_epoch(es: &mut SolLearnInfo, pool_balance: u64) -> MinerEpochState {
+    let total_miner = es.total_active_miner.saturating_sub(es.epoch_joined_miners);
+    let total_task_completed = es.epoch_task_completed;
+    let available = pool_balance.saturating_sub(es.unclaimed_rewards);
+    let (epoch_reward, perf_reward) =
+        split_epoch_reward(es, total_miner, total_task_completed, available);
+
+    let snapshot = MinerEpochState {
+        perf_reward,
+        epoch_reward,
+        total_task_completed,
+        total_miner,
+        bump: 0,
+        epoch_id: es.last_epoch,
+    };
+
+    es.last_epoch += 1;
+    es.last_time += es.epoch_duration;
+    es.epoch_task_completed = 0;
+    es.epoch_joined_miners = 0;
+    es.unclaimed_rewards += epoch_reward + perf_reward;
+
+    snapshot
+}
+
+pub fn emit_epoch_updated(snapshot: &MinerEpochState) {
+    emit!(EpochUpdated {
+        epoch_id: snapshot.epoch_id,
+        epoch_reward: snapshot.epoch_reward,
+        perf_reward: snapshot.perf_reward,
+        total_miner: snapshot.total_miner,
+        total_task_completed: snapshot.total_task_completed,
+    });
+}
+
+pub fn activate_miner(es: &mut SolLearnInfo, miner: &mut MinerInfo) {
+    miner.is_active = true;
+    miner.joined_epoch = es.last_epoch;
+    es.total_active_miner += 1;
+    es.epoch_joined_miners += 1;
+}
+
+// The miner is owed no epoch reward from the current epoch on.
+pub fn deactivate_miner(es: &mut SolLearnInfo, miner: &mut MinerInfo) {
+    miner.is_active = false;
+    miner.deactivated_epoch = es.last_epoch;
+    es.total_active_miner = es.total_active_miner.saturating_sub(1);
+    if miner.joined_epoch == es.last_epoch {
+        es.epoch_joined_miners = es.epoch_joined_miners.saturating_sub(1);
+    }
+}
+
+// A task only counts towards the perf reward once the miner has a history
+// slot for it, otherwise its share would be owed to nobody and stay locked
+// in unclaimed_rewards.
+pub fn record_task_completed(es: &mut SolLearnInfo, miner: &mut MinerInfo) {
+    if miner.record_task(es.last_epoch) {
+        es.epoch_task_completed += 1;
+    }
+}
+
+// Moves last_epoch of an inactive miner past the epochs it is owed nothing
+// for, so a miner that left does not need to claim every epoch it missed.
+pub fn skip_idle_epochs(miner: &mut MinerInfo, current_epoch: u64) {
+    if miner.is_active || miner.last_epoch < miner.deactivated_epoch {
+        return;
+    }
+
+    let next = miner.first_task_epoch().unwrap_or(current_epoch).min(current_epoch);
+    if next > miner.last_epoch {
+        miner.last_epoch = next;
+    }
+}
+
+pub fn load_epoch_snapshot(info: &AccountInfo, program_id: &Pubkey) -> Result<MinerEpochState> {
+    if info.owner != program_id {
+        return Err(SolLearnError::InvalidEpochId.into());
+    }
+    let snapshot = MinerEpochState::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let expected = Pubkey::create_program_address(
+        &[
+            b"reward_in_epoch",
+            snapshot.epoch_id.to_le_bytes().as_ref(),
+            &[snapshot.bump],
+        ],
+        program_id,
+    )
+    .map_err(|_| SolLearnError::InvalidEpochId)?;
+    if expected != info.key() {
+        return Err(SolLearnError::InvalidEpochId.into());
+    }
+
+    Ok(snapshot)
+}
+
+// compute units kept in reserve for one more task and the bounty transfer
+pub const CRANK_TASK_COMPUTE: u64 = 40_000;
+
+// number of remaining accounts crank_tasks takes for a task
+pub fn task_account_count(task: &Task) -> usize {
+    match task {
+        Task::MintDaoToken {
+            recipient: AssignmentOrPubkey::Assignment(_),
+            ..
+        } => 3,
+        Task::MintDaoToken { role, .. } if *role == DAOTokenReceiverRole::Referrer as u8 => 3,
+        Task::MintDaoToken { .. } => 2,
+        Task::CreateAssignment { .. } => 1,
+        Task::PayMiner {
+            recipient: AssignmentOrPubkey::Assignment(_),
+            ..
+        } => 4,
+        Task::PayMiner { .. } => 1,
+        Task::SlashMiner {
+            target: AssignmentOrPubkey::Assignment(_),
+            ..
+        } => 4,
+        Task::SlashMiner { .. } => 3,
+    }
+}
+
+// Loaders for accounts passed in remaining accounts: the owner, the
+// discriminator and the PDA (from the bump stored in the account) are checked.
+pub fn load_assignment(info: &AccountInfo, program_id: &Pubkey) -> Result<Assignment> {
+    if info.owner != program_id {
+        return Err(SolLearnError::WrongAssignmentId.into());
+    }
+    let assignment = Assignment::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let expected = Pubkey::create_program_address(
+        &[
+            b"assignment",
+            assignment.id.to_le_bytes().as_ref(),
+            &[assignment.bump],
+        ],
+        program_id,
+    )
+    .map_err(|_| SolLearnError::WrongAssignmentId)?;
+    if expected != info.key() {
+        return Err(SolLearnError::WrongAssignmentId.into());
+    }
+
+    Ok(assignment)
+}
+
+pub fn load_referrer(info: &AccountInfo, program_id: &Pubkey) -> Result<Referrer> {
+    if info.owner != program_id {
+        return Err(SolLearnError::WrongReferrer.into());
+    }
+    let referrer = Referrer::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let expected = Pubkey::create_program_address(
+        &[b"referrer", referrer.user.as_ref(), &[referrer.bump]],
+        program_id,
+    )
+    .map_err(|_| SolLearnError::WrongReferrer)?;
+    if expected != info.key() {
+        return Err(SolLearnError::WrongReferrer.into());
+    }
+
+    Ok(referrer)
+}
+
+pub fn load_miner(info: &AccountInfo, sol_learn: &Pubkey, program_id: &Pubkey) -> Result<MinerInfo> {
+    if info.owner != program_id {
+        return Err(SolLearnError::Unauthorized.into());
+    }
+    let miner = MinerInfo::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let expected = Pubkey::create_program_address(
+        &[b"miner", miner.miner.as_ref(), sol_learn.as_ref(), &[miner.bump]],
+        program_id,
+    )
+    .map_err(|_| SolLearnError::Unauthorized)?;
+    if expected != info.key() {
+        return Err(SolLearnError::Unauthorized.into());
+    }
+
+    Ok(miner)
+}
+
+pub fn load_miner_stats(info: &AccountInfo, sol_learn: &Pubkey, program_id: &Pubkey) -> Result<MinerStats> {
+    if info.owner != program_id {
+        return Err(SolLearnError::Unauthorized.into());
+    }
+    let stats = MinerStats::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let expected = Pubkey::create_program_address(
+        &[b"miner_stats", stats.miner.as_ref(), sol_learn.as_ref(), &[stats.bump]],
+        program_id,
+    )
+    .map_err(|_| SolLearnError::Unauthorized)?;
+    if expected != info.key() {
+        return Err(SolLearnError::Unauthorized.into());
+    }
+
+    Ok(stats)
+}
+
+pub fn load_wallet(info: &AccountInfo, mint: &Pubkey) -> Result<TokenAccount> {
+    if info.owner != &token::ID {
+        return Err(SolLearnError::WrongRecipient.into());
+    }
+    let wallet = TokenAccount::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    if wallet.mint != *mint {
+        return Err(SolLearnError::InvalidToken.into());
+    }
+
+    Ok(wallet)
+}
+
+pub fn store_account<T: AccountSerialize>(info: &AccountInfo, account: &T) -> Result<()> {
+    account.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])
+}
+
+// Creates an account owned by the program at the PDA of `seeds`, rent paid
+// by `payer`.
+pub fn create_pda_account<'info>(
+    system_program: AccountInfo<'info>,
+    payer: AccountInfo<'info>,
+    account: AccountInfo<'info>,
+    seeds: &[&[u8]],
+    space: usize,
+    program_id: &Pubkey,
+) -> Result<()> {
+    system_program::create_account(
+        CpiContext::new(
+            system_program,
+            system_program::CreateAccount {
+                from: payer,
+                to: account,
+            },
+        )
+        .with_signer(&[seeds]),
+        Rent::get()?.minimum_balance(space),
+        space as u64,
+        program_id,
+    )
+}
+
+// Grows the tasks account to fit its queue, the extra rent paid by `payer`.
+pub fn fit_tasks<'info>(
+    tasks: &Account<'info, Tasks>,
+    payer: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+) -> Result<()> {
+    grow_account(tasks.to_account_info(), tasks.space(), payer, system_program)
+}
+
+// Grows `info` to `space` bytes, the extra rent paid by `payer`.
+pub fn grow_account<'info>(
+    info: AccountInfo<'info>,
+    space: usize,
+    payer: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+) -> Result<()> {
+    if space <= info.data_len() {
+        return Ok(());
+    }
+    let rent = Rent::get()?.minimum_balance(space).saturating_sub(info.lamports());
+    if rent > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program,
+                system_program::Transfer {
+                    from: payer,
+                    to: info.clone(),
+                },
+            ),
+            rent,
+        )?;
+    }
+    info.realloc(space, false)?;
+
+    Ok(())
+}
+
+pub fn pages_for(assignment_count: usize) -> usize {
+    assignment_count.div_ceil(ASSIGNMENTS_PER_PAGE)
+}
+
+pub fn load_assignment_page(
+    info: &AccountInfo,
+    inference_id: u64,
+    page: usize,
+    program_id: &Pubkey,
+) -> Result<AssignmentPage> {
+    if info.owner != program_id {
+        return Err(SolLearnError::InvalidAssignmentPage.into());
+    }
+    let stored = AssignmentPage::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let expected = Pubkey::create_program_address(
+        &[
+            b"assignment_page",
+            stored.inference_id.to_le_bytes().as_ref(),
+            &[stored.page],
+            &[stored.bump],
+        ],
+        program_id,
+    )
+    .map_err(|_| SolLearnError::InvalidAssignmentPage)?;
+    if expected != info.key() || stored.inference_id != inference_id || stored.page as usize != page {
+        return Err(SolLearnError::InvalidAssignmentPage.into());
+    }
+
+    Ok(stored)
+}
+
+// Reads the assignments of the current round from the pages of the
+// inference, passed first in `infos` in page order.
+pub fn load_assignment_list(
+    infos: &[AccountInfo],
+    inference: &Inference,
+    program_id: &Pubkey,
+) -> Result<AssignmentList> {
+    let mut list = AssignmentList::default();
+    for page in 0..pages_for(inference.assignment_count as usize) {
+        let info = infos.get(page).ok_or(SolLearnError::MissingAssignmentPages)?;
+        let stored = load_assignment_page(info, inference.id, page, program_id)?;
+        list.ids.extend(stored.assignments);
+        list.digests.extend(stored.digests);
+    }
+    if list.ids.len() != inference.assignment_count as usize {
+        return Err(SolLearnError::InvalidAssignmentPage.into());
+    }
+
+    Ok(list)
+}
+
+// Writes `list` over the pages of the inference, creating the pages not
+// used before with rent from `payer`.
+pub fn store_assignment_list<'info>(
+    infos: &[AccountInfo<'info>],
+    inference_id: u64,
+    list: &AssignmentList,
+    payer: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    program_id: &Pubkey,
+) -> Result<()> {
+    let id_bytes = inference_id.to_le_bytes();
+    let chunks = list
+        .ids
+        .chunks(ASSIGNMENTS_PER_PAGE)
+        .zip(list.digests.chunks(ASSIGNMENTS_PER_PAGE));
+    for (page, (ids, digests)) in chunks.enumerate() {
+        let info = infos.get(page).ok_or(SolLearnError::MissingAssignmentPages)?;
+        let bump = if info.owner == program_id {
+            load_assignment_page(info, inference_id, page, program_id)?.bump
+        } else {
+            let (expected, bump) = Pubkey::find_program_address(
+                &[b"assignment_page", id_bytes.as_ref(), &[page as u8]],
+                program_id,
+            );
+            if expected != info.key() {
+                return Err(SolLearnError::InvalidAssignmentPage.into());
+            }
+            create_pda_account(
+                system_program.clone(),
+                payer.clone(),
+                info.clone(),
+                &[b"assignment_page", id_bytes.as_ref(), &[page as u8], &[bump]],
+                ANCHOR_DISCRIMINATOR + AssignmentPage::INIT_SPACE,
+                program_id,
+            )?;
+            bump
+        };
+        let stored = AssignmentPage {
+            bump,
+            inference_id,
+            page: page as u8,
+            assignments: ids.to_vec(),
+            digests: digests.to_vec(),
+        };
+        store_account(info, &stored)?;
+    }
+
+    Ok(())
+}
+
+// Sets the digest of one assignment, only its page is written.
+pub fn set_assignment_digest(
+    infos: &[AccountInfo],
+    inference: &Inference,
+    assignment_id: u64,
+    digest: [u8; 32],
+    program_id: &Pubkey,
+) -> Result<()> {
+    for page in 0..pages_for(inference.assignment_count as usize) {
+        let info = infos.get(page).ok_or(SolLearnError::MissingAssignmentPages)?;
+        let mut stored = load_assignment_page(info, inference.id, page, program_id)?;
+        if let Some(index) = stored.assignments.iter().position(|&id| id == assignment_id) {
+            stored.digests[index] = digest;
+            return store_account(info, &stored);
+        }
+    }
+
+    Err(SolLearnError::Unauthorized.into())
+}
+
+// Content of an Inline reference and the address of its ChunkedData, which
+// `owner` must own when given. Off-chain references have no content here.
+pub fn read_data_ref<'a, 'info>(
+    data_ref: &DataRef,
+    data: &'a Option<Box<Account<'info, ChunkedData>>>,
+    owner: Option<Pubkey>,
+) -> Result<(Pubkey, Option<&'a [u8]>)> {
+    data_ref.validate()?;
+    if data_ref.scheme != DataScheme::Inline {
+        return Ok((Pubkey::default(), None));
+    }
+    let data = match data {
+        Some(data) if owner.is_none_or(|owner| data.owner == owner) => data,
+        _ => return Err(SolLearnError::Unauthorized.into()),
+    };
+    Ok((data.key(), Some(data.content(&data_ref.keccak)?)))
+}
+
+// Mints the DAO token, the vault PDA is its mint authority.
+pub fn mint_from_vault<'info>(
+    token_program: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    vault_pda: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    sol_learn: &Pubkey,
+    vault_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = MintTo {
+        mint,
+        to,
+        authority: vault_pda,
+    };
+    let seeds = [b"vault", sol_learn.as_ref(), &[vault_bump]];
+    let signer_seeds = &[&seeds[..]];
+    let cpi_ctx = CpiContext::new(token_program, cpi_accounts).with_signer(signer_seeds);
+    token::mint_to(cpi_ctx, amount)
+}
+
+// Creates the associated DAO token wallet of `owner` when `wallet` does not
+// exist yet, so a receiver without one does not block the task queue.
+pub fn ensure_dao_wallet<'info>(
+    associated_token_program: Option<AccountInfo<'info>>,
+    token_program: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    payer: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    wallet: AccountInfo<'info>,
+    owner: AccountInfo<'info>,
+) -> Result<()> {
+    if !wallet.data_is_empty() {
+        return Ok(());
+    }
+    if wallet.key() != get_associated_token_address(owner.key, mint.key) {
+        return Err(SolLearnError::WrongRecipient.into());
+    }
+    let associated_token_program =
+        associated_token_program.ok_or(SolLearnError::MissingTaskAccounts)?;
+    let cpi_accounts = Create {
+        payer,
+        associated_token: wallet,
+        authority: owner,
+        mint,
+        system_program,
+        token_program,
+    };
+    associated_token::create_idempotent(CpiContext::new(associated_token_program, cpi_accounts))
+}
+
+// Transfers out of the vault wallet, signed by the vault PDA.
+pub fn transfer_from_vault<'info>(
+    token_program: AccountInfo<'info>,
+    vault_wallet: AccountInfo<'info>,
+    vault_pda: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    sol_learn: &Pubkey,
+    vault_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let cpi_accounts = Transfer {
+        from: vault_wallet,
+        to,
+        authority: vault_pda,
+    };
+    let seeds = [b"vault", sol_learn.as_ref(), &[vault_bump]];
+    let signer_seeds = &[&seeds[..]];
+    let cpi_ctx = CpiContext::new(token_program, cpi_accounts).with_signer(signer_seeds);
+    token::transfer(cpi_ctx, amount)
+}
+
+// Keeps the delegators' cut of a payment to `miner` in the vault as delegated
+// stake and returns what is left for the miner itself.
+pub fn credit_delegators(acc: &mut SolLearnInfo, miner: &mut MinerInfo, amount: u64) -> u64 {
+    let cut = miner.delegators_cut(amount);
+    if cut > 0 {
+        miner.delegated_stake += cut;
+        acc.total_stake += cut;
+        emit!(DelegatorsRewarded {
+            miner: miner.miner,
+            amount: cut,
+        });
+    }
+    amount - cut
+}
+
+// Reward of a miner for one epoch snapshot: an equal share of epoch_reward if
+// it was active through the epoch, plus perf_reward pro rata to its tasks.
+pub fn epoch_reward_of(miner: &mut MinerInfo, snapshot: &MinerEpochState) -> u64 {
+    let mut reward = 0;
+    let was_active = miner.joined_epoch < snapshot.epoch_id
+        && (miner.is_active || snapshot.epoch_id < miner.deactivated_epoch);
+    if was_active && snapshot.total_miner > 0 {
+        reward += snapshot.epoch_reward / snapshot.total_miner;
+    }
+
+    let tasks = miner.take_tasks(snapshot.epoch_id);
+    if tasks > 0 && snapshot.total_task_completed > 0 {
+        let tasks = tasks.min(snapshot.total_task_completed);
+        reward += ((snapshot.perf_reward as u128 * tasks as u128)
+            / snapshot.total_task_completed as u128) as u64;
+    }
+
+    reward
+}
+
 pub fn only_empty_tasks(t: &mut Tasks) -> Result<()> {
     if t.values.len() > 0 {
         return Err(SolLearnError::MustWaitTasks.into());
@@ -58,22 +633,16 @@ pub fn _slash_miner(
     is_fined: bool,
     acc: &mut SolLearnInfo,
     miner_addresses: &mut MinersOfModel,
-) -> Result<u64> {
+) -> Result<(u64, u64, ForcedUnstake)> {
     // if !acc.miner_addresses.values.contains(&miner.miner) {
     //     return Err(SolLearnError::Unauthorized.into());
     // }
 
     // _claim_reward(miner, false);
-    let mut remove_ind = 0;
-    for i in 0..(miner_addresses.data.len()/32) {
-        let pubkey_bytes: [u8; 32] = miner_addresses.data[i*32..(i+1)*32].try_into().unwrap();
-        
-        if pubkey_bytes == miner.miner.to_bytes() {
-            remove_ind = i;
-            break;
-        }
+    if let Some(remove_ind) = miner_addresses.position(&miner.miner) {
+        miner_addresses.remove(remove_ind);
+        deactivate_miner(acc, miner);
     }
-    miner_addresses.data.drain(remove_ind*32..(remove_ind+1)*32);
     
     miner.active_time = Clock::get()?.slot + acc.penalty_duration;
     emit!(MinerDeactivated {
@@ -85,12 +654,17 @@ pub fn _slash_miner(
 
 
     if is_fined {
-        let fine = (acc.miner_minimum_stake * acc.fine_percentage as u64) / PERCENTAGE_DENOMINATOR;
-        if miner.stake_amount < fine {
-            miner.stake_amount = 0;
-        } else {
-            miner.stake_amount -= fine;
-        }
+        // never fine more than the miner and its delegators have staked or
+        // queued for unstaking, the rest of the vault belongs to other miners
+        // and inference escrow
+        let delegated_before = miner.delegated_stake;
+        let fine = miner.take_fine(miner.fine(
+            acc.fine_model,
+            acc.miner_minimum_stake,
+            acc.fine_percentage,
+        ));
+        miner.offence_count += 1;
+        acc.total_stake = acc.total_stake.saturating_sub(fine);
         emit!(MinerPenalized {
             miner: miner.miner,
             model_address: miner.miner,
@@ -98,7 +672,20 @@ pub fn _slash_miner(
             fine,
         });
 
-        Ok(fine)
+        // a miner fined under the minimum stake is unstaked, it has to top up
+        // and rejoin to mine again
+        let mut forced = ForcedUnstake::default();
+        if fine > 0 && miner.stake_amount < acc.miner_minimum_stake {
+            let unlock_time = Clock::get()?.unix_timestamp as u64 + acc.unstake_delay_time;
+            forced = miner.force_unstake(unlock_time);
+            emit!(MinerStakeExhausted {
+                miner: miner.miner,
+                unstaked: forced.amount,
+                unlock_time,
+            });
+        }
+
+        Ok((fine, delegated_before - miner.delegated_stake, forced))
 
         // boost[_miner].reserved1 = 0;
         // system_program::transfer(
@@ -112,79 +699,132 @@ pub fn _slash_miner(
         //     inference.value + inference.fee_l2 + inference.fee_treasury,
         // )?;
     } else {
-        Ok(0)
+        Ok((0, 0, ForcedUnstake::default()))
     }
 }
 
-pub fn calculate_transferred_dao_token(
+// Splits a fine into the treasury part and the part refunded to the creator
+// of the inference.
+pub fn split_fine(acc: &SolLearnInfo, fine: u64) -> (u64, u64) {
+    let creator = (fine * acc.fine_creator_percentage as u64) / PERCENTAGE_DENOMINATOR;
+    (fine - creator, creator)
+}
+
+// Builds the next slash record of `miner`, stored at `record`.
+pub fn record_slash(
+    miner: &mut MinerInfo,
+    record: Pubkey,
+    bump: u8,
+    inference_id: u64,
+    assignment_id: u64,
+    reason: SlashReason,
+    amount: u64,
+) -> SlashRecord {
+    miner.slash_count += 1;
+    emit!(MinerSlashed {
+        miner: miner.miner,
+        slash_record: record,
+        inference_id,
+        assignment_id,
+        reason,
+        amount,
+    });
+
+    SlashRecord {
+        bump,
+        miner: miner.miner,
+        inference_id,
+        assignment_id,
+        reason,
+        amount,
+        delegated_amount: 0,
+        creator_wallet: Pubkey::default(),
+        status: SLASH_EXECUTED,
+        executable_at: 0,
+        bond: 0,
+        forced_unstake: ForcedUnstake::default(),
+    }
+}
+
+// Holds the fine of a new slash record in the vault for the dispute window,
+// as escrow so it can still be given back. Returns whether the fine is due
+// at once instead.
+pub fn hold_fine(
     acc: &mut SolLearnInfo,
-    inference: &mut Inference,
+    record: &mut SlashRecord,
+    delegated: u64,
+    creator_wallet: Pubkey,
+) -> Result<bool> {
+    record.delegated_amount = delegated;
+    record.creator_wallet = creator_wallet;
+    if record.amount == 0 || acc.dispute_window == 0 {
+        return Ok(true);
+    }
+
+    record.status = SLASH_PENDING;
+    record.executable_at = Clock::get()?.unix_timestamp as u64 + acc.dispute_window;
+    acc.total_escrow += record.amount;
+    Ok(false)
+}
+
+// DAO token the user receives for rating an inference `score`, a share of
+// user_percentage that grows with the score
+pub fn user_dao_token_received(acc: &SolLearnInfo, score: u8) -> u64 {
+    ((acc.dao_token_percentage.user_percentage as u64) * (score as u64) * acc.dao_token_reward)
+        / (MAX_INFERENCE_SCORE as u64)
+        / PERCENTAGE_DENOMINATOR
+}
+
+// Shares minted on resolution; the user's comes with rate_inference.
+pub fn calculate_transferred_dao_token(
+    acc: &SolLearnInfo,
+    inference: &Inference,
     is_referred: bool,
-) -> Result<()> {
-    let l2_owner_amt = (acc.dao_token_reward
-        * u64::from(acc.dao_token_percentage.l2_owner_percentage))
-        / PERCENTAGE_DENOMINATOR;
-    // dao_receivers.values = Vec::new();
-    // dao_receivers.values.insert(
-    //     0,
-    //     DAOTokenReceiverInfo {
-    //         receiver: acc.l2_owner,
-    //         amount: l2_owner_amt,
-    //         role: 5, // DAOTokenReceiverRole::L2Owner
-    //     },
-    // );
+    receivers: &mut Vec<DAOTokenReceiverInfo>,
+) {
+    let share = |percentage: u16| (acc.dao_token_reward * u64::from(percentage)) / PERCENTAGE_DENOMINATOR;
+
+    receivers.push(DAOTokenReceiverInfo {
+        receiver: AssignmentOrPubkey::Pubkey(acc.l2_owner),
+        amount: share(acc.dao_token_percentage.l2_owner_percentage),
+        role: DAOTokenReceiverRole::L2Owner as u8,
+    });
 
     if is_referred {
-        let referee_amt = (acc.dao_token_reward
-            * u64::from(acc.dao_token_percentage.referee_percentage))
-            / PERCENTAGE_DENOMINATOR;
-        let referer_amt = (acc.dao_token_reward
-            * u64::from(acc.dao_token_percentage.referrer_percentage))
-            / PERCENTAGE_DENOMINATOR;
-
-        // dao_receivers.values.insert(
-        //     0,
-        //     DAOTokenReceiverInfo {
-        //         receiver: inference.creator,
-        //         amount: referee_amt,
-        //         role: 4, // DAOTokenReceiverRole::Referee
-        //     },
-        // );
-
-        // dao_receivers.values.insert(
-        //     0,
-        //     DAOTokenReceiverInfo {
-        //         receiver: inference.referrer,
-        //         amount: referer_amt,
-        //         role: 3, // DAOTokenReceiverRole::Referrer
-        //     },
-        // );
+        receivers.push(DAOTokenReceiverInfo {
+            receiver: AssignmentOrPubkey::Pubkey(inference.creator),
+            amount: share(acc.dao_token_percentage.referee_percentage),
+            role: DAOTokenReceiverRole::Referee as u8,
+        });
+        receivers.push(DAOTokenReceiverInfo {
+            receiver: AssignmentOrPubkey::Pubkey(inference.referrer),
+            amount: share(acc.dao_token_percentage.referrer_percentage),
+            role: DAOTokenReceiverRole::Referrer as u8,
+        });
     }
-
-    Ok(())
 }
 
+// Votes come from the tallies of voting_info, the digest of each assignment
+// from `list`.
 pub fn filter_commitment(
     acc: &mut SolLearnInfo,
     inference: &mut Inference,
-    assignment: &mut Assignment,
+    voting_info: &VotingInfo,
+    list: &AssignmentList,
     tasks: &mut Tasks,
+    dao_receivers: &mut DAOTokenReceiverInfos,
 ) -> Result<bool> {
-    // let acc = &mut ctx.accounts.wh_account;
-    // let inference = &mut ctx.accounts.infs;
-    // let assignment = &ctx.accounts.assignment;
-    // let dao_receivers = &mut ctx.accounts.dao_receiver_infos;
-    let digests = &inference.digests;
-
-    let (most_voted_digest, max_count) = find_most_voted_digest(digests.values.clone())?;
-    if (max_count as u64) < get_threshold_value(inference.assignments.len() as u64) {
+    let (most_voted_digest, max_count) = voting_info.most_voted();
+    if max_count < get_threshold_value(list.ids.len() as u64) {
         return Ok(false);
     }
 
     let is_referred = inference.referrer != Pubkey::default();
-    let not_reached_limit = true; // validate_dao_supply_increase(is_referred);
+    acc.roll_dao_token_epoch();
+    let not_reached_limit = acc.dao_token != Pubkey::default() && acc.dao_token_mintable() > 0;
+    let mut receivers = vec![];
 
-    let is_match_miner_result = assignment.digest == most_voted_digest;
+    let is_match_miner_result = list.digest_of(inference.mining_assignment) == most_voted_digest;
 
     let mut fee_for_miner = 0;
     let mut share_fee_per_validator = 0;
@@ -195,10 +835,13 @@ pub fn filter_commitment(
         / PERCENTAGE_DENOMINATOR;
 
     if not_reached_limit && remain_token > 0 {
-        calculate_transferred_dao_token(acc, inference, is_referred)?;
+        calculate_transferred_dao_token(acc, inference, is_referred, &mut receivers);
     }
 
-    if is_match_miner_result {
+    if is_match_miner_result && max_count == 1 {
+        fee_for_miner = remain_value;
+        token_for_miner = remain_token;
+    } else if is_match_miner_result {
         fee_for_miner =
             (remain_value * acc.fee_ratio_miner_validator as u64) / PERCENTAGE_DENOMINATOR;
         share_fee_per_validator = (remain_value - fee_for_miner) / (max_count - 1);
@@ -210,39 +853,39 @@ pub fn filter_commitment(
         share_token_per_validator = remain_token / max_count;
     }
 
-    for i in 0..inference.assignments.len() {
+    for i in 0..list.ids.len() {
         // let assignment = &assignment[assignment_ids[i]];
-        if inference.digests.values[i] != most_voted_digest {
+        if list.digests[i] != most_voted_digest {
             // assignment.vote = 1; // Vote::Disapproval
             // slash_miner(ctx, assignment.worker, true)?;
-            let mut data = vec![];
-            data.push(0);
-            data.extend_from_slice(&inference.assignments[i].to_le_bytes());
-            data.push(0);
-            data.push(0);
-            data.push(1);
-            tasks.push_task(Task::new(FnType::SlashMiner, data));
+            tasks.push_task(Task::SlashMiner {
+                target: AssignmentOrPubkey::Assignment(list.ids[i]),
+                is_fined: false,
+                check_empty_commit: false,
+                vote: 1,
+                reason: if list.digests[i] == [0; 32] {
+                    SlashReason::RevealTimeout
+                } else {
+                    SlashReason::WrongResult
+                },
+            });
         } else {
             // assignment.vote = 2; // Vote::Approval
-            if assignment.role == 1 {
+            if list.ids[i] != inference.mining_assignment {
                 // AssignmentRole::Validating
                 if share_fee_per_validator > 0 {
-                    let mut data = vec![];
-                    data.push(1);
-                    data.extend_from_slice(&assignment.id.to_le_bytes());
-                    data.extend_from_slice(&share_fee_per_validator.to_le_bytes());
-                    data.push(2);
-                    tasks.push_task(Task::new(FnType::PayMiner, data));
+                    tasks.push_task(Task::PayMiner {
+                        recipient: AssignmentOrPubkey::Assignment(list.ids[i]),
+                        amount: share_fee_per_validator,
+                        vote: 2,
+                    });
                 }
-                if not_reached_limit && token_for_miner > 0 {
-                    // dao_receivers.values.insert(
-                    //     0,
-                    //     DAOTokenReceiverInfo {
-                    //         receiver: assignment.worker,
-                    //         amount: share_token_per_validator,
-                    //         role: 1, // DAOTokenReceiverRole::Validator
-                    //     },
-                    // );
+                if not_reached_limit && share_token_per_validator > 0 {
+                    receivers.push(DAOTokenReceiverInfo {
+                        receiver: AssignmentOrPubkey::Assignment(list.ids[i]),
+                        amount: share_token_per_validator,
+                        role: DAOTokenReceiverRole::Validator as u8,
+                    });
                 }
             } else {
                 if fee_for_miner > 0 {
@@ -250,80 +893,149 @@ pub fn filter_commitment(
                     //     assignment.worker,
                     //     fee_for_miner
                     // );
-                    let mut data = vec![];
-                    data.push(1);
-                    data.extend_from_slice(&assignment.id.to_le_bytes());
-                    data.extend_from_slice(&fee_for_miner.to_le_bytes());
-                    data.push(2);
-                    tasks.push_task(Task::new(FnType::PayMiner, data));
+                    tasks.push_task(Task::PayMiner {
+                        recipient: AssignmentOrPubkey::Assignment(list.ids[i]),
+                        amount: fee_for_miner,
+                        vote: 2,
+                    });
                 }
                 if not_reached_limit && token_for_miner > 0 {
-                    // dao_receivers.values.insert(
-                    //     0,
-                    //     DAOTokenReceiverInfo {
-                    //         receiver: assignment.worker,
-                    //         amount: token_for_miner,
-                    //         role: 0, // DAOTokenReceiverRole::Miner
-                    //     },
-                    // );
+                    receivers.push(DAOTokenReceiverInfo {
+                        receiver: AssignmentOrPubkey::Assignment(list.ids[i]),
+                        amount: token_for_miner,
+                        role: DAOTokenReceiverRole::Miner as u8,
+                    });
                 }
             }
         }
     }
 
-    // if not_reached_limit && dao_receivers.values.len() > 0 {
-    //     let receivers_inf = dao_receivers;
-    //     for i in 0..receivers_inf.values.len() {
-            // IDAOToken(dao_token).mint(
-            //     receivers_inf[i].receiver,
-            //     receivers_inf[i].amount
-            // );
-        // }
-
-        // emit DAOTokenMintedV2(
-        //     _getChainID(),
-        //     _inferenceId,
-        //     inferences[_inferenceId].modelAddress,
-        //     receiversInf
-        // );
-    // }
-
     if inference.fee_l2 > 0 {
-        let mut data = vec![];
-        data.push(0);
-        data.extend(acc.l2_owner.to_bytes());
-        data.extend_from_slice(&inference.fee_l2.to_le_bytes());
-        tasks.push_task(Task::new(FnType::PayMiner, data));
-    
+        tasks.push_task(Task::PayMiner {
+            recipient: AssignmentOrPubkey::Pubkey(acc.l2_owner),
+            amount: inference.fee_l2,
+            vote: 0,
+        });
     }
     if inference.fee_treasury > 0 {
-        let mut data = vec![];
-        data.push(0);
-        data.extend(acc.treasury.to_bytes());
-        data.extend_from_slice(&inference.fee_treasury.to_le_bytes());
-        tasks.push_task(Task::new(FnType::PayMiner, data));
+        tasks.push_task(Task::PayMiner {
+            recipient: AssignmentOrPubkey::Pubkey(acc.treasury),
+            amount: inference.fee_treasury,
+            vote: 0,
+        });
     }
 
+    // minted after the fees are paid, within what the emission limits leave
+    let requested: u64 = receivers.iter().map(|r| r.amount).sum();
+    let minted = prorate_dao_token(&mut receivers, acc.dao_token_mintable());
+    acc.record_dao_token_minted(minted);
+    if minted < requested {
+        emit!(DAOTokenEmissionLimited {
+            inference_id: inference.id,
+            requested,
+            minted,
+        });
+    }
+    receivers.retain(|r| r.amount > 0);
+    for receiver in receivers.iter() {
+        tasks.push_task(Task::MintDaoToken {
+            recipient: receiver.receiver,
+            amount: receiver.amount,
+            role: receiver.role,
+        });
+    }
+    dao_receivers.values = receivers;
+
     inference.status = 4;
+    inference.solved = true;
 
     Ok(true)
 }
 
-pub fn find_most_voted_digest(list_digests: Vec<[u8; 32]>) -> Result<([u8; 32], u64)> {
-    let mut max_count = 0;
-    let mut most_voted_digest = list_digests[0];
-    let mut counts: HashMap<[u8; 32], u64> = HashMap::new();
+// Starts another validation round of the miner's output after an
+// inconclusive vote: validators of the last round that did not reveal are
+// slashed as on a refund, the others are dropped unpaid (counted in
+// InferenceEscalated), and a larger set is drawn from the miners of the model
+// (the processed miner excluded). The new round shares the whole
+// inference.value like the first one would have. `list` becomes the
+// assignments of the new round. Returns false when no round is left or no
+// validator can be drawn, the inference is refunded then.
+pub fn escalate(
+    acc: &mut SolLearnInfo,
+    inference: &mut Inference,
+    voting_info: &mut VotingInfo,
+    list: &mut AssignmentList,
+    tasks: &mut Tasks,
+    miners_of_model: &MinersOfModel,
+    seed: &[u8; 32],
+) -> Result<bool> {
+    if inference.round >= acc.escalation_rounds {
+        return Ok(false);
+    }
+    let candidates: Vec<usize> = (0..miners_of_model.count())
+        .filter(|&i| miners_of_model.miner_at(i) != inference.processed_miner)
+        .collect();
+    let n = (list.ids.len() - 1 + acc.escalation_validators as usize)
+        .min(MAX_ASSIGNMENTS - 1)
+        .min(candidates.len());
+    if n == 0 {
+        return Ok(false);
+    }
+    let weights: Vec<u64> = candidates.iter().map(|&i| miners_of_model.stake_at(i)).collect();
+    let chosen = select_candidates(seed, &weights, n, acc.selection_mode)?;
 
-    for digest in list_digests.iter() {
-        let count = *(counts.get(digest).unwrap_or(&0));
-        counts.insert(*digest, count + 1);
-        if count > max_count {
-            max_count = count;
-            most_voted_digest = *digest;
-        }
+    let silent: Vec<u64> = (0..list.ids.len())
+        .filter(|&i| list.ids[i] != inference.mining_assignment && list.digests[i] == [0; 32])
+        .map(|i| list.ids[i])
+        .collect();
+    let unpaid_validators = (list.ids.len() - 1 - silent.len()) as u8;
+
+    // the new assignments are queued first so they can be created at once
+    let miner_digest = list.digest_of(inference.mining_assignment);
+    *list = AssignmentList::default();
+    list.push(inference.mining_assignment, miner_digest);
+    for ind in chosen {
+        acc.assignment_number += 1;
+        let assignment_id = acc.assignment_number;
+        tasks.push_task(Task::CreateAssignment {
+            assignment_id,
+            inference_id: inference.id,
+            worker: miners_of_model.miner_at(candidates[ind]),
+            role: 1,
+        });
+        list.push(assignment_id, [0; 32]);
     }
+    for assignment_id in silent {
+        tasks.push_task(Task::SlashMiner {
+            target: AssignmentOrPubkey::Assignment(assignment_id),
+            is_fined: false,
+            check_empty_commit: false,
+            vote: 0,
+            reason: SlashReason::RevealTimeout,
+        });
+    }
+    inference.assignment_count = list.ids.len() as u16;
+
+    // the new round gets the commit and reveal durations of the first one
+    let commit_duration = inference.commit_timeout - inference.submit_timeout;
+    let reveal_duration = inference.reveal_timeout - inference.commit_timeout;
+    inference.commit_timeout = Clock::get()?.slot + commit_duration;
+    inference.reveal_timeout = inference.commit_timeout + reveal_duration;
+    inference.status = 2;
+    inference.round += 1;
+    voting_info.total_commit = 0;
+    voting_info.total_reveal = 0;
+    voting_info.keep_only(miner_digest);
+
+    emit!(InferenceEscalated {
+        inference_id: inference.id,
+        round: inference.round,
+        validators: n as u8,
+        seed: *seed,
+        unpaid_validators,
+    });
 
-    Ok((most_voted_digest, max_count))
+    Ok(true)
 }
 
 pub fn get_threshold_value(x: u64) -> u64 {
@@ -344,27 +1056,43 @@ pub fn get_threshold_value(x: u64) -> u64 {
 //     map
 // }
 
-pub fn random_number(clk: &Clock, nonce: u64, range: u64) -> u64 {
-    if range == 0 {
-        return 0;
-    }
-    // tbd: get recent blockhash
-    // let data = recent_slothashes.data.borrow();
-    // let most_recent = array_ref![data, 12, 8];
-    let mut cloned_data: Vec<u8> = vec![];
-    let nonce_bytes = nonce.to_le_bytes();
-    cloned_data.extend_from_slice(&nonce_bytes);
-    let time_bytes = (clk.unix_timestamp as u64) as u64;
-    cloned_data.extend_from_slice(&time_bytes.to_le_bytes());
-    let res = hash(&cloned_data);
-    let rightmost: &[u8] = &res.to_bytes()[24..];
-
-    let seed = u64::try_from_slice(rightmost).unwrap();
-
-    seed % range
-
-    // let leader_schedule_epoch = clk.leader_schedule_epoch;
-    // let most_recent = array_ref![recent_blockhash_data, 0, 16];
-    // u128::from_le_bytes(*most_recent)
-    // leader_schedule_epoch % range
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joiners_share_epoch_rewards_from_the_next_epoch() {
+        let mut es = SolLearnInfo::deserialize(&mut &[0u8; SolLearnInfo::LEN][..]).unwrap();
+        es.reward_per_epoch = 1_000;
+        let mut old = MinerInfo::deserialize(&mut &[0u8; MinerInfo::LEN][..]).unwrap();
+        let mut new = old.clone();
+        let mut gone = old.clone();
+        activate_miner(&mut es, &mut old);
+        close_epoch(&mut es, 10_000);
+
+        activate_miner(&mut es, &mut new);
+        activate_miner(&mut es, &mut gone);
+        deactivate_miner(&mut es, &mut gone);
+        let epoch_1 = close_epoch(&mut es, 10_000);
+        assert_eq!((epoch_1.total_miner, epoch_1.epoch_reward), (1, 1_000));
+        assert_eq!(epoch_reward_of(&mut old, &epoch_1), 1_000);
+        assert_eq!(epoch_reward_of(&mut new, &epoch_1), 0);
+        assert_eq!(epoch_reward_of(&mut gone, &epoch_1), 0);
+
+        let epoch_2 = close_epoch(&mut es, 10_000);
+        assert_eq!(epoch_2.total_miner, 2);
+        assert_eq!(epoch_reward_of(&mut new, &epoch_2), 500);
+        assert_eq!(es.unclaimed_rewards, 2_000);
+    }
+
+    #[test]
+    fn liabilities_of_another_layout_are_refused() {
+        // SolLearnInfo::LEN before the liabilities
+        assert!(SolLearnInfo::deserialize(&mut &[0u8; 317][..]).is_err());
+
+        let mut es = SolLearnInfo::deserialize(&mut &[0u8; SolLearnInfo::LEN][..]).unwrap();
+        assert!(only_current_version(&es).is_err());
+        es.version = SOL_LEARN_INFO_VERSION;
+        assert!(only_current_version(&es).is_ok());
+    }
 }
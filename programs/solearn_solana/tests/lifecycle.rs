@@ -0,0 +1,2790 @@
+// Drives the whole inference lifecycle through the program: miners register
+// and join, a user pays for an inference, workers seize / submit / commit /
+// reveal and the queued tasks are cranked with pay_miner and slash_miner.
+// Every timeout branch of resolve_inference is covered by warping slots.
+//
+// The program built by `anchor build` (target/deploy/solearn_solana.so) is
+// loaded when present; without the SBF toolchain the suite falls back to the
+// natively compiled entry point.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::keccak::{hash, hashv};
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::{bpf_loader, system_instruction, sysvar};
+use anchor_lang::{system_program, Discriminator, InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use anchor_spl::token::spl_token;
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+
+use solearn_solana::comparator::COMPARATOR_LABEL;
+use solearn_solana::state::{
+    Delegation, MinerInfo, MinerStats, MinersOfModel, ModelInfo, ModelParams, SolLearnInfo,
+    FINE_PROPORTIONAL, MINERS_OF_MODEL_VERSION,
+};
+use solearn_solana::state_inf::{
+    Assignment, AssignmentList, AssignmentOrPubkey, AssignmentPage, ChunkedData,
+    DAOTokenReceiverInfos, DAOTokenReceiverRole, DataRef, DataScheme, Inference, MinerEpochState,
+    Referrer,
+    SlashReason, SlashRecord, Task, Tasks, VotingInfo,
+    ASSIGNMENTS_PER_PAGE, MAX_ASSIGNMENT_PAGES, SLASH_EXECUTED, SLASH_OVERTURNED, SLASH_PENDING,
+};
+
+const MIN_STAKE: u64 = 1_000_000;
+const MINTED: u64 = 10_000_000;
+const MINIMUM_FEE: u64 = 1_000;
+const INFER_VALUE: u64 = 101_000;
+// INFER_VALUE - MINIMUM_FEE, split 10% l2 owner / 10% treasury / 80% workers
+const REFUND: u64 = 100_000;
+const FEE_L2: u64 = 10_000;
+const FEE_TREASURY: u64 = 10_000;
+const WORKERS_FEE: u64 = 80_000;
+// fine_percentage 10% of the minimum stake
+const FINE: u64 = 100_000;
+const DURATION: u64 = 100;
+const MODEL_PARAMS: ModelParams = ModelParams {
+    minimum_fee: MINIMUM_FEE,
+    tier: 0,
+    miner_requirement: 3,
+    submit_duration: DURATION,
+    commit_duration: DURATION,
+    reveal_duration: DURATION,
+};
+
+// Anchor's entry wants the account slice and the infos to share a lifetime,
+// the test processor hands them out separately.
+fn process_instruction<'a, 'info>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'info>],
+    data: &[u8],
+) -> ProgramResult {
+    // SAFETY: only the slice's lifetime is widened; it outlives the call,
+    // which is all the entry point borrows it for
+    let accounts: &'info [AccountInfo<'info>] = unsafe { std::mem::transmute(accounts) };
+    solearn_solana::entry(program_id, accounts, data)
+}
+
+fn program_so() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../target/deploy/solearn_solana.so")
+}
+
+fn program_test() -> ProgramTest {
+    let mut program_test = ProgramTest::default();
+    match std::fs::read(program_so()) {
+        Ok(data) => program_test.add_account(
+            solearn_solana::ID,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: bpf_loader::id(),
+                executable: true,
+                rent_epoch: 0,
+            },
+        ),
+        Err(_) => program_test.add_program(
+            "solearn_solana",
+            solearn_solana::ID,
+            processor!(process_instruction),
+        ),
+    }
+    program_test
+}
+
+fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &solearn_solana::ID).0
+}
+
+struct Env {
+    ctx: ProgramTestContext,
+    sol_learn: Keypair,
+    mint: Keypair,
+    model: Pubkey,
+    user: Keypair,
+    treasury: Keypair,
+    miners: Vec<Keypair>,
+    vault_wallet: Pubkey,
+    user_wallet: Pubkey,
+    l2_wallet: Pubkey,
+    treasury_wallet: Pubkey,
+    miner_wallets: Vec<Pubkey>,
+    // DAO token wallets by owner, once the DAO token is set
+    dao_token: Option<Pubkey>,
+    dao_wallets: Vec<(Pubkey, Pubkey)>,
+    // referrer of the user, passed to infer once set
+    referrer: Option<Pubkey>,
+}
+
+// assignments of one inference once the mining role is seized
+struct Workers {
+    inference_id: u64,
+    miner: usize,
+    miner_assignment: u64,
+    validators: Vec<(usize, u64)>,
+}
+
+impl Env {
+    async fn new() -> Self {
+        Self::with_miners(3).await
+    }
+
+    async fn with_miners(count: usize) -> Self {
+        let mut ctx = program_test().start_with_context().await;
+        // give the SlotHashes sysvar some entries to seed the selection from
+        ctx.warp_to_slot(8).unwrap();
+
+        let mut env = Env {
+            ctx,
+            sol_learn: Keypair::new(),
+            mint: Keypair::new(),
+            model: Pubkey::new_unique(),
+            user: Keypair::new(),
+            treasury: Keypair::new(),
+            miners: (0..count).map(|_| Keypair::new()).collect(),
+            vault_wallet: Pubkey::default(),
+            user_wallet: Pubkey::default(),
+            l2_wallet: Pubkey::default(),
+            treasury_wallet: Pubkey::default(),
+            miner_wallets: vec![],
+            dao_token: None,
+            dao_wallets: vec![],
+            referrer: None,
+        };
+
+        let payer = env.ctx.payer.pubkey();
+        let user = env.user.pubkey();
+        let treasury = env.treasury.pubkey();
+        let miners: Vec<Pubkey> = env.miners.iter().map(|m| m.pubkey()).collect();
+
+        for account in [user].iter().chain(miners.iter()) {
+            env.send(
+                &[system_instruction::transfer(&payer, account, 1_000_000_000)],
+                &[],
+            )
+            .await
+            .unwrap();
+        }
+
+        let mint = env.mint.insecure_clone();
+        env.create_mint(&mint, &payer).await;
+        env.vault_wallet = env.create_wallet(&env.vault()).await;
+        env.user_wallet = env.create_wallet(&user).await;
+        env.l2_wallet = env.create_wallet(&payer).await;
+        env.treasury_wallet = env.create_wallet(&treasury).await;
+        for miner in miners.iter() {
+            let wallet = env.create_wallet(miner).await;
+            env.miner_wallets.push(wallet);
+        }
+        for wallet in [env.user_wallet]
+            .into_iter()
+            .chain(env.miner_wallets.clone())
+        {
+            env.send(
+                &[spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    &env.mint.pubkey(),
+                    &wallet,
+                    &payer,
+                    &[],
+                    MINTED,
+                )
+                .unwrap()],
+                &[],
+            )
+            .await
+            .unwrap();
+        }
+
+        env.initialize().await;
+        for i in 0..env.miners.len() {
+            env.register_and_join(i).await;
+        }
+
+        env
+    }
+
+    fn vault(&self) -> Pubkey {
+        pda(&[b"vault", self.sol_learn.pubkey().as_ref()])
+    }
+
+    fn models(&self) -> Pubkey {
+        pda(&[b"models", self.sol_learn.pubkey().as_ref()])
+    }
+
+    fn miners_of_model(&self) -> Pubkey {
+        pda(&[b"models", self.sol_learn.pubkey().as_ref(), self.model.as_ref()])
+    }
+
+    fn model_info(&self) -> Pubkey {
+        pda(&[b"model_info", self.sol_learn.pubkey().as_ref(), self.model.as_ref()])
+    }
+
+    fn miner_account(&self, miner: &Pubkey) -> Pubkey {
+        pda(&[b"miner", miner.as_ref(), self.sol_learn.pubkey().as_ref()])
+    }
+
+    fn miner_stats(&self, miner: &Pubkey) -> Pubkey {
+        pda(&[b"miner_stats", miner.as_ref(), self.sol_learn.pubkey().as_ref()])
+    }
+
+    fn inference(&self, inference_id: u64) -> Pubkey {
+        pda(&[b"inference", inference_id.to_le_bytes().as_ref()])
+    }
+
+    fn voting_info(&self, inference_id: u64) -> Pubkey {
+        pda(&[b"voting_info", inference_id.to_le_bytes().as_ref()])
+    }
+
+    fn tasks(&self, inference_id: u64) -> Pubkey {
+        pda(&[
+            b"tasks",
+            self.sol_learn.pubkey().as_ref(),
+            inference_id.to_le_bytes().as_ref(),
+        ])
+    }
+
+    fn slash_record(&self, miner: &Pubkey, index: u64) -> Pubkey {
+        let miner_account = self.miner_account(miner);
+        pda(&[b"slash_record", miner_account.as_ref(), index.to_le_bytes().as_ref()])
+    }
+
+    fn assignment(&self, assignment_id: u64) -> Pubkey {
+        pda(&[b"assignment", assignment_id.to_le_bytes().as_ref()])
+    }
+
+    fn assignment_page(&self, inference_id: u64, page: usize) -> Pubkey {
+        pda(&[b"assignment_page", inference_id.to_le_bytes().as_ref(), &[page as u8]])
+    }
+
+    fn dao_receiver_infos(&self, inference_id: u64) -> Pubkey {
+        pda(&[
+            b"dao_receiver_infos",
+            self.sol_learn.pubkey().as_ref(),
+            inference_id.to_le_bytes().as_ref(),
+        ])
+    }
+
+    fn referrer_state(&self, user: &Pubkey) -> Pubkey {
+        pda(&[b"referrer", user.as_ref()])
+    }
+
+    fn referral_code(&self, code: &str) -> Pubkey {
+        pda(&[b"referral_code", code.as_bytes()])
+    }
+
+    fn data(&self, owner: &Pubkey, data_id: u64) -> Pubkey {
+        pda(&[b"data", owner.as_ref(), data_id.to_le_bytes().as_ref()])
+    }
+
+    // writes `content` to a data account of `owner` in chunks and finalizes
+    // it, returns the hash infer / submit / reveal take
+    async fn upload(&mut self, owner: &Keypair, data_id: u64, content: &[u8]) -> [u8; 32] {
+        let data = self.data(&owner.pubkey(), data_id);
+        self.call(
+            solearn_solana::accounts::CreateDataVld {
+                data,
+                signer: owner.pubkey(),
+                system_program: system_program::ID,
+            },
+            solearn_solana::instruction::CreateData {
+                data_id,
+                len: content.len() as u32,
+            },
+            &[owner],
+        )
+        .await
+        .unwrap();
+        for (i, chunk) in content.chunks(800).enumerate() {
+            self.call(
+                solearn_solana::accounts::WriteDataVld {
+                    data,
+                    signer: owner.pubkey(),
+                },
+                solearn_solana::instruction::WriteData {
+                    _data_id: data_id,
+                    offset: (i * 800) as u32,
+                    chunk: chunk.to_vec(),
+                },
+                &[owner],
+            )
+            .await
+            .unwrap();
+        }
+        let content_hash = hash(content).to_bytes();
+        self.call(
+            solearn_solana::accounts::WriteDataVld {
+                data,
+                signer: owner.pubkey(),
+            },
+            solearn_solana::instruction::FinalizeData {
+                data_id,
+                hash: content_hash,
+            },
+            &[owner],
+        )
+        .await
+        .unwrap();
+        content_hash
+    }
+
+    // assignments of the current round, read from the pages of the inference
+    async fn assignments(&mut self, inference_id: u64) -> AssignmentList {
+        let inference: Inference = self.fetch(self.inference(inference_id)).await;
+        let mut list = AssignmentList::default();
+        for page in 0..(inference.assignment_count as usize).div_ceil(ASSIGNMENTS_PER_PAGE) {
+            let stored: AssignmentPage = self.fetch(self.assignment_page(inference_id, page)).await;
+            list.ids.extend(stored.assignments);
+            list.digests.extend(stored.digests);
+        }
+        list
+    }
+
+    async fn send(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let mut all_signers = vec![&self.ctx.payer];
+        all_signers.extend_from_slice(signers);
+        let tx = Transaction::new_signed_with_payer(
+            ixs,
+            Some(&self.ctx.payer.pubkey()),
+            &all_signers[..],
+            self.ctx.last_blockhash,
+        );
+        self.ctx.banks_client.process_transaction(tx).await
+    }
+
+    async fn call<A: ToAccountMetas, D: InstructionData>(
+        &mut self,
+        accounts: A,
+        data: D,
+        signers: &[&Keypair],
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let ix = Instruction {
+            program_id: solearn_solana::ID,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        };
+        self.send(&[ix], signers).await
+    }
+
+    // like call, with every assignment page of the inference appended
+    async fn call_paged<A: ToAccountMetas, D: InstructionData>(
+        &mut self,
+        accounts: A,
+        data: D,
+        inference_id: u64,
+        signers: &[&Keypair],
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let mut metas = accounts.to_account_metas(None);
+        metas.extend(
+            (0..MAX_ASSIGNMENT_PAGES)
+                .map(|page| AccountMeta::new(self.assignment_page(inference_id, page), false)),
+        );
+        let ix = Instruction {
+            program_id: solearn_solana::ID,
+            accounts: metas,
+            data: data.data(),
+        };
+        self.send(&[ix], signers).await
+    }
+
+    async fn fetch<T: AccountDeserialize>(&mut self, address: Pubkey) -> T {
+        let account = self
+            .ctx
+            .banks_client
+            .get_account(address)
+            .await
+            .unwrap()
+            .unwrap();
+        T::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    async fn balance(&mut self, wallet: Pubkey) -> u64 {
+        let account = self
+            .ctx
+            .banks_client
+            .get_account(wallet)
+            .await
+            .unwrap()
+            .unwrap();
+        spl_token::state::Account::unpack(&account.data).unwrap().amount
+    }
+
+    async fn warp_past(&mut self, slot: u64) {
+        self.ctx.warp_to_slot(slot + 1).unwrap();
+        self.ctx.last_blockhash = self.ctx.get_new_latest_blockhash().await.unwrap();
+    }
+
+    async fn create_mint(&mut self, mint: &Keypair, authority: &Pubkey) {
+        let payer = self.ctx.payer.pubkey();
+        let rent = self.ctx.banks_client.get_rent().await.unwrap();
+        self.send(
+            &[
+                system_instruction::create_account(
+                    &payer,
+                    &mint.pubkey(),
+                    rent.minimum_balance(spl_token::state::Mint::LEN),
+                    spl_token::state::Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint2(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    authority,
+                    None,
+                    6,
+                )
+                .unwrap(),
+            ],
+            &[mint],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn create_wallet(&mut self, owner: &Pubkey) -> Pubkey {
+        let mint = self.mint.pubkey();
+        self.create_token_wallet(&mint, owner).await
+    }
+
+    async fn create_token_wallet(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        let payer = self.ctx.payer.pubkey();
+        let rent = self.ctx.banks_client.get_rent().await.unwrap();
+        let wallet = Keypair::new();
+        self.send(
+            &[
+                system_instruction::create_account(
+                    &payer,
+                    &wallet.pubkey(),
+                    rent.minimum_balance(spl_token::state::Account::LEN),
+                    spl_token::state::Account::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_account3(
+                    &spl_token::id(),
+                    &wallet.pubkey(),
+                    mint,
+                    owner,
+                )
+                .unwrap(),
+            ],
+            &[&wallet],
+        )
+        .await
+        .unwrap();
+        wallet.pubkey()
+    }
+
+    async fn initialize(&mut self) {
+        let admin = self.ctx.payer.pubkey();
+        let sol_learn = self.sol_learn.insecure_clone();
+        self.call(
+            solearn_solana::accounts::Initialize {
+                admin,
+                staking_token: self.mint.pubkey(),
+                vault_wallet_owner_pda: self.vault(),
+                models: self.models(),
+                sol_learn_account: sol_learn.pubkey(),
+                system_program: system_program::ID,
+                sysvar_clock: sysvar::clock::ID,
+            },
+            solearn_solana::instruction::Initialize {
+                reward_per_epoch: 0,
+                epoch_duration: 1_000_000_000,
+                min_stake: MIN_STAKE,
+                treasury_address: self.treasury.pubkey(),
+                fee_l2_percentage: 10_00,
+                fee_treasury_percentage: 10_00,
+                fee_ratio_miner_validator: 50_00,
+                submit_duration: DURATION,
+                commit_duration: DURATION,
+                reveal_duration: DURATION,
+                penalty_duration: 0,
+                miner_requirement: 3,
+                fine_percentage: 10_00,
+                dao_token_reward: 0,
+                miner_percentage: 50_00,
+                user_percentage: 30_00,
+                referrer_percentage: 10_00,
+                referee_percentage: 5_00,
+                l2_owner_percentage: 20_00,
+                unstake_delay_time: 0,
+            },
+            &[&sol_learn],
+        )
+        .await
+        .unwrap();
+
+        self.call(
+            solearn_solana::accounts::CreateModel {
+                admin,
+                sol_learn_account: self.sol_learn.pubkey(),
+                models: self.models(),
+                miners_of_model: self.miners_of_model(),
+                model_info: self.model_info(),
+                system_program: system_program::ID,
+            },
+            solearn_solana::instruction::CreateModel {
+                model: self.model,
+                params: MODEL_PARAMS,
+            },
+            &[],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn register_and_join(&mut self, index: usize) {
+        let miner = self.miners[index].insecure_clone();
+        self.call(
+            solearn_solana::accounts::MinerRegister {
+                miner: miner.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                models: self.models(),
+                miner_account: self.miner_account(&miner.pubkey()),
+                miner_stats: self.miner_stats(&miner.pubkey()),
+                miner_staking_wallet: self.miner_wallets[index],
+                vault_wallet_owner_pda: self.vault(),
+                vault_staking_wallet: self.vault_wallet,
+                staking_token: self.mint.pubkey(),
+                recent_slothashes: sysvar::slot_hashes::ID,
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+                sysvar_clock: sysvar::clock::ID,
+            },
+            solearn_solana::instruction::MinerRegister {
+                stake_amount: MIN_STAKE,
+            },
+            &[&miner],
+        )
+        .await
+        .unwrap();
+
+        self.join(index).await.unwrap();
+    }
+
+    async fn join(
+        &mut self,
+        index: usize,
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let miner = self.miners[index].insecure_clone();
+        self.call(
+            solearn_solana::accounts::JoinForMinting {
+                miner: miner.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&miner.pubkey()),
+                miners_of_model: self.miners_of_model(),
+                models: self.models(),
+                system_program: system_program::ID,
+                sysvar_clock: sysvar::clock::ID,
+            },
+            solearn_solana::instruction::JoinForMinting {},
+            &[&miner],
+        )
+        .await
+    }
+
+    async fn migrate_miners_of_model(
+        &mut self,
+        miners: &[Pubkey],
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let mut metas = solearn_solana::accounts::MigrateMinersOfModelVld {
+            signer: self.ctx.payer.pubkey(),
+            sol_learn_account: self.sol_learn.pubkey(),
+            miners_of_model: self.miners_of_model(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None);
+        metas.extend(
+            miners
+                .iter()
+                .map(|miner| AccountMeta::new_readonly(self.miner_account(miner), false)),
+        );
+        let ix = Instruction {
+            program_id: solearn_solana::ID,
+            accounts: metas,
+            data: solearn_solana::instruction::MigrateMinersOfModel { model: self.model }.data(),
+        };
+        self.send(&[ix], &[]).await
+    }
+
+    fn miner_index(&self, worker: &Pubkey) -> usize {
+        self.miners
+            .iter()
+            .position(|m| m.pubkey() == *worker)
+            .unwrap()
+    }
+
+    async fn start_inference(&mut self, inference_id: u64) -> Workers {
+        self.start_inference_with(inference_id, b"prompt").await
+    }
+
+    // the user's infer, an Inline input is read from its data account
+    async fn infer(
+        &mut self,
+        inference_id: u64,
+        input: DataRef,
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let user = self.user.insecure_clone();
+        let input_data = (input.scheme == DataScheme::Inline)
+            .then(|| self.data(&user.pubkey(), inference_id));
+        self.call_paged(
+            solearn_solana::accounts::InferVld {
+                infs: self.inference(inference_id),
+                sol_learn_account: self.sol_learn.pubkey(),
+                voting_info: self.voting_info(inference_id),
+                tasks: self.tasks(inference_id),
+                dao_receiver_infos: self.dao_receiver_infos(inference_id),
+                model_info: self.model_info(),
+                referee: self.referrer_state(&user.pubkey()),
+                referrer: self.referrer.map(|referrer| self.referrer_state(&referrer)),
+                miners_of_model: self.miners_of_model(),
+                signer: user.pubkey(),
+                vault_wallet_owner_pda: self.vault(),
+                vault_staking_wallet: self.vault_wallet,
+                miner_staking_wallet: self.user_wallet,
+                recent_slothashes: sysvar::slot_hashes::ID,
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+                input_data,
+            },
+            solearn_solana::instruction::Infer {
+                inference_id,
+                creator: self.user_wallet,
+                input,
+                _value: INFER_VALUE,
+                model: self.model,
+            },
+            inference_id,
+            &[&user],
+        )
+        .await
+    }
+
+    // infer, create every queued assignment and let the first chosen worker
+    // seize the mining role
+    async fn start_inference_with(&mut self, inference_id: u64, input: &[u8]) -> Workers {
+        let user = self.user.insecure_clone();
+        let input_hash = self.upload(&user, inference_id, input).await;
+        self.infer(inference_id, DataRef::inline(input_hash)).await.unwrap();
+
+        let assignments = self.assignments(inference_id).await;
+        for &assignment_id in assignments.ids.iter() {
+            self.call(
+                solearn_solana::accounts::CreateAssignmentVld {
+                    assignment: self.assignment(assignment_id),
+                    tasks: self.tasks(inference_id),
+                    signer: self.ctx.payer.pubkey(),
+                    system_program: system_program::ID,
+                },
+                solearn_solana::instruction::CreateAssignment { assignment_id },
+                &[],
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut workers = vec![];
+        for &assignment_id in assignments.ids.iter() {
+            let assignment: Assignment = self.fetch(self.assignment(assignment_id)).await;
+            workers.push((self.miner_index(&assignment.worker), assignment_id));
+        }
+        let (miner, miner_assignment) = workers.remove(0);
+
+        let signer = self.miners[miner].insecure_clone();
+        self.call(
+            solearn_solana::accounts::SeizeMinerRoleVld {
+                sol_learn_account: self.sol_learn.pubkey(),
+                infs: self.inference(inference_id),
+                assignment: self.assignment(miner_assignment),
+                voting_info: self.voting_info(inference_id),
+                miner_account: self.miner_account(&signer.pubkey()),
+                miner_stats: self.miner_stats(&signer.pubkey()),
+                signer: signer.pubkey(),
+                system_program: system_program::ID,
+                output_data: None,
+            },
+            solearn_solana::instruction::SeizeMinerRole {
+                assignment_id: miner_assignment,
+                inference_id,
+            },
+            &[&signer],
+        )
+        .await
+        .unwrap();
+
+        Workers {
+            inference_id,
+            miner,
+            miner_assignment,
+            validators: workers,
+        }
+    }
+
+    async fn submit(&mut self, workers: &Workers, output: &[u8]) {
+        let signer = self.miners[workers.miner].insecure_clone();
+        let output_hash = self.upload(&signer, workers.miner_assignment, output).await;
+        self.submit_ref(workers, DataRef::inline(output_hash)).await;
+    }
+
+    async fn submit_ref(&mut self, workers: &Workers, output: DataRef) {
+        self.try_submit_ref(workers, output).await.unwrap();
+    }
+
+    async fn try_submit_ref(&mut self, workers: &Workers, output: DataRef) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let signer = self.miners[workers.miner].insecure_clone();
+        let output_data = (output.scheme == DataScheme::Inline)
+            .then(|| self.data(&signer.pubkey(), workers.miner_assignment));
+        self.call_paged(
+            solearn_solana::accounts::SeizeMinerRoleVld {
+                sol_learn_account: self.sol_learn.pubkey(),
+                infs: self.inference(workers.inference_id),
+                assignment: self.assignment(workers.miner_assignment),
+                voting_info: self.voting_info(workers.inference_id),
+                miner_account: self.miner_account(&signer.pubkey()),
+                miner_stats: self.miner_stats(&signer.pubkey()),
+                signer: signer.pubkey(),
+                system_program: system_program::ID,
+                output_data,
+            },
+            solearn_solana::instruction::SubmitSolution {
+                assignment_id: workers.miner_assignment,
+                inference_id: workers.inference_id,
+                output,
+            },
+            workers.inference_id,
+            &[&signer],
+        )
+        .await
+    }
+
+    async fn commit(&mut self, workers: &Workers, validator: usize, output: &[u8]) {
+        let (index, assignment_id) = workers.validators[validator];
+        let signer = self.miners[index].insecure_clone();
+        let nonce = assignment_id;
+        let output_hash = hash(output).to_bytes();
+        let commitment = hashv(&[&nonce.to_le_bytes(), signer.pubkey().as_ref(), &output_hash]);
+        self.call(
+            solearn_solana::accounts::CommitVld {
+                sol_learn_account: self.sol_learn.pubkey(),
+                infs: self.inference(workers.inference_id),
+                assignment: self.assignment(assignment_id),
+                miner_account: self.miner_account(&signer.pubkey()),
+                voting_info: self.voting_info(workers.inference_id),
+                tasks: self.tasks(workers.inference_id),
+                signer: signer.pubkey(),
+                system_program: system_program::ID,
+            },
+            solearn_solana::instruction::Commit {
+                assignment_id,
+                inference_id: workers.inference_id,
+                commitment: commitment.to_bytes(),
+            },
+            &[&signer],
+        )
+        .await
+        .unwrap();
+    }
+
+    fn update_assignment_accounts(
+        &self,
+        workers: &Workers,
+        assignment_id: u64,
+        signer: &Pubkey,
+        worker: &Pubkey,
+    ) -> solearn_solana::accounts::UpdateAssignmentVld {
+        solearn_solana::accounts::UpdateAssignmentVld {
+            sol_learn_account: self.sol_learn.pubkey(),
+            infs: self.inference(workers.inference_id),
+            assignment: self.assignment(assignment_id),
+            miner_account: self.miner_account(worker),
+            miner_stats: self.miner_stats(worker),
+            voting_info: self.voting_info(workers.inference_id),
+            tasks: self.tasks(workers.inference_id),
+            dao_receiver_infos: self.dao_receiver_infos(workers.inference_id),
+            signer: *signer,
+            vault_wallet_owner_pda: self.vault(),
+            vault_staking_wallet: self.vault_wallet,
+            token_recipient: self.user_wallet,
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+            miners_of_model: self.miners_of_model(),
+            recent_slothashes: sysvar::slot_hashes::ID,
+            output_data: None,
+        }
+    }
+
+    // creates the assignments of an escalation round, the miner keeps its own
+    async fn next_round(&mut self, workers: &Workers) -> Workers {
+        let inference_id = workers.inference_id;
+        let assignments = self.assignments(inference_id).await;
+        let mut validators = vec![];
+        for &assignment_id in assignments.ids.iter() {
+            if assignment_id == workers.miner_assignment {
+                continue;
+            }
+            self.call(
+                solearn_solana::accounts::CreateAssignmentVld {
+                    assignment: self.assignment(assignment_id),
+                    tasks: self.tasks(inference_id),
+                    signer: self.ctx.payer.pubkey(),
+                    system_program: system_program::ID,
+                },
+                solearn_solana::instruction::CreateAssignment { assignment_id },
+                &[],
+            )
+            .await
+            .unwrap();
+            let assignment: Assignment = self.fetch(self.assignment(assignment_id)).await;
+            validators.push((self.miner_index(&assignment.worker), assignment_id));
+        }
+
+        Workers {
+            inference_id,
+            miner: workers.miner,
+            miner_assignment: workers.miner_assignment,
+            validators,
+        }
+    }
+
+    async fn reveal(&mut self, workers: &Workers, validator: usize, output: &[u8]) {
+        let (index, assignment_id) = workers.validators[validator];
+        let signer = self.miners[index].insecure_clone();
+        let output_hash = self.upload(&signer, assignment_id, output).await;
+        self.reveal_ref(workers, validator, DataRef::inline(output_hash)).await;
+    }
+
+    async fn reveal_ref(&mut self, workers: &Workers, validator: usize, output: DataRef) {
+        self.try_reveal_ref(workers, validator, output).await.unwrap();
+    }
+
+    async fn try_reveal_ref(
+        &mut self,
+        workers: &Workers,
+        validator: usize,
+        output: DataRef,
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let (index, assignment_id) = workers.validators[validator];
+        let signer = self.miners[index].insecure_clone();
+        let accounts = solearn_solana::accounts::UpdateAssignmentVld {
+            output_data: (output.scheme == DataScheme::Inline)
+                .then(|| self.data(&signer.pubkey(), assignment_id)),
+            ..self.update_assignment_accounts(workers, assignment_id, &signer.pubkey(), &signer.pubkey())
+        };
+        self.call_paged(
+            accounts,
+            solearn_solana::instruction::Reveal {
+                assignment_id,
+                inference_id: workers.inference_id,
+                nonce: assignment_id,
+                output,
+            },
+            workers.inference_id,
+            &[&signer],
+        )
+        .await
+    }
+
+    async fn resolve(&mut self, workers: &Workers) {
+        let payer = self.ctx.payer.pubkey();
+        let worker = self.miners[workers.miner].pubkey();
+        let accounts =
+            self.update_assignment_accounts(workers, workers.miner_assignment, &payer, &worker);
+        self.call_paged(
+            accounts,
+            solearn_solana::instruction::ResolveInference {
+                assignment_id: workers.miner_assignment,
+                inference_id: workers.inference_id,
+            },
+            workers.inference_id,
+            &[],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn pending_tasks(&mut self, inference_id: u64) -> Vec<Task> {
+        let tasks: Tasks = self.fetch(self.tasks(inference_id)).await;
+        tasks.pending().unwrap()
+    }
+
+    // runs every queued pay_miner / slash_miner task of the inference
+    async fn crank(&mut self, workers: &Workers) {
+        let inference_id = workers.inference_id;
+        for task in self.pending_tasks(inference_id).await {
+            match task {
+                Task::PayMiner { recipient, .. } => {
+                    let (assignment_id, recipient) = match recipient {
+                        AssignmentOrPubkey::Assignment(assignment_id) => {
+                            let assignment: Assignment =
+                                self.fetch(self.assignment(assignment_id)).await;
+                            let index = self.miner_index(&assignment.worker);
+                            (assignment_id, self.miner_wallets[index])
+                        }
+                        AssignmentOrPubkey::Pubkey(owner) => {
+                            let recipient = if owner == self.treasury.pubkey() {
+                                self.treasury_wallet
+                            } else {
+                                self.l2_wallet
+                            };
+                            (workers.miner_assignment, recipient)
+                        }
+                    };
+                    let assignment: Assignment = self.fetch(self.assignment(assignment_id)).await;
+                    self.call(
+                        solearn_solana::accounts::PayMinerVld {
+                            tasks: self.tasks(inference_id),
+                            sol_learn_account: self.sol_learn.pubkey(),
+                            assignment: self.assignment(assignment_id),
+                            miner_account: self.miner_account(&assignment.worker),
+                            miner_stats: self.miner_stats(&assignment.worker),
+                            vault_wallet_owner_pda: self.vault(),
+                            vault_staking_wallet: self.vault_wallet,
+                            token_recipient: recipient,
+                            token_program: spl_token::id(),
+                            system_program: system_program::ID,
+                        },
+                        solearn_solana::instruction::PayMiner { assignment_id },
+                        &[],
+                    )
+                    .await
+                    .unwrap();
+                }
+                Task::SlashMiner { target, .. } => {
+                    let assignment_id = match target {
+                        AssignmentOrPubkey::Assignment(assignment_id) => assignment_id,
+                        AssignmentOrPubkey::Pubkey(_) => workers.miner_assignment,
+                    };
+                    let assignment: Assignment = self.fetch(self.assignment(assignment_id)).await;
+                    let miner: MinerInfo = self.fetch(self.miner_account(&assignment.worker)).await;
+                    self.call(
+                        solearn_solana::accounts::SlashMinerVld {
+                            sol_learn_account: self.sol_learn.pubkey(),
+                            miner_account: self.miner_account(&assignment.worker),
+                            miner_stats: self.miner_stats(&assignment.worker),
+                            tasks: self.tasks(inference_id),
+                            assignment: self.assignment(assignment_id),
+                            miners_of_model: self.miners_of_model(),
+                            vault_wallet_owner_pda: self.vault(),
+                            vault_staking_wallet: self.vault_wallet,
+                            staking_token: self.mint.pubkey(),
+                            token_program: spl_token::id(),
+                            system_program: system_program::ID,
+                            token_recipient: self.treasury_wallet,
+                            infs: self.inference(inference_id),
+                            creator_wallet: self.user_wallet,
+                            signer: self.ctx.payer.pubkey(),
+                            slash_record: self.slash_record(&assignment.worker, miner.slash_count),
+                        },
+                        solearn_solana::instruction::SlashMiner { assignment_id },
+                        &[],
+                    )
+                    .await
+                    .unwrap();
+                }
+                task => panic!("unexpected task {:?}", task),
+            }
+        }
+        assert!(self.pending_tasks(inference_id).await.is_empty());
+    }
+
+    async fn set_crank_bounty(&mut self, crank_bounty: u64) {
+        let admin = self.ctx.payer.pubkey();
+        self.call(
+            solearn_solana::accounts::UpdateParamsVld {
+                admin,
+                sol_learn_account: self.sol_learn.pubkey(),
+                system_program: system_program::ID,
+            },
+            solearn_solana::instruction::SetCrankBounty { crank_bounty },
+            &[],
+        )
+        .await
+        .unwrap();
+    }
+
+    // drains the task queue of the inference with crank_tasks calls, passing
+    // the accounts of every pending task; a call stops once its compute
+    // budget runs low
+    async fn crank_tasks(&mut self, workers: &Workers, cranker_wallet: Pubkey) {
+        let inference_id = workers.inference_id;
+        let mut pending = self.pending_tasks(inference_id).await;
+        while !pending.is_empty() {
+            self.crank_tasks_once(inference_id, &pending, cranker_wallet).await;
+            let left = self.pending_tasks(inference_id).await;
+            assert!(left.len() < pending.len(), "crank_tasks made no progress");
+            pending = left;
+        }
+    }
+
+    async fn crank_tasks_once(&mut self, inference_id: u64, tasks: &[Task], cranker_wallet: Pubkey) {
+        // slashes of the same miner in one call use consecutive records
+        let mut slash_counts: HashMap<Pubkey, u64> = HashMap::new();
+        let mut remaining = vec![];
+        for task in tasks.iter().cloned() {
+            match task {
+                Task::CreateAssignment { assignment_id, .. } => {
+                    remaining.push(AccountMeta::new(self.assignment(assignment_id), false));
+                }
+                Task::PayMiner {
+                    recipient: AssignmentOrPubkey::Assignment(assignment_id),
+                    ..
+                } => {
+                    let assignment: Assignment = self.fetch(self.assignment(assignment_id)).await;
+                    let index = self.miner_index(&assignment.worker);
+                    remaining.push(AccountMeta::new(self.assignment(assignment_id), false));
+                    remaining.push(AccountMeta::new(self.miner_account(&assignment.worker), false));
+                    remaining.push(AccountMeta::new(self.miner_wallets[index], false));
+                    remaining.push(AccountMeta::new(self.miner_stats(&assignment.worker), false));
+                }
+                Task::PayMiner {
+                    recipient: AssignmentOrPubkey::Pubkey(owner),
+                    ..
+                } => {
+                    let wallet = if owner == self.treasury.pubkey() {
+                        self.treasury_wallet
+                    } else {
+                        self.l2_wallet
+                    };
+                    remaining.push(AccountMeta::new(wallet, false));
+                }
+                Task::SlashMiner {
+                    target: AssignmentOrPubkey::Assignment(assignment_id),
+                    ..
+                } => {
+                    let assignment: Assignment = self.fetch(self.assignment(assignment_id)).await;
+                    let index = self.next_slash_index(&mut slash_counts, &assignment.worker).await;
+                    remaining.push(AccountMeta::new(self.assignment(assignment_id), false));
+                    remaining.push(AccountMeta::new(self.miner_account(&assignment.worker), false));
+                    remaining.push(AccountMeta::new(self.slash_record(&assignment.worker, index), false));
+                    remaining.push(AccountMeta::new(self.miner_stats(&assignment.worker), false));
+                }
+                Task::SlashMiner {
+                    target: AssignmentOrPubkey::Pubkey(worker),
+                    ..
+                } => {
+                    let index = self.next_slash_index(&mut slash_counts, &worker).await;
+                    remaining.push(AccountMeta::new(self.miner_account(&worker), false));
+                    remaining.push(AccountMeta::new(self.slash_record(&worker, index), false));
+                    remaining.push(AccountMeta::new(self.miner_stats(&worker), false));
+                }
+                Task::MintDaoToken {
+                    recipient: AssignmentOrPubkey::Assignment(assignment_id),
+                    ..
+                } => {
+                    let assignment: Assignment = self.fetch(self.assignment(assignment_id)).await;
+                    remaining.push(AccountMeta::new(self.assignment(assignment_id), false));
+                    remaining.push(AccountMeta::new(self.dao_wallet(&assignment.worker), false));
+                    remaining.push(AccountMeta::new_readonly(assignment.worker, false));
+                }
+                Task::MintDaoToken {
+                    recipient: AssignmentOrPubkey::Pubkey(receiver),
+                    role,
+                    ..
+                } => {
+                    // the creator is recorded by its staking wallet
+                    let owner = if receiver == self.user_wallet {
+                        self.user.pubkey()
+                    } else {
+                        receiver
+                    };
+                    remaining.push(AccountMeta::new(self.dao_wallet(&owner), false));
+                    remaining.push(AccountMeta::new_readonly(owner, false));
+                    if role == DAOTokenReceiverRole::Referrer as u8 {
+                        remaining.push(AccountMeta::new(self.referrer_state(&owner), false));
+                    }
+                }
+            }
+        }
+
+        let accounts = solearn_solana::accounts::CrankTasksVld {
+            signer: self.ctx.payer.pubkey(),
+            sol_learn_account: self.sol_learn.pubkey(),
+            infs: self.inference(inference_id),
+            tasks: self.tasks(inference_id),
+            miners_of_model: self.miners_of_model(),
+            vault_wallet_owner_pda: self.vault(),
+            vault_staking_wallet: self.vault_wallet,
+            treasury_wallet: self.treasury_wallet,
+            creator_wallet: self.user_wallet,
+            cranker_wallet,
+            dao_token: self.dao_token,
+            token_program: spl_token::id(),
+            system_program: system_program::ID,
+            associated_token_program: self.dao_token.map(|_| associated_token::ID),
+        };
+        let mut metas = accounts.to_account_metas(None);
+        metas.extend(remaining);
+        let ix = Instruction {
+            program_id: solearn_solana::ID,
+            accounts: metas,
+            data: solearn_solana::instruction::CrankTasks { inference_id }.data(),
+        };
+        self.send(&[ix], &[]).await.unwrap();
+    }
+
+    async fn next_slash_index(&mut self, counts: &mut HashMap<Pubkey, u64>, worker: &Pubkey) -> u64 {
+        let index = match counts.get(worker) {
+            Some(&count) => count,
+            None => {
+                let miner: MinerInfo = self.fetch(self.miner_account(worker)).await;
+                miner.slash_count
+            }
+        };
+        counts.insert(*worker, index + 1);
+        index
+    }
+
+    // creates the DAO token with the vault as mint authority, sets it and
+    // gives every party a DAO token wallet
+    async fn set_dao_token(&mut self) {
+        let admin = self.ctx.payer.pubkey();
+        let dao_token = Keypair::new();
+        let vault = self.vault();
+        self.create_mint(&dao_token, &vault).await;
+        self.call(
+            solearn_solana::accounts::SetDaoTokenVld {
+                admin,
+                sol_learn_account: self.sol_learn.pubkey(),
+                vault_wallet_owner_pda: vault,
+                dao_token: dao_token.pubkey(),
+            },
+            solearn_solana::instruction::SetDaoToken {},
+            &[],
+        )
+        .await
+        .unwrap();
+
+        self.dao_token = Some(dao_token.pubkey());
+        let owners: Vec<Pubkey> = [admin, self.user.pubkey()]
+            .into_iter()
+            .chain(self.miners.iter().map(|m| m.pubkey()))
+            .collect();
+        for owner in owners {
+            let wallet = self.create_token_wallet(&dao_token.pubkey(), &owner).await;
+            self.dao_wallets.push((owner, wallet));
+        }
+    }
+
+    async fn register_referrer(
+        &mut self,
+        user: &Keypair,
+        code: &str,
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        self.call(
+            solearn_solana::accounts::RegisterReferrerVld {
+                signer: user.pubkey(),
+                referrer: self.referrer_state(&user.pubkey()),
+                referral_code: self.referral_code(code),
+                system_program: system_program::ID,
+            },
+            solearn_solana::instruction::RegisterReferrer {
+                code: code.to_string(),
+            },
+            &[user],
+        )
+        .await
+    }
+
+    async fn set_referrer(
+        &mut self,
+        user: &Keypair,
+        code: &str,
+        referrer: &Pubkey,
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        self.call(
+            solearn_solana::accounts::SetReferrerVld {
+                signer: user.pubkey(),
+                referee: self.referrer_state(&user.pubkey()),
+                referral_code: self.referral_code(code),
+                referrer: self.referrer_state(referrer),
+                system_program: system_program::ID,
+            },
+            solearn_solana::instruction::SetReferrer {
+                code: code.to_string(),
+            },
+            &[user],
+        )
+        .await
+    }
+
+    async fn rate(
+        &mut self,
+        workers: &Workers,
+        score: u8,
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let user = self.user.insecure_clone();
+        let miner = self.miners[workers.miner].pubkey();
+        let user_dao_wallet = self.dao_token.map(|_| self.dao_wallet(&user.pubkey()));
+        self.call(
+            solearn_solana::accounts::RateInferenceVld {
+                signer: user.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                infs: self.inference(workers.inference_id),
+                creator_wallet: self.user_wallet,
+                dao_receiver_infos: self.dao_receiver_infos(workers.inference_id),
+                model_info: self.model_info(),
+                miners_of_model: self.miners_of_model(),
+                miner_account: self.miner_account(&miner),
+                vault_wallet_owner_pda: self.vault(),
+                dao_token: self.dao_token,
+                user_dao_wallet,
+                token_program: spl_token::id(),
+            },
+            solearn_solana::instruction::RateInference {
+                inference_id: workers.inference_id,
+                score,
+            },
+            &[&user],
+        )
+        .await
+    }
+
+    // owners without a wallet of their own get the associated one from the crank
+    fn dao_wallet(&self, owner: &Pubkey) -> Pubkey {
+        match self.dao_wallets.iter().find(|(o, _)| o == owner) {
+            Some(&(_, wallet)) => wallet,
+            None => get_associated_token_address(owner, &self.dao_token.unwrap()),
+        }
+    }
+
+    // admin setters taking UpdateParamsVld
+    async fn update_params<D: InstructionData>(&mut self, data: D) {
+        let admin = self.ctx.payer.pubkey();
+        self.call(
+            solearn_solana::accounts::UpdateParamsVld {
+                admin,
+                sol_learn_account: self.sol_learn.pubkey(),
+                system_program: system_program::ID,
+            },
+            data,
+            &[],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn topup(&mut self, index: usize, amount: u64) {
+        let miner = self.miners[index].insecure_clone();
+        self.call(
+            solearn_solana::accounts::Topup {
+                miner: miner.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&miner.pubkey()),
+                miners_of_model: self.miners_of_model(),
+                miner_staking_wallet: self.miner_wallets[index],
+                vault_wallet_owner_pda: self.vault(),
+                vault_staking_wallet: self.vault_wallet,
+                staking_token: self.mint.pubkey(),
+                token_program: spl_token::id(),
+            },
+            solearn_solana::instruction::Topup {
+                topup_amount: amount,
+            },
+            &[&miner],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn unstake(
+        &mut self,
+        index: usize,
+        amount: u64,
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let miner = self.miners[index].insecure_clone();
+        self.call(
+            solearn_solana::accounts::MinerUnStaking {
+                miner: miner.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&miner.pubkey()),
+                miners_of_model: self.miners_of_model(),
+                system_program: system_program::ID,
+                sysvar_clock: sysvar::clock::ID,
+            },
+            solearn_solana::instruction::Unstake { amount },
+            &[&miner],
+        )
+        .await
+    }
+
+    async fn claim_unstaked(&mut self, index: usize) {
+        let miner = self.miners[index].insecure_clone();
+        self.call(
+            solearn_solana::accounts::MinerClaim {
+                miner: miner.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&miner.pubkey()),
+                miner_staking_wallet: self.miner_wallets[index],
+                vault_wallet_owner_pda: self.vault(),
+                vault_staking_wallet: self.vault_wallet,
+                staking_token: self.mint.pubkey(),
+                token_program: spl_token::id(),
+                sysvar_clock: sysvar::clock::ID,
+                system_program: system_program::ID,
+            },
+            solearn_solana::instruction::MinerClaimUnstaked {},
+            &[&miner],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn set_commission(&mut self, index: usize, commission: u16) {
+        let miner = self.miners[index].insecure_clone();
+        self.call(
+            solearn_solana::accounts::SetCommission {
+                miner: miner.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&miner.pubkey()),
+            },
+            solearn_solana::instruction::SetCommission { commission },
+            &[&miner],
+        )
+        .await
+        .unwrap();
+    }
+
+    // the user delegates to a miner
+    fn delegation(&self, index: usize) -> Pubkey {
+        let miner_account = self.miner_account(&self.miners[index].pubkey());
+        pda(&[b"delegation", self.user.pubkey().as_ref(), miner_account.as_ref()])
+    }
+
+    async fn delegate(&mut self, index: usize, amount: u64) {
+        let user = self.user.insecure_clone();
+        self.call(
+            solearn_solana::accounts::Delegate {
+                delegator: user.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&self.miners[index].pubkey()),
+                miners_of_model: self.miners_of_model(),
+                delegation: self.delegation(index),
+                delegator_wallet: self.user_wallet,
+                vault_wallet_owner_pda: self.vault(),
+                vault_staking_wallet: self.vault_wallet,
+                staking_token: self.mint.pubkey(),
+                token_program: spl_token::id(),
+                system_program: system_program::ID,
+            },
+            solearn_solana::instruction::Delegate { amount },
+            &[&user],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn undelegate(&mut self, index: usize, shares: u64) {
+        let user = self.user.insecure_clone();
+        self.call(
+            solearn_solana::accounts::Undelegate {
+                delegator: user.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&self.miners[index].pubkey()),
+                delegation: self.delegation(index),
+                sysvar_clock: sysvar::clock::ID,
+            },
+            solearn_solana::instruction::Undelegate { shares },
+            &[&user],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn claim_undelegated(&mut self, index: usize) {
+        let user = self.user.insecure_clone();
+        self.call(
+            solearn_solana::accounts::ClaimUndelegated {
+                delegator: user.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&self.miners[index].pubkey()),
+                miners_of_model: self.miners_of_model(),
+                delegation: self.delegation(index),
+                delegator_wallet: self.user_wallet,
+                vault_wallet_owner_pda: self.vault(),
+                vault_staking_wallet: self.vault_wallet,
+                staking_token: self.mint.pubkey(),
+                token_program: spl_token::id(),
+                sysvar_clock: sysvar::clock::ID,
+            },
+            solearn_solana::instruction::ClaimUndelegated {},
+            &[&user],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn miner_info(&mut self, index: usize) -> MinerInfo {
+        let miner = self.miners[index].pubkey();
+        self.fetch(self.miner_account(&miner)).await
+    }
+
+    async fn active_miners(&mut self) -> usize {
+        let miners_of_model: MinersOfModel = self.fetch(self.miners_of_model()).await;
+        miners_of_model.count()
+    }
+
+    async fn advance_clock(&mut self, seconds: u64) {
+        let mut clock: Clock = self.ctx.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp += seconds as i64;
+        self.ctx.set_sysvar(&clock);
+        self.ctx.last_blockhash = self.ctx.get_new_latest_blockhash().await.unwrap();
+    }
+
+    async fn open_dispute(&mut self, index: usize, record: u64) {
+        let miner = self.miners[index].insecure_clone();
+        let miner_account = self.miner_account(&miner.pubkey());
+        self.call(
+            solearn_solana::accounts::OpenDisputeVld {
+                signer: miner.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account,
+                slash_record: self.slash_record(&miner.pubkey(), record),
+                miner_wallet: self.miner_wallets[index],
+                vault_wallet_owner_pda: self.vault(),
+                vault_staking_wallet: self.vault_wallet,
+                token_program: spl_token::id(),
+            },
+            solearn_solana::instruction::OpenDispute { index: record },
+            &[&miner],
+        )
+        .await
+        .unwrap();
+    }
+
+    // resolved by the admin, who may settle disputes as well as the arbiter
+    async fn resolve_dispute(&mut self, index: usize, record: u64, overturned: bool) {
+        let miner = self.miners[index].pubkey();
+        self.call(
+            solearn_solana::accounts::ResolveDisputeVld {
+                signer: self.ctx.payer.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&miner),
+                slash_record: self.slash_record(&miner, record),
+                miner_wallet: self.miner_wallets[index],
+                treasury_wallet: self.treasury_wallet,
+                vault_wallet_owner_pda: self.vault(),
+                vault_staking_wallet: self.vault_wallet,
+                token_program: spl_token::id(),
+            },
+            solearn_solana::instruction::ResolveDispute {
+                index: record,
+                overturned,
+            },
+            &[],
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn execute_slash(
+        &mut self,
+        index: usize,
+        record: u64,
+    ) -> std::result::Result<(), solana_program_test::BanksClientError> {
+        let miner = self.miners[index].pubkey();
+        self.call(
+            solearn_solana::accounts::ExecuteSlashVld {
+                signer: self.ctx.payer.pubkey(),
+                sol_learn_account: self.sol_learn.pubkey(),
+                miner_account: self.miner_account(&miner),
+                slash_record: self.slash_record(&miner, record),
+                treasury_wallet: self.treasury_wallet,
+                creator_wallet: self.user_wallet,
+                vault_wallet_owner_pda: self.vault(),
+                vault_staking_wallet: self.vault_wallet,
+                token_program: spl_token::id(),
+            },
+            solearn_solana::instruction::ExecuteSlash { index: record },
+            &[],
+        )
+        .await
+    }
+}
+
+struct Balances {
+    vault: u64,
+    user: u64,
+    l2: u64,
+    treasury: u64,
+    miners: Vec<u64>,
+}
+
+async fn balances(env: &mut Env) -> Balances {
+    let mut miners = vec![];
+    for wallet in env.miner_wallets.clone() {
+        miners.push(env.balance(wallet).await);
+    }
+    Balances {
+        vault: env.balance(env.vault_wallet).await,
+        user: env.balance(env.user_wallet).await,
+        l2: env.balance(env.l2_wallet).await,
+        treasury: env.balance(env.treasury_wallet).await,
+        miners,
+    }
+}
+
+#[tokio::test]
+async fn all_workers_agree() {
+    let mut env = Env::new().await;
+    let before = balances(&mut env).await;
+    assert_eq!(before.vault, 3 * MIN_STAKE);
+
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    // the last reveal resolves the inference
+    env.reveal(&workers, 1, b"answer").await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    assert_eq!(inference.mining_assignment, workers.miner_assignment);
+    let tasks = env.pending_tasks(1).await;
+    assert_eq!(tasks.len(), 5);
+    assert!(tasks.iter().all(|t| matches!(t, Task::PayMiner { .. })));
+
+    env.crank(&workers).await;
+
+    let after = balances(&mut env).await;
+    let miner_fee = WORKERS_FEE / 2;
+    let validator_fee = (WORKERS_FEE - miner_fee) / 2;
+    assert_eq!(after.vault, before.vault + MINIMUM_FEE);
+    assert_eq!(after.user, before.user - INFER_VALUE);
+    assert_eq!(after.l2, before.l2 + FEE_L2);
+    assert_eq!(after.treasury, before.treasury + FEE_TREASURY);
+    assert_eq!(after.miners[workers.miner], before.miners[workers.miner] + miner_fee);
+    for &(index, _) in workers.validators.iter() {
+        assert_eq!(after.miners[index], before.miners[index] + validator_fee);
+    }
+    assert_eq!(env.active_miners().await, 3);
+}
+
+#[tokio::test]
+async fn submit_timeout_refunds_and_fines_the_miner() {
+    let mut env = Env::new().await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    env.warp_past(inference.submit_timeout).await;
+    env.resolve(&workers).await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 5);
+    let miner = env.miners[workers.miner].pubkey();
+    assert_eq!(
+        env.pending_tasks(1).await,
+        vec![Task::SlashMiner {
+            target: AssignmentOrPubkey::Pubkey(miner),
+            is_fined: true,
+            check_empty_commit: false,
+            vote: 0,
+            reason: SlashReason::SubmitTimeout,
+        }]
+    );
+
+    env.crank(&workers).await;
+
+    let after = balances(&mut env).await;
+    assert_eq!(after.vault, before.vault + MINIMUM_FEE - FINE);
+    assert_eq!(after.user, before.user - INFER_VALUE + REFUND);
+    assert_eq!(after.treasury, before.treasury + FINE);
+    assert_eq!(after.l2, before.l2);
+    assert_eq!(after.miners, before.miners);
+
+    // fined under the minimum stake, the rest of it is queued for withdrawal
+    let miner = env.miner_info(workers.miner).await;
+    assert!(!miner.is_active);
+    assert_eq!(miner.stake_amount, 0);
+    assert_eq!(miner.pending_unstake(), MIN_STAKE - FINE);
+    assert_eq!(env.active_miners().await, 2);
+}
+
+#[tokio::test]
+async fn commit_timeout_refunds_and_slashes_silent_validators() {
+    let mut env = Env::new().await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    env.warp_past(inference.commit_timeout).await;
+    env.resolve(&workers).await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    assert_eq!(env.pending_tasks(1).await.len(), 3);
+
+    env.crank(&workers).await;
+
+    let after = balances(&mut env).await;
+    assert_eq!(after.vault, before.vault + MINIMUM_FEE);
+    assert_eq!(after.user, before.user - INFER_VALUE + REFUND);
+    assert_eq!(after.treasury, before.treasury);
+    assert_eq!(after.miners, before.miners);
+
+    // only the validator that never committed is slashed, without a fine
+    assert!(env.miner_info(workers.miner).await.is_active);
+    assert!(env.miner_info(workers.validators[0].0).await.is_active);
+    let silent = env.miner_info(workers.validators[1].0).await;
+    assert!(!silent.is_active);
+    assert_eq!(silent.stake_amount, MIN_STAKE);
+    assert_eq!(env.active_miners().await, 2);
+}
+
+#[tokio::test]
+async fn reveal_timeout_without_quorum_refunds() {
+    let mut env = Env::new().await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"other").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"other").await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    env.warp_past(inference.reveal_timeout).await;
+    env.resolve(&workers).await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    assert_eq!(env.pending_tasks(1).await.len(), 1);
+
+    env.crank(&workers).await;
+
+    let after = balances(&mut env).await;
+    assert_eq!(after.vault, before.vault + MINIMUM_FEE);
+    assert_eq!(after.user, before.user - INFER_VALUE + REFUND);
+    assert_eq!(after.l2, before.l2);
+    assert_eq!(after.treasury, before.treasury);
+    assert_eq!(after.miners, before.miners);
+
+    assert!(env.miner_info(workers.miner).await.is_active);
+    assert!(env.miner_info(workers.validators[0].0).await.is_active);
+    assert!(!env.miner_info(workers.validators[1].0).await.is_active);
+}
+
+#[tokio::test]
+async fn reveal_timeout_with_quorum_pays_the_majority() {
+    let mut env = Env::new().await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    env.warp_past(inference.reveal_timeout).await;
+    env.resolve(&workers).await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    let tasks = env.pending_tasks(1).await;
+    assert_eq!(
+        tasks[2],
+        Task::SlashMiner {
+            target: AssignmentOrPubkey::Assignment(workers.validators[1].1),
+            is_fined: false,
+            check_empty_commit: false,
+            vote: 1,
+            reason: SlashReason::RevealTimeout,
+        }
+    );
+    assert_eq!(tasks.len(), 5);
+
+    env.crank(&workers).await;
+
+    let after = balances(&mut env).await;
+    let miner_fee = WORKERS_FEE / 2;
+    let validator_fee = WORKERS_FEE - miner_fee;
+    let (revealed, silent) = (workers.validators[0].0, workers.validators[1].0);
+    assert_eq!(after.vault, before.vault + MINIMUM_FEE);
+    assert_eq!(after.user, before.user - INFER_VALUE);
+    assert_eq!(after.l2, before.l2 + FEE_L2);
+    assert_eq!(after.treasury, before.treasury + FEE_TREASURY);
+    assert_eq!(after.miners[workers.miner], before.miners[workers.miner] + miner_fee);
+    assert_eq!(after.miners[revealed], before.miners[revealed] + validator_fee);
+    assert_eq!(after.miners[silent], before.miners[silent]);
+
+    assert!(!env.miner_info(silent).await.is_active);
+    assert_eq!(env.active_miners().await, 2);
+}
+
+#[tokio::test]
+async fn crank_tasks_pays_out_and_earns_the_bounty() {
+    const BOUNTY: u64 = 500;
+    let mut env = Env::new().await;
+    env.set_crank_bounty(BOUNTY).await;
+    let payer = env.ctx.payer.pubkey();
+    let cranker_wallet = env.create_wallet(&payer).await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.crank_bounty, BOUNTY);
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+
+    env.crank_tasks(&workers, cranker_wallet).await;
+
+    // the bounty is set aside before the fees are split
+    let fee_value = REFUND - BOUNTY;
+    let fee_l2 = fee_value / 10;
+    let fee_treasury = fee_value / 10;
+    let miner_fee = (fee_value - fee_l2 - fee_treasury) / 2;
+    let validator_fee = miner_fee / 2;
+    let after = balances(&mut env).await;
+    assert_eq!(env.balance(cranker_wallet).await, BOUNTY);
+    assert_eq!(after.vault, before.vault + MINIMUM_FEE);
+    assert_eq!(after.user, before.user - INFER_VALUE);
+    assert_eq!(after.l2, before.l2 + fee_l2);
+    assert_eq!(after.treasury, before.treasury + fee_treasury);
+    assert_eq!(after.miners[workers.miner], before.miners[workers.miner] + miner_fee);
+    for &(index, _) in workers.validators.iter() {
+        assert_eq!(after.miners[index], before.miners[index] + validator_fee);
+    }
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.crank_bounty, 0);
+}
+
+#[tokio::test]
+async fn partial_unstake_keeps_the_miner_active() {
+    let mut env = Env::new().await;
+    let before = balances(&mut env).await;
+
+    env.topup(0, MIN_STAKE / 2).await;
+    // the remaining stake may not drop under the minimum stake
+    assert!(env.unstake(0, MIN_STAKE).await.is_err());
+    env.unstake(0, 200_000).await.unwrap();
+    env.unstake(0, 300_000).await.unwrap();
+
+    let info = env.miner_info(0).await;
+    assert!(info.is_active);
+    assert_eq!(info.stake_amount, MIN_STAKE);
+    assert_eq!(info.pending_unstake(), 500_000);
+    let miners_of_model: MinersOfModel = env.fetch(env.miners_of_model()).await;
+    let index = miners_of_model.position(&env.miners[0].pubkey()).unwrap();
+    assert_eq!(miners_of_model.stake_at(index), MIN_STAKE);
+
+    // the unstake delay is zero, so both requests have matured
+    env.claim_unstaked(0).await;
+    let after = balances(&mut env).await;
+    assert_eq!(after.miners[0], before.miners[0]);
+    assert_eq!(after.vault, before.vault);
+    assert_eq!(env.miner_info(0).await.pending_unstake(), 0);
+    assert_eq!(env.active_miners().await, 3);
+}
+
+#[tokio::test]
+async fn delegators_share_the_miner_fee() {
+    let mut env = Env::new().await;
+    let workers = env.start_inference(1).await;
+    let m = workers.miner;
+    env.set_commission(m, 20_00).await;
+    env.delegate(m, MIN_STAKE).await;
+    let miners_of_model: MinersOfModel = env.fetch(env.miners_of_model()).await;
+    let index = miners_of_model.position(&env.miners[m].pubkey()).unwrap();
+    assert_eq!(miners_of_model.stake_at(index), 2 * MIN_STAKE);
+    let before = balances(&mut env).await;
+
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+    env.crank(&workers).await;
+
+    // half of the stake is delegated, the miner keeps 20% of that half
+    let miner_fee = WORKERS_FEE / 2;
+    let delegators_fee = miner_fee / 2 - miner_fee / 2 / 5;
+    let after = balances(&mut env).await;
+    assert_eq!(after.miners[m], before.miners[m] + miner_fee - delegators_fee);
+    assert_eq!(after.vault, before.vault - REFUND + delegators_fee);
+    let info = env.miner_info(m).await;
+    assert_eq!(info.delegated_stake, MIN_STAKE + delegators_fee);
+
+    // the unstake delay is zero, the shares are worth the fee share too
+    let delegation: Delegation = env.fetch(env.delegation(m)).await;
+    assert_eq!(delegation.shares, MIN_STAKE);
+    env.undelegate(m, MIN_STAKE).await;
+    env.claim_undelegated(m).await;
+    let claimed = balances(&mut env).await;
+    assert_eq!(claimed.user, after.user + MIN_STAKE + delegators_fee);
+    assert_eq!(claimed.vault, after.vault - MIN_STAKE - delegators_fee);
+    let miners_of_model: MinersOfModel = env.fetch(env.miners_of_model()).await;
+    assert_eq!(miners_of_model.stake_at(index), MIN_STAKE);
+}
+
+#[tokio::test]
+async fn fines_are_split_with_the_creator_and_recorded() {
+    let mut env = Env::new().await;
+    env.update_params(solearn_solana::instruction::SetFineCreatorPercentage {
+        fine_creator_percentage: 25_00,
+    })
+    .await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    env.warp_past(inference.submit_timeout).await;
+    env.resolve(&workers).await;
+    env.crank(&workers).await;
+
+    let after = balances(&mut env).await;
+    assert_eq!(after.treasury, before.treasury + FINE * 3 / 4);
+    assert_eq!(after.user, before.user - INFER_VALUE + REFUND + FINE / 4);
+    assert_eq!(after.vault, before.vault + MINIMUM_FEE - FINE);
+
+    let miner = env.miners[workers.miner].pubkey();
+    assert_eq!(env.miner_info(workers.miner).await.slash_count, 1);
+    let record: SlashRecord = env.fetch(env.slash_record(&miner, 0)).await;
+    assert_eq!(record.miner, miner);
+    assert_eq!(record.inference_id, 1);
+    assert_eq!(record.assignment_id, workers.miner_assignment);
+    assert_eq!(record.reason, SlashReason::SubmitTimeout);
+    assert_eq!(record.amount, FINE);
+}
+
+#[tokio::test]
+async fn proportional_fine_exhausts_the_stake() {
+    let mut env = Env::new().await;
+    env.update_params(solearn_solana::instruction::SetFineModel {
+        fine_model: FINE_PROPORTIONAL,
+    })
+    .await;
+    env.update_params(solearn_solana::instruction::SetFinePercentage {
+        fine_percentage: 60_00,
+    })
+    .await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    env.warp_past(inference.submit_timeout).await;
+    env.resolve(&workers).await;
+    env.crank(&workers).await;
+
+    let fine = MIN_STAKE * 6 / 10;
+    let after = balances(&mut env).await;
+    assert_eq!(after.treasury, before.treasury + fine);
+
+    // what is left is under the minimum stake and queued for withdrawal
+    let info = env.miner_info(workers.miner).await;
+    assert!(!info.is_active);
+    assert_eq!(info.offence_count, 1);
+    assert_eq!(info.stake_amount, 0);
+    assert_eq!(info.pending_unstake(), MIN_STAKE - fine);
+    env.claim_unstaked(workers.miner).await;
+    let claimed = balances(&mut env).await;
+    assert_eq!(claimed.miners[workers.miner], before.miners[workers.miner] + MIN_STAKE - fine);
+}
+
+const DISPUTE_WINDOW: u64 = 1_000;
+const DISPUTE_BOND: u64 = 5_000;
+
+#[tokio::test]
+async fn undisputed_fine_is_paid_after_the_window() {
+    let mut env = Env::new().await;
+    env.update_params(solearn_solana::instruction::SetDisputeParams {
+        dispute_window: DISPUTE_WINDOW,
+        dispute_bond: DISPUTE_BOND,
+    })
+    .await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    env.warp_past(inference.submit_timeout).await;
+    env.resolve(&workers).await;
+    env.crank(&workers).await;
+
+    // the fine is taken from the stake but stays in the vault
+    let held = balances(&mut env).await;
+    assert_eq!(held.treasury, before.treasury);
+    assert_eq!(held.vault, before.vault + MINIMUM_FEE);
+    assert_eq!(env.miner_info(workers.miner).await.pending_unstake(), MIN_STAKE - FINE);
+    let miner = env.miners[workers.miner].pubkey();
+    let record: SlashRecord = env.fetch(env.slash_record(&miner, 0)).await;
+    assert_eq!(record.status, SLASH_PENDING);
+    assert!(env.execute_slash(workers.miner, 0).await.is_err());
+
+    env.advance_clock(DISPUTE_WINDOW).await;
+    env.execute_slash(workers.miner, 0).await.unwrap();
+    let after = balances(&mut env).await;
+    assert_eq!(after.treasury, before.treasury + FINE);
+    assert_eq!(after.vault, before.vault + MINIMUM_FEE - FINE);
+    let record: SlashRecord = env.fetch(env.slash_record(&miner, 0)).await;
+    assert_eq!(record.status, SLASH_EXECUTED);
+}
+
+#[tokio::test]
+async fn overturned_dispute_restores_the_stake_and_the_bond() {
+    let mut env = Env::new().await;
+    env.update_params(solearn_solana::instruction::SetDisputeParams {
+        dispute_window: DISPUTE_WINDOW,
+        dispute_bond: DISPUTE_BOND,
+    })
+    .await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    env.warp_past(inference.submit_timeout).await;
+    env.resolve(&workers).await;
+    env.crank(&workers).await;
+
+    env.open_dispute(workers.miner, 0).await;
+    let disputed = balances(&mut env).await;
+    assert_eq!(disputed.miners[workers.miner], before.miners[workers.miner] - DISPUTE_BOND);
+    // a disputed fine waits for the arbiter even after the window
+    env.advance_clock(DISPUTE_WINDOW).await;
+    assert!(env.execute_slash(workers.miner, 0).await.is_err());
+
+    env.resolve_dispute(workers.miner, 0, true).await;
+    let after = balances(&mut env).await;
+    assert_eq!(after.miners[workers.miner], before.miners[workers.miner]);
+    assert_eq!(after.treasury, before.treasury);
+    assert_eq!(after.vault, before.vault + MINIMUM_FEE);
+    let info = env.miner_info(workers.miner).await;
+    // the unstake forced by the fine is undone as well
+    assert_eq!(info.stake_amount, MIN_STAKE);
+    assert_eq!(info.pending_unstake(), 0);
+    assert_eq!(info.offence_count, 0);
+    let miner = env.miners[workers.miner].pubkey();
+    let record: SlashRecord = env.fetch(env.slash_record(&miner, 0)).await;
+    assert_eq!(record.status, SLASH_OVERTURNED);
+
+    // the penalty is lifted, the miner rejoins at once
+    assert!(!info.is_active);
+    env.join(workers.miner).await.unwrap();
+    assert!(env.miner_info(workers.miner).await.is_active);
+    let miners_of_model: MinersOfModel = env.fetch(env.miners_of_model()).await;
+    assert!(miners_of_model.position(&miner).is_some());
+}
+
+#[tokio::test]
+async fn inconclusive_vote_escalates_to_a_new_round() {
+    let mut env = Env::new().await;
+    env.update_params(solearn_solana::instruction::SetEscalation {
+        escalation_rounds: 1,
+        escalation_validators: 2,
+    })
+    .await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"first").await;
+    env.commit(&workers, 1, b"second").await;
+    env.reveal(&workers, 0, b"first").await;
+    // no digest reaches the threshold, a new round starts instead of a refund
+    env.reveal(&workers, 1, b"second").await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 2);
+    assert_eq!(inference.round, 1);
+    // only two miners are left to draw from besides the processed one
+    assert_eq!(inference.assignment_count, 3);
+    let assignments = env.assignments(1).await;
+    assert_eq!(assignments.ids[0], workers.miner_assignment);
+    assert!(assignments.digests[1..].iter().all(|d| *d == [0; 32]));
+    let voting_info: VotingInfo = env.fetch(env.voting_info(1)).await;
+    assert_eq!(voting_info.tallies.len(), 1);
+    assert_eq!(voting_info.tallies[0].count, 1);
+
+    let round = env.next_round(&workers).await;
+    env.commit(&round, 0, b"answer").await;
+    env.commit(&round, 1, b"answer").await;
+    env.reveal(&round, 0, b"answer").await;
+    env.reveal(&round, 1, b"answer").await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    env.crank(&round).await;
+
+    // the fee goes to the round that agreed, the first one is not paid
+    let after = balances(&mut env).await;
+    let miner_fee = WORKERS_FEE / 2;
+    let validator_fee = (WORKERS_FEE - miner_fee) / 2;
+    assert_eq!(after.user, before.user - INFER_VALUE);
+    assert_eq!(after.miners[round.miner], before.miners[round.miner] + miner_fee);
+    for &(index, _) in round.validators.iter() {
+        assert_eq!(after.miners[index], before.miners[index] + validator_fee);
+    }
+}
+
+#[tokio::test]
+async fn inconclusive_vote_refunds_once_rounds_run_out() {
+    let mut env = Env::new().await;
+    env.update_params(solearn_solana::instruction::SetEscalation {
+        escalation_rounds: 1,
+        escalation_validators: 2,
+    })
+    .await;
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"first").await;
+    env.commit(&workers, 1, b"second").await;
+    env.reveal(&workers, 0, b"first").await;
+    env.reveal(&workers, 1, b"second").await;
+
+    let round = env.next_round(&workers).await;
+    env.commit(&round, 0, b"third").await;
+    env.commit(&round, 1, b"fourth").await;
+    env.reveal(&round, 0, b"third").await;
+    env.reveal(&round, 1, b"fourth").await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    assert_eq!(inference.round, 1);
+    let after = balances(&mut env).await;
+    assert_eq!(after.user, before.user - INFER_VALUE + REFUND);
+}
+
+#[tokio::test]
+async fn large_validator_set_spans_assignment_pages() {
+    let mut env = Env::with_miners(10).await;
+    let admin = env.ctx.payer.pubkey();
+    env.call(
+        solearn_solana::accounts::UpdateModel {
+            admin,
+            sol_learn_account: env.sol_learn.pubkey(),
+            model_info: env.model_info(),
+        },
+        solearn_solana::instruction::UpdateModel {
+            model: env.model,
+            params: ModelParams {
+                minimum_fee: MINIMUM_FEE,
+                tier: 0,
+                miner_requirement: 10,
+                submit_duration: DURATION,
+                commit_duration: DURATION,
+                reveal_duration: DURATION,
+            },
+        },
+        &[],
+    )
+    .await
+    .unwrap();
+    let before = balances(&mut env).await;
+
+    let workers = env.start_inference(1).await;
+    assert_eq!(workers.validators.len(), 9);
+    env.submit(&workers, b"answer").await;
+    for validator in 0..9 {
+        env.commit(&workers, validator, b"answer").await;
+    }
+    for validator in 0..9 {
+        env.reveal(&workers, validator, b"answer").await;
+    }
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    assert_eq!(inference.assignment_count, 10);
+    let assignments = env.assignments(1).await;
+    assert_eq!(assignments.ids.len(), 10);
+    assert!(assignments.digests.iter().all(|d| *d == assignments.digests[0]));
+    let voting_info: VotingInfo = env.fetch(env.voting_info(1)).await;
+    assert_eq!(voting_info.tallies.len(), 1);
+    assert_eq!(voting_info.tallies[0].count, 10);
+
+    env.crank(&workers).await;
+    let after = balances(&mut env).await;
+    let miner_fee = WORKERS_FEE / 2;
+    let validator_fee = (WORKERS_FEE - miner_fee) / 9;
+    assert_eq!(after.miners[workers.miner], before.miners[workers.miner] + miner_fee);
+    for &(index, _) in workers.validators.iter() {
+        assert_eq!(after.miners[index], before.miners[index] + validator_fee);
+    }
+}
+
+#[tokio::test]
+async fn label_comparator_groups_outputs_by_label() {
+    let mut env = Env::new().await;
+    let admin = env.ctx.payer.pubkey();
+    env.call(
+        solearn_solana::accounts::UpdateModel {
+            admin,
+            sol_learn_account: env.sol_learn.pubkey(),
+            model_info: env.model_info(),
+        },
+        solearn_solana::instruction::SetModelComparator {
+            model: env.model,
+            comparator: COMPARATOR_LABEL,
+            tolerance: 0,
+        },
+        &[],
+    )
+    .await
+    .unwrap();
+    let before = balances(&mut env).await;
+
+    // label 7 with different scores after it
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, &[7, 0, 0, 0, 90]).await;
+    env.commit(&workers, 0, &[7, 0, 0, 0, 85]).await;
+    env.commit(&workers, 1, &[7, 0, 0, 0, 60]).await;
+    env.reveal(&workers, 0, &[7, 0, 0, 0, 85]).await;
+    env.reveal(&workers, 1, &[7, 0, 0, 0, 60]).await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    let voting_info: VotingInfo = env.fetch(env.voting_info(1)).await;
+    assert_eq!(voting_info.tallies.len(), 1);
+    assert_eq!(voting_info.tallies[0].count, 3);
+
+    env.crank(&workers).await;
+    let after = balances(&mut env).await;
+    let miner_fee = WORKERS_FEE / 2;
+    let validator_fee = (WORKERS_FEE - miner_fee) / 2;
+    assert_eq!(after.miners[workers.miner], before.miners[workers.miner] + miner_fee);
+    for &(index, _) in workers.validators.iter() {
+        assert_eq!(after.miners[index], before.miners[index] + validator_fee);
+    }
+}
+
+#[tokio::test]
+async fn large_payloads_are_written_in_chunks() {
+    let mut env = Env::new().await;
+    let input: Vec<u8> = (0..4_000u32).map(|i| i as u8).collect();
+    let output: Vec<u8> = (0..3_000u32).map(|i| (i * 7) as u8).collect();
+
+    let workers = env.start_inference_with(1, &input).await;
+    env.submit(&workers, &output).await;
+    env.commit(&workers, 0, &output).await;
+    env.commit(&workers, 1, &output).await;
+    env.reveal(&workers, 0, &output).await;
+    env.reveal(&workers, 1, &output).await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    assert_eq!(inference.input, DataRef::inline(hash(&input).to_bytes()));
+    let stored: ChunkedData = env.fetch(inference.input_data).await;
+    assert!(stored.finalized);
+    assert_eq!(stored.data, input);
+
+    let assignment: Assignment = env.fetch(env.assignment(workers.miner_assignment)).await;
+    assert_eq!(assignment.output, DataRef::inline(hash(&output).to_bytes()));
+    let stored: ChunkedData = env.fetch(assignment.output_data).await;
+    assert_eq!(stored.data, output);
+    let voting_info: VotingInfo = env.fetch(env.voting_info(1)).await;
+    assert_eq!(voting_info.tallies.len(), 1);
+    assert_eq!(voting_info.tallies[0].count, 3);
+}
+
+#[tokio::test]
+async fn off_chain_outputs_are_grouped_by_content_hash() {
+    let mut env = Env::new().await;
+    let content_hash = hash(b"answer").to_bytes();
+    let off_chain = |scheme, uri: &str| DataRef {
+        scheme,
+        uri: uri.to_string(),
+        keccak: content_hash,
+    };
+
+    let workers = env.start_inference(1).await;
+    env.submit_ref(&workers, off_chain(DataScheme::Ipfs, "ipfs://bafyanswer")).await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal_ref(&workers, 1, off_chain(DataScheme::Https, "https://example.com/answer"))
+        .await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    let voting_info: VotingInfo = env.fetch(env.voting_info(1)).await;
+    assert_eq!(voting_info.tallies.len(), 1);
+    assert_eq!(voting_info.tallies[0].count, 3);
+
+    let assignment: Assignment = env.fetch(env.assignment(workers.miner_assignment)).await;
+    assert_eq!(assignment.output.scheme, DataScheme::Ipfs);
+    assert_eq!(assignment.output_data, Pubkey::default());
+}
+
+#[tokio::test]
+async fn off_chain_outputs_need_the_exact_comparator() {
+    let mut env = Env::new().await;
+    let admin = env.ctx.payer.pubkey();
+    env.call(
+        solearn_solana::accounts::UpdateModel {
+            admin,
+            sol_learn_account: env.sol_learn.pubkey(),
+            model_info: env.model_info(),
+        },
+        solearn_solana::instruction::SetModelComparator {
+            model: env.model,
+            comparator: COMPARATOR_LABEL,
+            tolerance: 0,
+        },
+        &[],
+    )
+    .await
+    .unwrap();
+    let off_chain = |scheme, uri: &str| DataRef {
+        scheme,
+        uri: uri.to_string(),
+        keccak: hash(b"answer").to_bytes(),
+    };
+
+    // only the content hash is on chain, labels can not be compared
+    let workers = env.start_inference(1).await;
+    let submitted = env
+        .try_submit_ref(&workers, off_chain(DataScheme::Ipfs, "ipfs://bafyanswer"))
+        .await;
+    assert!(submitted.is_err());
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    let revealed = env
+        .try_reveal_ref(&workers, 0, off_chain(DataScheme::Https, "https://example.com/answer"))
+        .await;
+    assert!(revealed.is_err());
+    let voting_info: VotingInfo = env.fetch(env.voting_info(1)).await;
+    assert_eq!(voting_info.total_reveal, 0);
+
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+}
+
+#[tokio::test]
+async fn resolved_inference_mints_the_dao_token() {
+    const DAO_TOKEN_REWARD: u64 = 1_000_000;
+    let mut env = Env::new().await;
+    env.set_dao_token().await;
+    env.update_params(solearn_solana::instruction::SetDaoTokenReward {
+        new_dao_token_reward: DAO_TOKEN_REWARD,
+    })
+    .await;
+    let payer = env.ctx.payer.pubkey();
+    let cranker_wallet = env.create_wallet(&payer).await;
+
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+
+    let receivers: DAOTokenReceiverInfos = env.fetch(env.dao_receiver_infos(1)).await;
+    assert_eq!(receivers.values.len(), 4);
+    assert!(env
+        .pending_tasks(1)
+        .await
+        .iter()
+        .any(|t| matches!(t, Task::MintDaoToken { .. })));
+
+    env.crank_tasks(&workers, cranker_wallet).await;
+
+    // miner_percentage 50% of the reward goes to the workers, shared like
+    // the fee, and l2 owner 20%; the user's 30% waits for its rating
+    let workers_token = DAO_TOKEN_REWARD / 2;
+    let miner_token = workers_token / 2;
+    let validator_token = (workers_token - miner_token) / 2;
+    let miner = env.miners[workers.miner].pubkey();
+    assert_eq!(env.balance(env.dao_wallet(&miner)).await, miner_token);
+    for &(index, _) in workers.validators.iter() {
+        let validator = env.miners[index].pubkey();
+        assert_eq!(env.balance(env.dao_wallet(&validator)).await, validator_token);
+    }
+    let user = env.user.pubkey();
+    assert_eq!(env.balance(env.dao_wallet(&user)).await, 0);
+    assert_eq!(env.balance(env.dao_wallet(&payer)).await, DAO_TOKEN_REWARD / 5);
+}
+
+#[tokio::test]
+async fn crank_creates_a_missing_dao_token_wallet() {
+    const DAO_TOKEN_REWARD: u64 = 1_000_000;
+    let mut env = Env::new().await;
+    env.set_dao_token().await;
+    env.update_params(solearn_solana::instruction::SetDaoTokenReward {
+        new_dao_token_reward: DAO_TOKEN_REWARD,
+    })
+    .await;
+    let payer = env.ctx.payer.pubkey();
+    let cranker_wallet = env.create_wallet(&payer).await;
+
+    let workers = env.start_inference(1).await;
+    // the miner never opened a DAO token wallet
+    let miner = env.miners[workers.miner].pubkey();
+    env.dao_wallets.retain(|(owner, _)| *owner != miner);
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+
+    env.crank_tasks(&workers, cranker_wallet).await;
+
+    assert!(env.pending_tasks(1).await.is_empty());
+    let workers_token = DAO_TOKEN_REWARD / 2;
+    assert_eq!(env.balance(env.dao_wallet(&miner)).await, workers_token / 2);
+}
+
+#[tokio::test]
+async fn dao_token_emission_stops_at_the_epoch_budget() {
+    const DAO_TOKEN_REWARD: u64 = 1_000_000;
+    let mut env = Env::new().await;
+    env.set_dao_token().await;
+    env.update_params(solearn_solana::instruction::SetDaoTokenReward {
+        new_dao_token_reward: DAO_TOKEN_REWARD,
+    })
+    .await;
+    env.update_params(solearn_solana::instruction::SetDaoTokenEpochBudget {
+        new_dao_token_epoch_budget: DAO_TOKEN_REWARD * 7 / 20,
+    })
+    .await;
+    let payer = env.ctx.payer.pubkey();
+    let cranker_wallet = env.create_wallet(&payer).await;
+
+    // the first inference asks for 70% of the reward, the workers' 50% and
+    // the l2 owner's 20%, and gets half of it
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+    env.crank_tasks(&workers, cranker_wallet).await;
+
+    let user = env.user.pubkey();
+    let miner = env.miners[workers.miner].pubkey();
+    assert_eq!(env.balance(env.dao_wallet(&miner)).await, DAO_TOKEN_REWARD / 8);
+    assert_eq!(env.balance(env.dao_wallet(&payer)).await, DAO_TOKEN_REWARD / 10);
+    let acc: SolLearnInfo = env.fetch(env.sol_learn.pubkey()).await;
+    assert_eq!(acc.dao_token_epoch_minted, DAO_TOKEN_REWARD * 7 / 20);
+    // and the user's rating gets nothing either
+    env.rate(&workers, 10).await.unwrap();
+    assert_eq!(env.balance(env.dao_wallet(&user)).await, 0);
+
+    // nothing is left for the second one in this epoch
+    let workers = env.start_inference(2).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+    assert!(!env
+        .pending_tasks(2)
+        .await
+        .iter()
+        .any(|t| matches!(t, Task::MintDaoToken { .. })));
+    let receivers: DAOTokenReceiverInfos = env.fetch(env.dao_receiver_infos(2)).await;
+    assert!(receivers.values.is_empty());
+}
+
+#[tokio::test]
+async fn referred_inference_rewards_the_referrer() {
+    const DAO_TOKEN_REWARD: u64 = 1_000_000;
+    let mut env = Env::new().await;
+    env.set_dao_token().await;
+    env.update_params(solearn_solana::instruction::SetDaoTokenReward {
+        new_dao_token_reward: DAO_TOKEN_REWARD,
+    })
+    .await;
+    let payer = env.ctx.payer.pubkey();
+    let cranker_wallet = env.create_wallet(&payer).await;
+
+    let user = env.user.insecure_clone();
+    let referrer = Keypair::new();
+    env.send(
+        &[system_instruction::transfer(&payer, &referrer.pubkey(), 1_000_000_000)],
+        &[],
+    )
+    .await
+    .unwrap();
+    env.register_referrer(&referrer, "friend").await.unwrap();
+    assert!(env.set_referrer(&referrer, "friend", &referrer.pubkey()).await.is_err());
+    env.set_referrer(&user, "friend", &referrer.pubkey()).await.unwrap();
+    // a new blockhash, the same transaction would be dropped as a duplicate
+    env.ctx.last_blockhash = env.ctx.get_new_latest_blockhash().await.unwrap();
+    assert!(env.set_referrer(&user, "friend", &referrer.pubkey()).await.is_err());
+    // the referrer can not be referred back by its referee
+    env.register_referrer(&user, "back").await.unwrap();
+    assert!(env.set_referrer(&referrer, "back", &user.pubkey()).await.is_err());
+
+    let dao_token = env.dao_token.unwrap();
+    let wallet = env.create_token_wallet(&dao_token, &referrer.pubkey()).await;
+    env.dao_wallets.push((referrer.pubkey(), wallet));
+    // once referred the user can not leave the referrer out
+    let input = DataRef {
+        scheme: DataScheme::Ipfs,
+        uri: "ipfs://bafyprompt".to_string(),
+        keccak: [1; 32],
+    };
+    assert!(env.infer(1, input).await.is_err());
+    env.referrer = Some(referrer.pubkey());
+
+    let workers = env.start_inference(1).await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.referrer, referrer.pubkey());
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+    env.crank_tasks(&workers, cranker_wallet).await;
+
+    // the user gets the referee share, its own comes with its rating
+    assert_eq!(env.balance(wallet).await, DAO_TOKEN_REWARD / 10);
+    assert_eq!(env.balance(env.dao_wallet(&user.pubkey())).await, DAO_TOKEN_REWARD / 20);
+    let stats: Referrer = env.fetch(env.referrer_state(&referrer.pubkey())).await;
+    assert_eq!(stats.referee_count, 1);
+    assert_eq!(stats.referred_inferences, 1);
+    assert_eq!(stats.referred_volume, INFER_VALUE);
+    assert_eq!(stats.earned_rewards, DAO_TOKEN_REWARD / 10);
+}
+
+#[tokio::test]
+async fn rating_mints_the_user_share_and_scores_the_miner() {
+    const DAO_TOKEN_REWARD: u64 = 1_000_000;
+    let mut env = Env::new().await;
+    env.set_dao_token().await;
+    env.update_params(solearn_solana::instruction::SetDaoTokenReward {
+        new_dao_token_reward: DAO_TOKEN_REWARD,
+    })
+    .await;
+    env.update_params(solearn_solana::instruction::SetQualityWeight {
+        new_quality_weight: 50_00,
+    })
+    .await;
+
+    let workers = env.start_inference(1).await;
+    // not solved yet
+    assert!(env.rate(&workers, 8).await.is_err());
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+
+    assert!(env.rate(&workers, 11).await.is_err());
+    // the rejected rate(8) above would otherwise be replayed from the cache
+    env.ctx.last_blockhash = env.ctx.get_new_latest_blockhash().await.unwrap();
+    env.rate(&workers, 8).await.unwrap();
+    env.ctx.last_blockhash = env.ctx.get_new_latest_blockhash().await.unwrap();
+    assert!(env.rate(&workers, 8).await.is_err());
+
+    // user_percentage 30% of the reward, times 8 / 10
+    let user = env.user.pubkey();
+    assert_eq!(env.balance(env.dao_wallet(&user)).await, DAO_TOKEN_REWARD * 24 / 100);
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.score, 8);
+    let model_info: ModelInfo = env.fetch(env.model_info()).await;
+    assert_eq!((model_info.quality_score, model_info.rating_count), (8_000, 1));
+
+    // half of the miner's selection weight follows its 80% quality score
+    let miner = env.miners[workers.miner].pubkey();
+    let miner_info: MinerInfo = env.fetch(env.miner_account(&miner)).await;
+    assert_eq!(miner_info.quality_score, 8_000);
+    let miners_of_model: MinersOfModel = env.fetch(env.miners_of_model()).await;
+    let index = miners_of_model.position(&miner).unwrap();
+    assert_eq!(miners_of_model.stake_at(index), miner_info.weight() * 9 / 10);
+}
+
+#[tokio::test]
+async fn refunded_inference_can_not_be_rated() {
+    let mut env = Env::new().await;
+    env.set_dao_token().await;
+
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    env.warp_past(inference.commit_timeout).await;
+    env.resolve(&workers).await;
+
+    let inference: Inference = env.fetch(env.inference(1)).await;
+    assert_eq!(inference.status, 4);
+    assert!(!inference.solved);
+    assert!(env.rate(&workers, 1).await.is_err());
+
+    let model_info: ModelInfo = env.fetch(env.model_info()).await;
+    assert_eq!(model_info.rating_count, 0);
+    let miner = env.miners[workers.miner].pubkey();
+    let miner_info: MinerInfo = env.fetch(env.miner_account(&miner)).await;
+    assert_eq!(miner_info.rating_count, 0);
+}
+
+#[tokio::test]
+async fn miners_of_model_of_the_old_layout_is_migrated() {
+    let mut env = Env::new().await;
+    let current: MinersOfModel = env.fetch(env.miners_of_model()).await;
+    let miners: Vec<Pubkey> = (0..current.count()).map(|i| current.miner_at(i)).collect();
+
+    // the list before selection weights: the miner keys alone, no version
+    let address = env.miners_of_model();
+    let mut account = env.ctx.banks_client.get_account(address).await.unwrap().unwrap();
+    let mut data = MinersOfModel::DISCRIMINATOR.to_vec();
+    data.push(current.bump);
+    data.extend_from_slice(&(miners.len() as u32 * 32).to_le_bytes());
+    for miner in miners.iter() {
+        data.extend_from_slice(miner.as_ref());
+    }
+    account.data = data;
+    env.ctx.set_account(&address, &account.into());
+
+    // the program does not read the old layout
+    let user = env.user.insecure_clone();
+    let input_hash = env.upload(&user, 1, b"prompt").await;
+    assert!(env.infer(1, DataRef::inline(input_hash)).await.is_err());
+    // every listed miner is needed, in order
+    assert!(env.migrate_miners_of_model(&miners[1..]).await.is_err());
+    let reversed: Vec<Pubkey> = miners.iter().rev().copied().collect();
+    assert!(env.migrate_miners_of_model(&reversed).await.is_err());
+
+    env.migrate_miners_of_model(&miners).await.unwrap();
+    let migrated: MinersOfModel = env.fetch(address).await;
+    assert_eq!(migrated.version, MINERS_OF_MODEL_VERSION);
+    assert_eq!(migrated.data, current.data);
+    env.ctx.last_blockhash = env.ctx.get_new_latest_blockhash().await.unwrap();
+    assert!(env.migrate_miners_of_model(&miners).await.is_err());
+    env.infer(1, DataRef::inline(input_hash)).await.unwrap();
+}
+
+#[tokio::test]
+async fn models_without_a_model_info_get_one() {
+    let mut env = Env::new().await;
+    // a model of the old add_model: listed in Models, without a ModelInfo
+    let address = env.model_info();
+    env.ctx.set_account(&address, &Account::default().into());
+
+    let user = env.user.insecure_clone();
+    let input_hash = env.upload(&user, 1, b"prompt").await;
+    assert!(env.infer(1, DataRef::inline(input_hash)).await.is_err());
+
+    let init_model_info = |model: Pubkey, model_info: Pubkey, env: &Env| Instruction {
+        program_id: solearn_solana::ID,
+        accounts: solearn_solana::accounts::InitModelInfoVld {
+            admin: env.ctx.payer.pubkey(),
+            sol_learn_account: env.sol_learn.pubkey(),
+            models: env.models(),
+            model_info,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: solearn_solana::instruction::InitModelInfo {
+            model,
+            params: MODEL_PARAMS,
+        }
+        .data(),
+    };
+    // only listed models
+    let unknown = Pubkey::new_unique();
+    let unknown_info = pda(&[b"model_info", env.sol_learn.pubkey().as_ref(), unknown.as_ref()]);
+    let ix = init_model_info(unknown, unknown_info, &env);
+    assert!(env.send(&[ix], &[]).await.is_err());
+
+    let ix = init_model_info(env.model, address, &env);
+    env.send(&[ix], &[]).await.unwrap();
+    let model_info: ModelInfo = env.fetch(address).await;
+    assert_eq!((model_info.model, model_info.enabled), (env.model, true));
+    assert_eq!(model_info.minimum_fee, MINIMUM_FEE);
+    env.ctx.last_blockhash = env.ctx.get_new_latest_blockhash().await.unwrap();
+    env.infer(1, DataRef::inline(input_hash)).await.unwrap();
+}
+
+#[tokio::test]
+async fn miner_stats_record_wins_and_missed_commits() {
+    let mut env = Env::new().await;
+    let payer = env.ctx.payer.pubkey();
+    let cranker_wallet = env.create_wallet(&payer).await;
+
+    let workers = env.start_inference(1).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    env.commit(&workers, 1, b"answer").await;
+    env.reveal(&workers, 0, b"answer").await;
+    env.reveal(&workers, 1, b"answer").await;
+    env.crank_tasks(&workers, cranker_wallet).await;
+
+    let miner = env.miners[workers.miner].pubkey();
+    let stats: MinerStats = env.fetch(env.miner_stats(&miner)).await;
+    assert_eq!((stats.seized, stats.submitted, stats.revealed), (1, 1, 0));
+    assert_eq!((stats.wins, stats.slashes), (1, 0));
+    assert_eq!(stats.success_rate, 10_000);
+    assert!(stats.last_active_slot > 0);
+    for &(index, _) in workers.validators.iter() {
+        let validator = env.miners[index].pubkey();
+        let stats: MinerStats = env.fetch(env.miner_stats(&validator)).await;
+        assert_eq!((stats.revealed, stats.wins), (1, 1));
+    }
+
+    // a validator that never commits loses one eighth of its success rate
+    let workers = env.start_inference(2).await;
+    env.submit(&workers, b"answer").await;
+    env.commit(&workers, 0, b"answer").await;
+    let inference: Inference = env.fetch(env.inference(2)).await;
+    env.warp_past(inference.commit_timeout).await;
+    env.resolve(&workers).await;
+    env.crank(&workers).await;
+
+    let silent = env.miners[workers.validators[1].0].pubkey();
+    let stats: MinerStats = env.fetch(env.miner_stats(&silent)).await;
+    assert_eq!((stats.slashes, stats.missed_commits), (1, 1));
+    assert_eq!(stats.outcome_count, 2);
+    assert_eq!(stats.success_rate, 8_750);
+}
+
+#[tokio::test]
+async fn update_epoch_closes_every_elapsed_epoch() {
+    let mut env = Env::new().await;
+    let admin = env.ctx.payer.pubkey();
+    let sol_learn = env.sol_learn.pubkey();
+    let reward_pool = pda(&[b"reward_pool", sol_learn.as_ref()]);
+    env.call(
+        solearn_solana::accounts::InitRewardPool {
+            admin,
+            sol_learn_account: sol_learn,
+            reward_pool_pda: reward_pool,
+            system_program: system_program::ID,
+        },
+        solearn_solana::instruction::InitRewardPool {},
+        &[],
+    )
+    .await
+    .unwrap();
+    let reward_pool_wallet = env.create_wallet(&reward_pool).await;
+    let snapshot = |epoch_id: u64| pda(&[b"reward_in_epoch", epoch_id.to_le_bytes().as_ref()]);
+
+    // three epochs elapsed, a fourth snapshot account is passed but not due
+    env.advance_clock(3 * 1_000_000_000).await;
+    let mut ix = Instruction {
+        program_id: solearn_solana::ID,
+        accounts: solearn_solana::accounts::NewEpochVld {
+            sol_learn_account: sol_learn,
+            miner_reward: snapshot(0),
+            reward_pool_pda: reward_pool,
+            reward_pool_wallet,
+            signer: admin,
+            system_program: system_program::ID,
+            sysvar_clock: sysvar::clock::ID,
+        }
+        .to_account_metas(None),
+        data: solearn_solana::instruction::UpdateEpoch { epoch_id: 0 }.data(),
+    };
+    ix.accounts
+        .extend((1..=3).map(|epoch_id| AccountMeta::new(snapshot(epoch_id), false)));
+    env.send(&[ix], &[]).await.unwrap();
+
+    let acc: SolLearnInfo = env.fetch(sol_learn).await;
+    assert_eq!(acc.last_epoch, 3);
+    assert_eq!(acc.epoch_joined_miners, 0);
+    // the miners joined in epoch 0, they are counted from epoch 1 on
+    let totals = [0, 3, 3];
+    for (epoch_id, total_miner) in totals.into_iter().enumerate() {
+        let state: MinerEpochState = env.fetch(snapshot(epoch_id as u64)).await;
+        assert_eq!(state.epoch_id, epoch_id as u64);
+        assert_eq!(state.total_miner, total_miner);
+    }
+    let pending = env.ctx.banks_client.get_account(snapshot(3)).await.unwrap();
+    assert!(pending.is_none());
+}
+
+#[tokio::test]
+async fn miners_of_a_retired_model_are_reassigned() {
+    let mut env = Env::with_miners(1).await;
+    let admin = env.ctx.payer.pubkey();
+    let sol_learn = env.sol_learn.pubkey();
+    let miner = env.miners[0].insecure_clone();
+    let next_model = Pubkey::new_unique();
+    let next_miners_of_model = pda(&[b"models", sol_learn.as_ref(), next_model.as_ref()]);
+    env.call(
+        solearn_solana::accounts::CreateModel {
+            admin,
+            sol_learn_account: sol_learn,
+            models: env.models(),
+            miners_of_model: next_miners_of_model,
+            model_info: pda(&[b"model_info", sol_learn.as_ref(), next_model.as_ref()]),
+            system_program: system_program::ID,
+        },
+        solearn_solana::instruction::CreateModel {
+            model: next_model,
+            params: ModelParams {
+                minimum_fee: MINIMUM_FEE,
+                tier: 0,
+                miner_requirement: 3,
+                submit_duration: DURATION,
+                commit_duration: DURATION,
+                reveal_duration: DURATION,
+            },
+        },
+        &[],
+    )
+    .await
+    .unwrap();
+
+    // the miner leaves, its model is retired under it
+    env.unstake(0, MIN_STAKE).await.unwrap();
+    env.call(
+        solearn_solana::accounts::RetireModel {
+            admin,
+            sol_learn_account: sol_learn,
+            models: env.models(),
+            miners_of_model: env.miners_of_model(),
+            model_info: env.model_info(),
+            system_program: system_program::ID,
+        },
+        solearn_solana::instruction::RetireModel { model: env.model },
+        &[],
+    )
+    .await
+    .unwrap();
+
+    let reassign = Instruction {
+        program_id: solearn_solana::ID,
+        accounts: solearn_solana::accounts::ReassignModel {
+            signer: admin,
+            sol_learn_account: sol_learn,
+            miner_account: env.miner_account(&miner.pubkey()),
+            models: env.models(),
+            recent_slothashes: sysvar::slot_hashes::ID,
+        }
+        .to_account_metas(None),
+        data: solearn_solana::instruction::ReassignModel {}.data(),
+    };
+    env.send(std::slice::from_ref(&reassign), &[]).await.unwrap();
+    assert_eq!(env.miner_info(0).await.model, next_model);
+    env.advance_clock(1).await;
+    assert!(env.send(&[reassign], &[]).await.is_err());
+
+    // accounts seeded by the miner's model resolve again
+    env.call(
+        solearn_solana::accounts::Topup {
+            miner: miner.pubkey(),
+            sol_learn_account: sol_learn,
+            miner_account: env.miner_account(&miner.pubkey()),
+            miners_of_model: next_miners_of_model,
+            miner_staking_wallet: env.miner_wallets[0],
+            vault_wallet_owner_pda: env.vault(),
+            vault_staking_wallet: env.vault_wallet,
+            staking_token: env.mint.pubkey(),
+            token_program: spl_token::id(),
+        },
+        solearn_solana::instruction::Topup {
+            topup_amount: MIN_STAKE,
+        },
+        &[&miner],
+    )
+    .await
+    .unwrap();
+    assert_eq!(env.miner_info(0).await.stake_amount, MIN_STAKE);
+}
@@ -10,7 +10,9 @@ use mpl_token_metadata::types::{Collection, Creator, DataV2};
 use anchor_spl::metadata::{ 
     create_master_edition_v3, create_metadata_accounts_v3, CreateMasterEditionV3, CreateMetadataAccountsV3
 };
-use solearn_solana::cpi::accounts::InferVld;
+use anchor_lang::solana_program::keccak::hash;
+use solearn_solana::cpi::accounts::{CreateDataVld, InferVld, WriteDataVld};
+use solearn_solana::state_inf::DataRef;
 use anchor_spl::token::{self, Transfer};
 
 declare_id!("7rPLAQ9UN8vMhxWBTzp2DHLfoqpihXaXhoGJ6WiCNUjY");
@@ -273,7 +275,10 @@ pub mod prompt_system_manager {
     }
     
     // infer 
-    pub fn infer_request(ctx: Context<SytemInfer>, input: Vec<u8>, creator: Pubkey, _value: u64, inference_id: u64,) -> Result<()> {
+    // the prompt and the input are written to a solearn data account owned by
+    // the signer, the assignment pages of the inference go in the remaining
+    // accounts
+    pub fn infer_request<'info>(ctx: Context<'_, '_, '_, 'info, SytemInfer<'info>>, input: Vec<u8>, creator: Pubkey, _value: u64, inference_id: u64, model: Pubkey) -> Result<()> {
         msg!("Instruction: Infer Request");
 
         // append infer request
@@ -295,6 +300,23 @@ pub mod prompt_system_manager {
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
         token::transfer(cpi_ctx, ctx.accounts.promt_account.fee)?;
 
+        // store the request, too large for the infer instruction
+        let infer_hash = hash(&infer_data).to_bytes();
+        let cpi_ctx = CpiContext::new(ctx.accounts.solearn_program.to_account_info(), CreateDataVld {
+            data: ctx.accounts.input_data.to_account_info(),
+            signer: ctx.accounts.signer.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+        });
+        solearn_solana::cpi::create_data(cpi_ctx, inference_id, infer_data.len() as u32)?;
+        let write_accounts = || WriteDataVld {
+            data: ctx.accounts.input_data.to_account_info(),
+            signer: ctx.accounts.signer.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.solearn_program.to_account_info(), write_accounts());
+        solearn_solana::cpi::write_data(cpi_ctx, inference_id, 0, infer_data)?;
+        let cpi_ctx = CpiContext::new(ctx.accounts.solearn_program.to_account_info(), write_accounts());
+        solearn_solana::cpi::finalize_data(cpi_ctx, inference_id, infer_hash)?;
+
         // call infer to workerhub
         let cpi_program = ctx.accounts.solearn_program.to_account_info();
         let cpi_accounts = InferVld {
@@ -304,19 +326,24 @@ pub mod prompt_system_manager {
             // assignment: ctx.accounts.assignment.to_account_info(), // Add missing field
             // miner_addresses: ctx.accounts.miner_addresses.to_account_info(), // Add missing field
             tasks: ctx.accounts.tasks.to_account_info(), // Add missing field
+            dao_receiver_infos: ctx.accounts.dao_receiver_infos.to_account_info(),
             signer: ctx.accounts.signer.to_account_info(), // Add missing field
             vault_wallet_owner_pda: ctx.accounts.vault_wallet_owner_pda.to_account_info(), // Add missing field
             miner_staking_wallet: ctx.accounts.miner_staking_wallet.to_account_info(),
-            models: ctx.accounts.models.to_account_info(),
-            // referrer: ctx.accounts.referrer.to_account_info(),
+            recent_slothashes: ctx.accounts.recent_slothashes.to_account_info(),
+            model_info: ctx.accounts.model_info.to_account_info(),
+            referee: ctx.accounts.referee.to_account_info(),
+            referrer: ctx.accounts.referrer.as_ref().map(|referrer| referrer.to_account_info()),
             token_program: ctx.accounts.token_program.to_account_info(),
             vault_staking_wallet: ctx.accounts.vault_staking_wallet.to_account_info(),
             miners_of_model: ctx.accounts.miners_of_model.to_account_info(),
             // dao_receiver_infos: ctx.accounts.miners_of_model.to_account_info(),
             voting_info: ctx.accounts.miners_of_model.to_account_info(),
+            input_data: Some(ctx.accounts.input_data.to_account_info()),
         };
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        solearn_solana::cpi::infer(cpi_ctx, inference_id, creator, infer_data, infer_value, ctx.accounts.models.key())?;
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        solearn_solana::cpi::infer(cpi_ctx, inference_id, creator, DataRef::inline(infer_hash), infer_value, model)?;
         
 
         Ok(())
@@ -264,6 +264,9 @@ pub struct SytemInfer<'info> {
     pub tasks: UncheckedAccount<'info>,
     /// CHECK:
     #[account(mut)]
+    pub dao_receiver_infos: UncheckedAccount<'info>,
+    /// CHECK:
+    #[account(mut)]
     pub signer: Signer<'info>,
     /// CHECK:
     #[account(mut)]
@@ -286,12 +289,15 @@ pub struct SytemInfer<'info> {
     pub inferer_token_account: InterfaceAccount<'info, TokenAccount>,
     pub miner_staking_wallet: InterfaceAccount<'info, TokenAccount>,
     pub vault_staking_wallet: InterfaceAccount<'info, TokenAccount>,
-    /// CHECK:
+    /// CHECK: checked by the solearn program
+    pub model_info: UncheckedAccount<'info>,
+    /// CHECK: the signer's referral state, checked by the solearn program
+    pub referee: UncheckedAccount<'info>,
+    /// CHECK: state of the signer's referrer, required once it has one
     #[account(mut)]
-    pub models: UncheckedAccount<'info>,
-    /// CHECK:
-    #[account(mut)]
-    pub referrer: UncheckedAccount<'info>,
+    pub referrer: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked by the solearn program
+    pub recent_slothashes: UncheckedAccount<'info>,
     /// init new promt account
     #[account( 
         seeds = ["promt".as_bytes(), 
@@ -302,6 +308,9 @@ pub struct SytemInfer<'info> {
     )]
     pub promt_account: Account<'info, PromptAccount>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: solearn data account of the signer for the inference id, created here
+    #[account(mut)]
+    pub input_data: UncheckedAccount<'info>,
 }
 
 
@@ -1,5 +1,5 @@
 import { AnchorProvider, Program, Wallet, setProvider } from "@coral-xyz/anchor";
-import { clusterApiUrl, Connection, PublicKey, Keypair, SystemProgram, SYSVAR_CLOCK_PUBKEY } from "@solana/web3.js";
+import { clusterApiUrl, Connection, PublicKey, Keypair, SystemProgram, SYSVAR_CLOCK_PUBKEY, SYSVAR_SLOT_HASHES_PUBKEY } from "@solana/web3.js";
 import type { HybridModel } from "../target/types/hybrid_model";
 import hm from "../target/idl/hybrid_model.json";
 import { BN } from "bn.js";
@@ -26,6 +26,7 @@ export const init = async () => {
     const seeds = [Buffer.from("model_storage"), id.toBuffer('le', 8)];
     const accounts: Record<string, PublicKey> = {
         sysvarClock: SYSVAR_CLOCK_PUBKEY,
+        recentSlothashes: SYSVAR_SLOT_HASHES_PUBKEY,
         systemProgram: SystemProgram.programId,
     };
     accounts.modelStorage = PublicKey.findProgramAddressSync(seeds, program.programId)[0];
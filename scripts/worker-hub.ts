@@ -1,5 +1,5 @@
 import { AnchorProvider, Program, Wallet, setProvider } from "@coral-xyz/anchor";
-import { clusterApiUrl, Connection, PublicKey, Keypair, SystemProgram, SYSVAR_CLOCK_PUBKEY, TransactionInstruction, Transaction, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { clusterApiUrl, Connection, PublicKey, Keypair, SystemProgram, SYSVAR_CLOCK_PUBKEY, SYSVAR_SLOT_HASHES_PUBKEY, TransactionInstruction, Transaction, LAMPORTS_PER_SOL } from "@solana/web3.js";
 import type { Solearn } from "../target/types/solearn";
 // import type { BasicToken } from "../target/types/basic_token";
 import solearnIdl from "../target/idl/solearn.json";
@@ -124,6 +124,7 @@ export const init = async () => {
     const accounts: Record<string, PublicKey> = {
         tokenProgram: TOKEN_PROGRAM_ID,
         sysvarClock: SYSVAR_CLOCK_PUBKEY,
+        recentSlothashes: SYSVAR_SLOT_HASHES_PUBKEY,
         systemProgram: SystemProgram.programId,
     };
     const stakingTokenPubKey = new PublicKey(token1.publicKey.toBase58()); // new PublicKey("6qKcWsgczLGbpDH6426r4govbd1HgDMg6j5LLikx3bop");
@@ -158,6 +159,14 @@ export const init = async () => {
         ],
         solearnProgram.programId
     )[0];
+    accounts.modelInfo = PublicKey.findProgramAddressSync(
+        [
+            Buffer.from("model_info"),
+            solLearnAccount.publicKey.toBuffer(),
+            model1.publicKey.toBuffer(),
+        ],
+        solearnProgram.programId
+    )[0];
     accounts.minerStakingWallet = tokenAccount1;
 
     // const tx = await solearnProgram.methods
@@ -215,7 +224,17 @@ export const init = async () => {
     // await sleep(1000);
 
     const tx3 = await solearnProgram.methods
-        .addModel(model1.publicKey)
+        .createModel(
+            model1.publicKey,
+            {
+                minimumFee: minFeeToUse,
+                tier: 0,
+                minerRequirement,
+                submitDuration,
+                commitDuration,
+                revealDuration,
+            },
+        )
         .accounts(accounts)
         .rpc();
     console.log("tx3", tx3);
@@ -7,7 +7,7 @@ import {
   TOKEN_PROGRAM_ID,
   getAssociatedTokenAddressSync,
 } from '@solana/spl-token';
-import { Keypair, SYSVAR_CLOCK_PUBKEY, SYSVAR_RENT_PUBKEY, PublicKey } from '@solana/web3.js';
+import { Keypair, SYSVAR_CLOCK_PUBKEY, SYSVAR_SLOT_HASHES_PUBKEY, SYSVAR_RENT_PUBKEY, PublicKey } from '@solana/web3.js';
 const TOKEN_PROGRAM = TOKEN_PROGRAM_ID;
 const IDL = require('../target/idl/prompt_system_manager.json');
 IDL.address = new PublicKey("7rPLAQ9UN8vMhxWBTzp2DHLfoqpihXaXhoGJ6WiCNUjY");
@@ -22,6 +22,7 @@ describe('Prompt System Manager Bankrun test', () => {
   const accounts: Record<string, PublicKey> = {
     tokenProgram: TOKEN_PROGRAM,
     sysvarClock: SYSVAR_CLOCK_PUBKEY,
+    recentSlothashes: SYSVAR_SLOT_HASHES_PUBKEY,
     systemProgram: SYSTEM_PROGRAM_ID,
     associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
     rent: SYSVAR_RENT_PUBKEY,
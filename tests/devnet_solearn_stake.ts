@@ -8,7 +8,7 @@ import {
   createAssociatedTokenAccountIdempotentInstruction,
   getAssociatedTokenAddressSync,
 } from '@solana/spl-token';
-import {Keypair, SYSVAR_CLOCK_PUBKEY, SYSVAR_RENT_PUBKEY, PublicKey, sendAndConfirmTransaction, Transaction} from '@solana/web3.js';
+import {Keypair, SYSVAR_CLOCK_PUBKEY, SYSVAR_SLOT_HASHES_PUBKEY, SYSVAR_RENT_PUBKEY, PublicKey, sendAndConfirmTransaction, Transaction} from '@solana/web3.js';
 const TOKEN_PROGRAM = TOKEN_PROGRAM_ID;
 const IDL = require('../target/idl/solearn.json');
 IDL.address = new PublicKey(process.env.SOLEARN_PROGRAM_ID ? process.env.SOLEARN_PROGRAM_ID : IDL.address );
@@ -24,6 +24,7 @@ describe('Solearn Staking', () => {
   const accounts: Record<string, PublicKey> = {
     tokenProgram: TOKEN_PROGRAM,
     sysvarClock: SYSVAR_CLOCK_PUBKEY,
+    recentSlothashes: SYSVAR_SLOT_HASHES_PUBKEY,
     systemProgram: SYSTEM_PROGRAM_ID,
     associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
     rent: SYSVAR_RENT_PUBKEY,
@@ -68,9 +69,21 @@ describe('Solearn Staking', () => {
       [Buffer.from('models'), accounts.solLearnAccount.toBuffer(), modelPubkey.toBuffer()],
       program.programId,
     )[0];
+    accounts.modelInfo = PublicKey.findProgramAddressSync(
+      [Buffer.from('model_info'), accounts.solLearnAccount.toBuffer(), modelPubkey.toBuffer()],
+      program.programId,
+    )[0];
     const addModelInst = await program.instruction
-      .addModel(
+      .createModel(
         modelPubkey,
+        {
+          minimumFee: new BN(100000),
+          tier: 0,
+          minerRequirement: 1,
+          submitDuration: new BN(10),
+          commitDuration: new BN(10),
+          revealDuration: new BN(10),
+        },
         {
           accounts: {...accounts}
         }
@@ -12,7 +12,7 @@ import {
   getAssociatedTokenAddressSync,
   getMinimumBalanceForRentExemptMint,
 } from '@solana/spl-token';
-import { LAMPORTS_PER_SOL, SYSVAR_CLOCK_PUBKEY, PublicKey, SystemProgram, Transaction, type TransactionInstruction } from '@solana/web3.js';
+import { LAMPORTS_PER_SOL, SYSVAR_CLOCK_PUBKEY, SYSVAR_SLOT_HASHES_PUBKEY, PublicKey, SystemProgram, Transaction, type TransactionInstruction } from '@solana/web3.js';
 import { BankrunProvider } from 'anchor-bankrun';
 import { assert, expect } from 'chai';
 import { startAnchor, Clock } from 'solana-bankrun';
@@ -207,11 +207,23 @@ export async function initProgram(_s) {
     [Buffer.from('models'), _s.solearnAccount.publicKey.toBuffer(), _s.model1.publicKey.toBuffer()],
     _s.program.programId,
   )[0];
+  _s.accounts.modelInfo = PublicKey.findProgramAddressSync(
+    [Buffer.from('model_info'), _s.solearnAccount.publicKey.toBuffer(), _s.model1.publicKey.toBuffer()],
+    _s.program.programId,
+  )[0];
 
 
   // after init success let add new model 
-  await sendAndConfirmTx(_s.provider, [await _s.program.instruction.addModel(
+  await sendAndConfirmTx(_s.provider, [await _s.program.instruction.createModel(
     _s.model1.publicKey,
+    {
+      minimumFee: new BN(100000),
+      tier: 0,
+      minerRequirement: 1,
+      submitDuration: new BN(10),
+      commitDuration: new BN(10),
+      revealDuration: new BN(10),
+    },
     {
       accounts: { ..._s.accounts }
     }
@@ -304,6 +316,7 @@ describe('Solearn Bankrun example', function () {
   const accounts: Record<string, PublicKey> = {
     tokenProgram: TOKEN_PROGRAM,
     sysvarClock: SYSVAR_CLOCK_PUBKEY,
+    recentSlothashes: SYSVAR_SLOT_HASHES_PUBKEY,
     systemProgram: SYSTEM_PROGRAM_ID,
   };
   let state = {
@@ -779,6 +792,16 @@ describe('Solearn Bankrun example', function () {
 
         }
         
+        // completed tasks are counted on the assignment worker's miner account
+        const workerBuf = await simulateAndGetResponse(state.provider,
+          [await state.program.instruction.getAssignment(assignmentId, "worker", { accounts: { ...state.accounts } })],
+          [state.provider.wallet.payer]
+        );
+        state.accounts.minerAccount = PublicKey.findProgramAddressSync(
+          [Buffer.from('miner'), workerBuf.subarray(4, 36), state.accounts.solLearnAccount.toBuffer()],
+          state.program.programId,
+        )[0];
+
         state.accounts.tokenRecipient = state.ownerToTokenAccount[assignedWorkerPubkey];
         // console.log('tokenRecipient', state.accounts.tokenRecipient.toBase58());
         await sendAndConfirmTx(state.provider, [await state.program.instruction.payMiner(assignmentId,
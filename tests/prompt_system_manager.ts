@@ -13,7 +13,7 @@ import {
   getAssociatedTokenAddressSync,
   getMinimumBalanceForRentExemptMint,
 } from '@solana/spl-token';
-import { LAMPORTS_PER_SOL, SYSVAR_CLOCK_PUBKEY, SYSVAR_RENT_PUBKEY, PublicKey, SystemProgram, Transaction, type TransactionInstruction } from '@solana/web3.js';
+import { LAMPORTS_PER_SOL, SYSVAR_CLOCK_PUBKEY, SYSVAR_SLOT_HASHES_PUBKEY, SYSVAR_RENT_PUBKEY, PublicKey, SystemProgram, Transaction, type TransactionInstruction } from '@solana/web3.js';
 import { BankrunProvider } from 'anchor-bankrun';
 import { assert } from 'chai';
 import { startAnchor } from 'solana-bankrun';
@@ -35,6 +35,7 @@ describe('Prompt System Manager Bankrun test', () => {
   const accounts: Record<string, PublicKey> = {
     tokenProgram: TOKEN_PROGRAM,
     sysvarClock: SYSVAR_CLOCK_PUBKEY,
+    recentSlothashes: SYSVAR_SLOT_HASHES_PUBKEY,
     systemProgram: SYSTEM_PROGRAM_ID,
     associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
     rent: SYSVAR_RENT_PUBKEY,
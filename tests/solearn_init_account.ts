@@ -6,7 +6,7 @@ import { BN, type Program } from '@coral-xyz/anchor';
 import {
   TOKEN_PROGRAM_ID,
 } from '@solana/spl-token';
-import { SYSVAR_CLOCK_PUBKEY, PublicKey, Keypair} from '@solana/web3.js';
+import { SYSVAR_CLOCK_PUBKEY, SYSVAR_SLOT_HASHES_PUBKEY, PublicKey, Keypair} from '@solana/web3.js';
 
 const TOKEN_PROGRAM = TOKEN_PROGRAM_ID;
 const IDL = require('../target/idl/solearn.json');
@@ -30,6 +30,7 @@ describe('Solearn Deploy', () => {
   const accounts: Record<string, PublicKey> = {
     tokenProgram: TOKEN_PROGRAM,
     sysvarClock: SYSVAR_CLOCK_PUBKEY,
+    recentSlothashes: SYSVAR_SLOT_HASHES_PUBKEY,
     systemProgram: SYSTEM_PROGRAM_ID,
   };
 
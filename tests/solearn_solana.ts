@@ -12,7 +12,7 @@ import {
   getAssociatedTokenAddressSync,
   getMinimumBalanceForRentExemptMint,
 } from '@solana/spl-token';
-import { LAMPORTS_PER_SOL, SYSVAR_CLOCK_PUBKEY, PublicKey, SystemProgram, Transaction, type TransactionInstruction } from '@solana/web3.js';
+import { LAMPORTS_PER_SOL, SYSVAR_CLOCK_PUBKEY, SYSVAR_SLOT_HASHES_PUBKEY, PublicKey, SystemProgram, Transaction, type TransactionInstruction } from '@solana/web3.js';
 import { BankrunProvider } from 'anchor-bankrun';
 import { assert } from 'chai';
 import { startAnchor, Clock } from 'solana-bankrun';
@@ -41,6 +41,7 @@ describe('Solearn Bankrun example', () => {
   const accounts: Record<string, PublicKey> = {
     tokenProgram: TOKEN_PROGRAM,
     sysvarClock: SYSVAR_CLOCK_PUBKEY,
+    recentSlothashes: SYSVAR_SLOT_HASHES_PUBKEY,
     systemProgram: SYSTEM_PROGRAM_ID,
   };
 
@@ -151,11 +152,23 @@ describe('Solearn Bankrun example', () => {
       [Buffer.from('models'), solearnAccount.publicKey.toBuffer(), model1.publicKey.toBuffer()],
       program.programId,
     )[0];
+    accounts.modelInfo = PublicKey.findProgramAddressSync(
+      [Buffer.from('model_info'), solearnAccount.publicKey.toBuffer(), model1.publicKey.toBuffer()],
+      program.programId,
+    )[0];
 
 
     // after init success let add new model 
-    await sendAndConfirmTx(provider, [await program.instruction.addModel(
+    await sendAndConfirmTx(provider, [await program.instruction.createModel(
       model1.publicKey,
+      {
+        minimumFee: new BN(100000),
+        tier: 0,
+        minerRequirement: 1,
+        submitDuration: new BN(10),
+        commitDuration: new BN(10),
+        revealDuration: new BN(10),
+      },
       {
         accounts: {...accounts}
       }